@@ -0,0 +1,636 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    ops::{Add, Div, Mul, Neg, Sub},
+};
+
+#[cfg(test)]
+use crate::{assert_close, random_matrix};
+
+// Reverse-mode automatic differentiation. Every operation on a `Variable`
+// appends a node to its `Tape` holding the result and the operation which
+// produced it, and `Tape::gradients` walks the nodes backwards applying the
+// chain rule. Elementwise operations broadcast 1 x n rows, m x 1 columns and
+// 1 x 1 scalars over the other operand, as NumPy does in the book.
+#[derive(Default)]
+pub struct Tape {
+    nodes: RefCell<Vec<Node>>,
+}
+
+struct Node {
+    value: na::DMatrix<f64>,
+    op: Op,
+}
+
+enum Op {
+    Leaf,
+    Add(usize, usize),
+    Sub(usize, usize),
+    Mul(usize, usize),
+    Div(usize, usize),
+    Neg(usize),
+    Scale(usize, f64),
+    Powf(usize, f64),
+    Exp(usize),
+    Ln(usize),
+    MatMul(usize, usize),
+    Transpose(usize),
+    Sum(usize),
+    RowSum(usize),
+    ColumnSum(usize),
+    Relu(usize),
+    Sigmoid(usize),
+    Tanh(usize),
+    Softmax(usize),
+    // Holds the softmax of the input and the one-hot targets.
+    SoftmaxCrossEntropy(usize, na::DMatrix<f64>, na::DMatrix<f64>),
+}
+
+impl Tape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn var(&self, value: na::DMatrix<f64>) -> Variable<'_> {
+        self.push(value, Op::Leaf)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn push(&self, value: na::DMatrix<f64>, op: Op) -> Variable<'_> {
+        let mut nodes = self.nodes.borrow_mut();
+        nodes.push(Node { value, op });
+        Variable {
+            tape: self,
+            index: nodes.len() - 1,
+        }
+    }
+
+    // The gradients of `output` with respect to every variable recorded
+    // before it. An output which is not 1 x 1 is treated as the sum of its
+    // elements.
+    pub fn gradients(&self, output: Variable) -> Gradients<'_> {
+        assert!(
+            std::ptr::eq(self, output.tape),
+            "the output belongs to another tape."
+        );
+        let nodes = self.nodes.borrow();
+        let mut grads: Vec<Option<na::DMatrix<f64>>> = vec![None; output.index + 1];
+        let (rows, columns) = nodes[output.index].value.shape();
+        grads[output.index] = Some(na::DMatrix::from_element(rows, columns, 1.0));
+        for index in (0..=output.index).rev() {
+            let Some(grad) = grads[index].take() else {
+                continue;
+            };
+            for (parent, contribution) in backwards(&nodes, index, &grad) {
+                match &mut grads[parent] {
+                    Some(total) => *total += contribution,
+                    slot => *slot = Some(contribution),
+                }
+            }
+            grads[index] = Some(grad);
+        }
+        Gradients { tape: self, grads }
+    }
+}
+
+fn backwards(
+    nodes: &[Node],
+    index: usize,
+    grad: &na::DMatrix<f64>,
+) -> Vec<(usize, na::DMatrix<f64>)> {
+    let value = |i: usize| &nodes[i].value;
+    let out = &nodes[index].value;
+    match &nodes[index].op {
+        Op::Leaf => vec![],
+        &Op::Add(a, b) => vec![
+            (a, reduce_to(grad.clone(), value(a).shape())),
+            (b, reduce_to(grad.clone(), value(b).shape())),
+        ],
+        &Op::Sub(a, b) => vec![
+            (a, reduce_to(grad.clone(), value(a).shape())),
+            (b, reduce_to(-grad, value(b).shape())),
+        ],
+        &Op::Mul(a, b) => {
+            let shape = grad.shape();
+            let (x, y) = (broadcast(value(a), shape), broadcast(value(b), shape));
+            vec![
+                (a, reduce_to(grad.component_mul(&y), value(a).shape())),
+                (b, reduce_to(grad.component_mul(&x), value(b).shape())),
+            ]
+        }
+        &Op::Div(a, b) => {
+            let shape = grad.shape();
+            let (x, y) = (broadcast(value(a), shape), broadcast(value(b), shape));
+            let da = grad.component_div(&y);
+            let db = -grad.component_mul(&x).component_div(&y.component_mul(&y));
+            vec![
+                (a, reduce_to(da, value(a).shape())),
+                (b, reduce_to(db, value(b).shape())),
+            ]
+        }
+        &Op::Neg(a) => vec![(a, -grad)],
+        &Op::Scale(a, factor) => vec![(a, grad * factor)],
+        &Op::Powf(a, n) => vec![(a, grad.zip_map(value(a), |g, x| g * n * x.powf(n - 1.0)))],
+        &Op::Exp(a) => vec![(a, grad.component_mul(out))],
+        &Op::Ln(a) => vec![(a, grad.component_div(value(a)))],
+        &Op::MatMul(a, b) => vec![
+            (a, grad * value(b).transpose()),
+            (b, value(a).transpose() * grad),
+        ],
+        &Op::Transpose(a) => vec![(a, grad.transpose())],
+        &Op::Sum(a) | &Op::RowSum(a) | &Op::ColumnSum(a) => {
+            vec![(a, broadcast(grad, value(a).shape()))]
+        }
+        &Op::Relu(a) => vec![(
+            a,
+            grad.zip_map(value(a), |g, x| if x > 0.0 { g } else { 0.0 }),
+        )],
+        &Op::Sigmoid(a) => vec![(a, grad.zip_map(out, |g, y| g * y * (1.0 - y)))],
+        &Op::Tanh(a) => vec![(a, grad.zip_map(out, |g, y| g * (1.0 - y * y)))],
+        &Op::Softmax(a) => {
+            let weighted = grad.component_mul(out).column_sum();
+            let mut dx = grad.clone();
+            for (mut row, &sum) in dx.row_iter_mut().zip(weighted.iter()) {
+                row.add_scalar_mut(-sum);
+            }
+            vec![(a, dx.component_mul(out))]
+        }
+        Op::SoftmaxCrossEntropy(a, y, t) => {
+            vec![(*a, (y - t) * (grad[(0, 0)] / t.nrows() as f64))]
+        }
+    }
+}
+
+// Repeats a row, a column or a scalar to fill `shape`.
+fn broadcast(m: &na::DMatrix<f64>, shape: (usize, usize)) -> na::DMatrix<f64> {
+    if m.shape() == shape {
+        return m.clone();
+    }
+    let (rows, columns) = m.shape();
+    na::DMatrix::from_fn(shape.0, shape.1, |i, j| {
+        m[(
+            if rows == 1 { 0 } else { i },
+            if columns == 1 { 0 } else { j },
+        )]
+    })
+}
+
+// Sums a gradient over the axes along which its operand was broadcast.
+fn reduce_to(mut grad: na::DMatrix<f64>, shape: (usize, usize)) -> na::DMatrix<f64> {
+    if shape.0 == 1 && grad.nrows() != 1 {
+        grad = row_sum(&grad);
+    }
+    if shape.1 == 1 && grad.ncols() != 1 {
+        grad = column_sum(&grad);
+    }
+    grad
+}
+
+fn row_sum(m: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    na::DMatrix::from_row_slice(1, m.ncols(), m.row_sum().as_slice())
+}
+
+fn column_sum(m: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    na::DMatrix::from_column_slice(m.nrows(), 1, m.column_sum().as_slice())
+}
+
+fn broadcast_shape(a: (usize, usize), b: (usize, usize)) -> (usize, usize) {
+    let dim = |a: usize, b: usize| {
+        assert!(
+            a == b || a == 1 || b == 1,
+            "shapes {:?} and {:?} cannot be broadcast together.",
+            a,
+            b
+        );
+        a.max(b)
+    };
+    (dim(a.0, b.0), dim(a.1, b.1))
+}
+
+#[derive(Clone, Copy)]
+pub struct Variable<'t> {
+    tape: &'t Tape,
+    index: usize,
+}
+
+impl<'t> Variable<'t> {
+    pub fn value(&self) -> na::DMatrix<f64> {
+        self.tape.nodes.borrow()[self.index].value.clone()
+    }
+
+    pub fn shape(&self) -> (usize, usize) {
+        self.tape.nodes.borrow()[self.index].value.shape()
+    }
+
+    // The value of a 1 x 1 variable such as a loss.
+    pub fn scalar(&self) -> f64 {
+        let nodes = self.tape.nodes.borrow();
+        let value = &nodes[self.index].value;
+        assert_eq!(value.shape(), (1, 1), "the variable is not a scalar.");
+        value[(0, 0)]
+    }
+
+    fn unary(self, op: Op, f: impl Fn(&na::DMatrix<f64>) -> na::DMatrix<f64>) -> Self {
+        let value = f(&self.tape.nodes.borrow()[self.index].value);
+        self.tape.push(value, op)
+    }
+
+    fn elementwise(self, other: Self, op: Op, f: impl Fn(f64, f64) -> f64) -> Self {
+        assert!(
+            std::ptr::eq(self.tape, other.tape),
+            "variables from different tapes cannot be combined."
+        );
+        let value = {
+            let nodes = self.tape.nodes.borrow();
+            let (a, b) = (&nodes[self.index].value, &nodes[other.index].value);
+            let shape = broadcast_shape(a.shape(), b.shape());
+            broadcast(a, shape).zip_map(&broadcast(b, shape), f)
+        };
+        self.tape.push(value, op)
+    }
+
+    fn constant(self, value: f64) -> Self {
+        self.tape.var(na::DMatrix::from_element(1, 1, value))
+    }
+
+    pub fn matmul(self, other: Self) -> Self {
+        assert!(
+            std::ptr::eq(self.tape, other.tape),
+            "variables from different tapes cannot be combined."
+        );
+        let value = {
+            let nodes = self.tape.nodes.borrow();
+            &nodes[self.index].value * &nodes[other.index].value
+        };
+        self.tape.push(value, Op::MatMul(self.index, other.index))
+    }
+
+    pub fn transpose(self) -> Self {
+        self.unary(Op::Transpose(self.index), |x| x.transpose())
+    }
+
+    pub fn powf(self, n: f64) -> Self {
+        self.unary(Op::Powf(self.index, n), |x| x.map(|x| x.powf(n)))
+    }
+
+    pub fn sqrt(self) -> Self {
+        self.powf(0.5)
+    }
+
+    pub fn exp(self) -> Self {
+        self.unary(Op::Exp(self.index), |x| x.map(f64::exp))
+    }
+
+    pub fn ln(self) -> Self {
+        self.unary(Op::Ln(self.index), |x| x.map(f64::ln))
+    }
+
+    // The sum of all elements as a 1 x 1 variable.
+    pub fn sum(self) -> Self {
+        self.unary(Op::Sum(self.index), |x| {
+            na::DMatrix::from_element(1, 1, x.sum())
+        })
+    }
+
+    pub fn mean(self) -> Self {
+        let len = self.tape.nodes.borrow()[self.index].value.len();
+        self.sum() * (1.0 / len.max(1) as f64)
+    }
+
+    // The sum over the rows, 1 x n, as with nalgebra.
+    pub fn row_sum(self) -> Self {
+        self.unary(Op::RowSum(self.index), row_sum)
+    }
+
+    pub fn row_mean(self) -> Self {
+        let rows = self.shape().0;
+        self.row_sum() * (1.0 / rows.max(1) as f64)
+    }
+
+    // The sum over the columns, m x 1.
+    pub fn column_sum(self) -> Self {
+        self.unary(Op::ColumnSum(self.index), column_sum)
+    }
+
+    pub fn column_mean(self) -> Self {
+        let columns = self.shape().1;
+        self.column_sum() * (1.0 / columns.max(1) as f64)
+    }
+
+    pub fn relu(self) -> Self {
+        self.unary(Op::Relu(self.index), |x| x.map(|x| x.max(0.0)))
+    }
+
+    pub fn sigmoid(self) -> Self {
+        self.unary(Op::Sigmoid(self.index), |x| {
+            x.map(|x| 1.0 / (1.0 + (-x).exp()))
+        })
+    }
+
+    pub fn tanh(self) -> Self {
+        self.unary(Op::Tanh(self.index), |x| x.map(f64::tanh))
+    }
+
+    // The softmax of every row.
+    pub fn softmax(self) -> Self {
+        self.unary(Op::Softmax(self.index), softmax)
+    }
+
+    // The mean cross entropy error of the softmax of every row against the
+    // one-hot targets `t`, exactly as `SoftmaxWithLoss` computes it, with its
+    // gradient of (y - t) / batch_size.
+    pub fn softmax_cross_entropy(self, t: &na::DMatrix<u8>) -> Self {
+        let y = softmax(&self.tape.nodes.borrow()[self.index].value);
+        assert_eq!(
+            y.shape(),
+            t.shape(),
+            "one one-hot target per row is needed."
+        );
+        let t = t.clone().cast::<f64>();
+        let delta = 1e-7;
+        let loss = -t.zip_map(&y, |t, y| t * (y + delta).ln()).sum() / y.nrows() as f64;
+        self.tape.push(
+            na::DMatrix::from_element(1, 1, loss),
+            Op::SoftmaxCrossEntropy(self.index, y, t),
+        )
+    }
+}
+
+fn softmax(x: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    let mut y = x.clone();
+    for mut row in y.row_iter_mut() {
+        let max = row.max();
+        row.apply(|x| *x = (*x - max).exp());
+        let sum = row.sum();
+        row /= sum;
+    }
+    y
+}
+
+impl fmt::Debug for Variable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Variable")
+            .field("index", &self.index)
+            .field("value", &self.value())
+            .finish()
+    }
+}
+
+impl<'t> Add for Variable<'t> {
+    type Output = Variable<'t>;
+
+    fn add(self, other: Self) -> Self::Output {
+        self.elementwise(other, Op::Add(self.index, other.index), |a, b| a + b)
+    }
+}
+
+impl<'t> Sub for Variable<'t> {
+    type Output = Variable<'t>;
+
+    fn sub(self, other: Self) -> Self::Output {
+        self.elementwise(other, Op::Sub(self.index, other.index), |a, b| a - b)
+    }
+}
+
+impl<'t> Mul for Variable<'t> {
+    type Output = Variable<'t>;
+
+    // Elementwise; see `matmul` for the matrix product.
+    fn mul(self, other: Self) -> Self::Output {
+        self.elementwise(other, Op::Mul(self.index, other.index), |a, b| a * b)
+    }
+}
+
+impl<'t> Div for Variable<'t> {
+    type Output = Variable<'t>;
+
+    fn div(self, other: Self) -> Self::Output {
+        self.elementwise(other, Op::Div(self.index, other.index), |a, b| a / b)
+    }
+}
+
+impl<'t> Neg for Variable<'t> {
+    type Output = Variable<'t>;
+
+    fn neg(self) -> Self::Output {
+        self.unary(Op::Neg(self.index), |x| -x)
+    }
+}
+
+impl<'t> Add<f64> for Variable<'t> {
+    type Output = Variable<'t>;
+
+    fn add(self, other: f64) -> Self::Output {
+        self + self.constant(other)
+    }
+}
+
+impl<'t> Sub<f64> for Variable<'t> {
+    type Output = Variable<'t>;
+
+    fn sub(self, other: f64) -> Self::Output {
+        self + self.constant(-other)
+    }
+}
+
+impl<'t> Mul<f64> for Variable<'t> {
+    type Output = Variable<'t>;
+
+    fn mul(self, factor: f64) -> Self::Output {
+        self.unary(Op::Scale(self.index, factor), |x| x * factor)
+    }
+}
+
+impl<'t> Div<f64> for Variable<'t> {
+    type Output = Variable<'t>;
+
+    fn div(self, divisor: f64) -> Self::Output {
+        self * (1.0 / divisor)
+    }
+}
+
+pub struct Gradients<'t> {
+    tape: &'t Tape,
+    grads: Vec<Option<na::DMatrix<f64>>>,
+}
+
+impl<'t> Gradients<'t> {
+    // Zero for variables the output does not depend on.
+    pub fn wrt(&self, variable: Variable<'t>) -> na::DMatrix<f64> {
+        match self.grads.get(variable.index) {
+            Some(Some(grad)) => grad.clone(),
+            _ => {
+                let (rows, columns) = self.tape.nodes.borrow()[variable.index].value.shape();
+                na::DMatrix::zeros(rows, columns)
+            }
+        }
+    }
+}
+
+// Central differences of `f` at `x`.
+#[cfg(test)]
+fn numerical_gradient(
+    f: impl Fn(&na::DMatrix<f64>) -> f64,
+    x: &na::DMatrix<f64>,
+) -> na::DMatrix<f64> {
+    let h = 1e-5;
+    let mut x = x.clone();
+    na::DMatrix::from_fn(x.nrows(), x.ncols(), |i, j| {
+        let original = x[(i, j)];
+        x[(i, j)] = original + h;
+        let plus = f(&x);
+        x[(i, j)] = original - h;
+        let minus = f(&x);
+        x[(i, j)] = original;
+        (plus - minus) / (2.0 * h)
+    })
+}
+
+#[test]
+fn test_matches_affine_layer() {
+    use crate::layers::{affine_layer::Affine, Layer};
+    use crate::shared::Shared;
+
+    let (x, w, b) = (
+        random_matrix(4, 3, 0),
+        random_matrix(3, 2, 1),
+        random_matrix(1, 2, 2),
+    );
+    let dout = random_matrix(4, 2, 3);
+    let mut affine = Affine::new(
+        Shared::new(w.clone()),
+        Shared::new(na::DVector::from_row_slice(b.as_slice())),
+    );
+    let y = affine.forwards(&x, true);
+    let dx = affine.backwards(&dout);
+
+    let tape = Tape::new();
+    let (vx, vw, vb) = (tape.var(x), tape.var(w), tape.var(b));
+    let vy = vx.matmul(vw) + vb;
+    assert_close(&vy.value(), &y, 1e-12);
+    let grads = tape.gradients((vy * tape.var(dout)).sum());
+    assert_close(&grads.wrt(vx), &dx, 1e-12);
+    assert_close(&grads.wrt(vw), &affine.dw, 1e-12);
+    assert_close(
+        &grads.wrt(vb).transpose(),
+        &na::DMatrix::from_column_slice(2, 1, affine.db.as_slice()),
+        1e-12,
+    );
+}
+
+#[test]
+fn test_matches_activation_layers() {
+    use crate::layers::{relu_layer::Relu, sigmoid_layer::Sigmoid, Layer};
+
+    fn check(mut layer: impl Layer, f: impl for<'t> Fn(Variable<'t>) -> Variable<'t>) {
+        let x = random_matrix(5, 4, 0);
+        let dout = random_matrix(5, 4, 1);
+        let y = layer.forwards(&x, true);
+        let dx = layer.backwards(&dout);
+        let tape = Tape::new();
+        let vx = tape.var(x);
+        let vy = f(vx);
+        assert_close(&vy.value(), &y, 1e-12);
+        let grads = tape.gradients((vy * tape.var(dout)).sum());
+        assert_close(&grads.wrt(vx), &dx, 1e-12);
+    }
+    check(Relu::new(), |x| x.relu());
+    check(Sigmoid::new(), |x| x.sigmoid());
+}
+
+#[test]
+fn test_matches_softmax_with_loss() {
+    use crate::layers::softmax_with_loss_layer::SoftmaxWithLoss;
+
+    let x = random_matrix(3, 4, 0);
+    let t = na::dmatrix![0u8, 1, 0, 0; 0, 0, 0, 1; 1, 0, 0, 0];
+    let mut layer = SoftmaxWithLoss::new();
+    let loss = layer.forwards(&x, &t);
+    let tape = Tape::new();
+    let vx = tape.var(x);
+    let vloss = vx.softmax_cross_entropy(&t);
+    assert!((vloss.scalar() - loss).abs() < 1e-12);
+    assert_close(&tape.gradients(vloss).wrt(vx), &layer.backwards(1.0), 1e-12);
+}
+
+#[test]
+fn test_matches_batch_normalisation_layer() {
+    use crate::layers::{batch_normalisation_layer::BatchNormalisationLayer, Layer};
+    use crate::shared::Shared;
+
+    let (x, gamma, beta) = (
+        random_matrix(6, 3, 0),
+        random_matrix(1, 3, 1),
+        random_matrix(1, 3, 2),
+    );
+    let dout = random_matrix(6, 3, 3);
+    let mut layer = BatchNormalisationLayer::new(
+        Shared::new(na::DVector::from_row_slice(gamma.as_slice())),
+        Shared::new(na::DVector::from_row_slice(beta.as_slice())),
+        0.9,
+    );
+    let y = layer.forwards(&x, true);
+    let dx = layer.backwards(&dout);
+
+    let tape = Tape::new();
+    let (vx, vgamma, vbeta) = (tape.var(x), tape.var(gamma), tape.var(beta));
+    let xc = vx - vx.row_mean();
+    let var = (xc * xc).row_mean();
+    let vy = xc / (var + 10e-7).sqrt() * vgamma + vbeta;
+    assert_close(&vy.value(), &y, 1e-10);
+    let grads = tape.gradients((vy * tape.var(dout)).sum());
+    assert_close(&grads.wrt(vx), &dx, 1e-8);
+    assert_close(
+        &grads.wrt(vgamma).transpose(),
+        &na::DMatrix::from_column_slice(3, 1, layer.dgamma.as_slice()),
+        1e-10,
+    );
+    assert_close(
+        &grads.wrt(vbeta).transpose(),
+        &na::DMatrix::from_column_slice(3, 1, layer.dbeta.as_slice()),
+        1e-10,
+    );
+}
+
+#[test]
+fn test_against_numerical_gradient() {
+    let x = random_matrix(3, 4, 0).map(|x| x.abs() + 0.5);
+    let c = random_matrix(3, 1, 1);
+    fn f<'t>(
+        tape: &'t Tape,
+        x: &na::DMatrix<f64>,
+        c: &na::DMatrix<f64>,
+    ) -> (Variable<'t>, Variable<'t>) {
+        let vx = tape.var(x.clone());
+        let vc = tape.var(c.clone());
+        let y = (vx.ln() * vc).tanh() + vx.exp().softmax() / (vx + 1.0)
+            - vx.transpose().column_sum().powf(2.0).sum() * 0.1;
+        let z = y.matmul(vx.transpose()).sigmoid().mean() + (-y).relu().column_mean().sum();
+        (vx, z)
+    }
+    let tape = Tape::new();
+    let (vx, z) = f(&tape, &x, &c);
+    let grad = tape.gradients(z).wrt(vx);
+    let numerical = numerical_gradient(|x| f(&Tape::new(), x, &c).1.scalar(), &x);
+    assert_close(&grad, &numerical, 1e-6);
+}
+
+#[test]
+fn test_unused_variable_has_zero_gradient() {
+    let tape = Tape::new();
+    let a = tape.var(na::dmatrix![1.0, 2.0]);
+    let b = tape.var(na::dmatrix![3.0; 4.0]);
+    // a is used twice, so both uses add up.
+    let y = (a * a + a).sum();
+    let grads = tape.gradients(y);
+    assert_eq!(grads.wrt(a), na::dmatrix![3.0, 5.0]);
+    assert_eq!(grads.wrt(b), na::dmatrix![0.0; 0.0]);
+    assert_eq!(tape.len(), 5);
+}
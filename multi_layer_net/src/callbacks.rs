@@ -0,0 +1,84 @@
+use crate::{float::Float, trainer::Model};
+
+pub mod early_stopping;
+pub mod model_checkpoint;
+pub mod progress_logger;
+pub mod terminate_on_nan;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+// What is known about an epoch once it has finished. `loss` is the mean of
+// the batch losses of the epoch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EpochLogs {
+    pub loss: f64,
+    pub train_accuracy: f64,
+    pub val_loss: Option<f64>,
+    pub val_accuracy: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Monitor {
+    Loss,
+    TrainAccuracy,
+    ValLoss,
+    ValAccuracy,
+}
+
+impl Monitor {
+    // None when the metric is a validation one but no validation data was
+    // given.
+    pub fn value(&self, logs: &EpochLogs) -> Option<f64> {
+        match self {
+            Monitor::Loss => Some(logs.loss),
+            Monitor::TrainAccuracy => Some(logs.train_accuracy),
+            Monitor::ValLoss => logs.val_loss,
+            Monitor::ValAccuracy => logs.val_accuracy,
+        }
+    }
+
+    // Losses improve by going down and accuracies by going up.
+    pub fn improves(&self, value: f64, best: f64, min_delta: f64) -> bool {
+        match self {
+            Monitor::Loss | Monitor::ValLoss => value < best - min_delta,
+            Monitor::TrainAccuracy | Monitor::ValAccuracy => value > best + min_delta,
+        }
+    }
+}
+
+// Hooks called by `Trainer::fit`. Epochs and batches are counted from zero,
+// batches within their epoch. Returning `Control::Stop` ends training after
+// the current batch or epoch.
+pub trait Callback<T: Float = f64> {
+    fn on_epoch_begin(&mut self, _epoch: usize) {}
+
+    fn on_batch_begin(&mut self, _epoch: usize, _batch: usize) {}
+
+    fn on_batch_end(&mut self, _epoch: usize, _batch: usize, _loss: f64) -> Control {
+        Control::Continue
+    }
+
+    fn on_epoch_end(&mut self, _epoch: usize, _logs: &EpochLogs, _model: &dyn Model<T>) -> Control {
+        Control::Continue
+    }
+}
+
+#[test]
+fn test_monitor_improves() {
+    let logs = EpochLogs {
+        loss: 0.5,
+        train_accuracy: 0.9,
+        val_loss: None,
+        val_accuracy: Some(0.8),
+    };
+    assert_eq!(Monitor::Loss.value(&logs), Some(0.5));
+    assert_eq!(Monitor::ValLoss.value(&logs), None);
+    assert!(Monitor::ValLoss.improves(0.4, 0.5, 0.0));
+    assert!(!Monitor::ValLoss.improves(0.45, 0.5, 0.1));
+    assert!(Monitor::ValAccuracy.improves(0.9, 0.8, 0.05));
+    assert!(!Monitor::ValAccuracy.improves(0.7, 0.8, 0.0));
+}
@@ -0,0 +1,91 @@
+use super::{Callback, Control, EpochLogs, Monitor};
+use crate::{float::Float, trainer::Model};
+
+// Stops training once the monitored metric has not improved by more than
+// `min_delta` for `patience` consecutive epochs.
+pub struct EarlyStopping {
+    monitor: Monitor,
+    patience: usize,
+    min_delta: f64,
+    best: Option<f64>,
+    best_epoch: Option<usize>,
+    num_bad_epochs: usize,
+    stopped_epoch: Option<usize>,
+}
+
+impl EarlyStopping {
+    pub fn new(monitor: Monitor, patience: usize, min_delta: f64) -> Self {
+        Self {
+            monitor,
+            patience,
+            min_delta,
+            best: None,
+            best_epoch: None,
+            num_bad_epochs: 0,
+            stopped_epoch: None,
+        }
+    }
+
+    pub fn best(&self) -> Option<f64> {
+        self.best
+    }
+
+    pub fn best_epoch(&self) -> Option<usize> {
+        self.best_epoch
+    }
+
+    // The epoch after which training was stopped, if it was.
+    pub fn stopped_epoch(&self) -> Option<usize> {
+        self.stopped_epoch
+    }
+}
+
+impl<T: Float> Callback<T> for EarlyStopping {
+    fn on_epoch_end(&mut self, epoch: usize, logs: &EpochLogs, _model: &dyn Model<T>) -> Control {
+        let Some(value) = self.monitor.value(logs) else {
+            return Control::Continue;
+        };
+        match self.best {
+            Some(best) if !self.monitor.improves(value, best, self.min_delta) => {
+                self.num_bad_epochs += 1;
+            }
+            _ => {
+                self.best = Some(value);
+                self.best_epoch = Some(epoch);
+                self.num_bad_epochs = 0;
+            }
+        }
+        if self.num_bad_epochs >= self.patience {
+            self.stopped_epoch = Some(epoch);
+            return Control::Stop;
+        }
+        Control::Continue
+    }
+}
+
+#[test]
+fn test_early_stopping() {
+    use crate::multi_layer_net_extended::MultiLayerNetExtended;
+
+    let model: MultiLayerNetExtended = MultiLayerNetExtended::new(2, vec![2], 2, 0.0, "he", "relu");
+    let mut early_stopping = EarlyStopping::new(Monitor::ValLoss, 3, 0.01);
+    let logs = |val_loss| EpochLogs {
+        loss: 0.0,
+        train_accuracy: 0.0,
+        val_loss: Some(val_loss),
+        val_accuracy: None,
+    };
+    for (epoch, val_loss) in [1.0, 0.8, 0.795, 0.9].into_iter().enumerate() {
+        assert_eq!(
+            early_stopping.on_epoch_end(epoch, &logs(val_loss), &model),
+            Control::Continue
+        );
+    }
+    assert_eq!(
+        early_stopping.on_epoch_end(4, &logs(0.79), &model),
+        Control::Stop
+    );
+    assert_eq!(early_stopping.best(), Some(0.8));
+    assert_eq!(early_stopping.best_epoch(), Some(1));
+    assert_eq!(early_stopping.stopped_epoch(), Some(4));
+}
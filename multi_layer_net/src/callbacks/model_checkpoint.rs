@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+
+use super::{Callback, Control, EpochLogs, Monitor};
+use crate::{float::Float, trainer::Model};
+
+// Saves the model to `path` every time the monitored metric reaches a new
+// best, so that the file always holds the best model seen so far.
+pub struct ModelCheckpoint {
+    path: PathBuf,
+    monitor: Monitor,
+    best: Option<f64>,
+    best_epoch: Option<usize>,
+}
+
+impl ModelCheckpoint {
+    pub fn new(path: impl Into<PathBuf>, monitor: Monitor) -> Self {
+        Self {
+            path: path.into(),
+            monitor,
+            best: None,
+            best_epoch: None,
+        }
+    }
+
+    pub fn best(&self) -> Option<f64> {
+        self.best
+    }
+
+    pub fn best_epoch(&self) -> Option<usize> {
+        self.best_epoch
+    }
+}
+
+impl<T: Float> Callback<T> for ModelCheckpoint {
+    fn on_epoch_end(&mut self, epoch: usize, logs: &EpochLogs, model: &dyn Model<T>) -> Control {
+        let Some(value) = self.monitor.value(logs) else {
+            return Control::Continue;
+        };
+        if let Some(best) = self.best {
+            if !self.monitor.improves(value, best, 0.0) {
+                return Control::Continue;
+            }
+        }
+        self.best = Some(value);
+        self.best_epoch = Some(epoch);
+        // Training on without the checkpoint would silently lose the best
+        // model, so a failed save stops it instead.
+        if let Err(e) = model.save(&self.path) {
+            eprintln!("Could not save {}: {}", self.path.display(), e);
+            return Control::Stop;
+        }
+        Control::Continue
+    }
+}
+
+#[test]
+fn test_model_checkpoint_saves_best() {
+    use crate::multi_layer_net_extended::MultiLayerNetExtended;
+
+    let path = std::env::temp_dir().join("model_checkpoint_best.ckpt");
+    let mut model_checkpoint = ModelCheckpoint::new(&path, Monitor::ValAccuracy);
+    let logs = |val_accuracy| EpochLogs {
+        loss: 0.0,
+        train_accuracy: 0.0,
+        val_loss: None,
+        val_accuracy: Some(val_accuracy),
+    };
+    let best: MultiLayerNetExtended = MultiLayerNetExtended::new(2, vec![3], 2, 0.0, "he", "relu");
+    let worse: MultiLayerNetExtended = MultiLayerNetExtended::new(2, vec![3], 2, 0.0, "he", "relu");
+    model_checkpoint.on_epoch_end(0, &logs(0.5), &worse);
+    model_checkpoint.on_epoch_end(1, &logs(0.9), &best);
+    model_checkpoint.on_epoch_end(2, &logs(0.7), &worse);
+    let loaded: MultiLayerNetExtended = MultiLayerNetExtended::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(model_checkpoint.best_epoch(), Some(1));
+    assert_eq!(loaded.to_checkpoint(), best.to_checkpoint());
+}
@@ -0,0 +1,39 @@
+use super::{Callback, Control, EpochLogs};
+use crate::{float::Float, trainer::Model};
+
+// Prints the batch loss every `print_every` batches and a summary at the end
+// of every epoch. A `print_every` of 0 only prints the summaries.
+pub struct ProgressLogger {
+    print_every: usize,
+}
+
+impl ProgressLogger {
+    pub fn new(print_every: usize) -> Self {
+        Self { print_every }
+    }
+}
+
+impl<T: Float> Callback<T> for ProgressLogger {
+    fn on_batch_end(&mut self, epoch: usize, batch: usize, loss: f64) -> Control {
+        if self.print_every > 0 && (batch + 1).is_multiple_of(self.print_every) {
+            println!("Epoch {} batch {} loss {:.4}", epoch + 1, batch + 1, loss);
+        }
+        Control::Continue
+    }
+
+    fn on_epoch_end(&mut self, epoch: usize, logs: &EpochLogs, _model: &dyn Model<T>) -> Control {
+        print!(
+            "Epoch {} loss {:.4} Train Acc. {:.1}%",
+            epoch + 1,
+            logs.loss,
+            logs.train_accuracy * 100.0
+        );
+        match (logs.val_loss, logs.val_accuracy) {
+            (Some(loss), Some(accuracy)) => {
+                println!(" Val. loss {:.4} Val. Acc. {:.1}%", loss, accuracy * 100.0)
+            }
+            _ => println!(),
+        }
+        Control::Continue
+    }
+}
@@ -0,0 +1,48 @@
+use super::{Callback, Control};
+use crate::float::Float;
+
+// Stops training as soon as a batch loss is NaN or infinite, since nothing
+// can be learnt from there on.
+#[derive(Clone, Debug, Default)]
+pub struct TerminateOnNaN {
+    terminated_at: Option<(usize, usize)>,
+}
+
+impl TerminateOnNaN {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The epoch and batch whose loss was not finite, if any, counted from 0
+    // like the indices passed to callbacks.
+    pub fn terminated_at(&self) -> Option<(usize, usize)> {
+        self.terminated_at
+    }
+}
+
+impl<T: Float> Callback<T> for TerminateOnNaN {
+    fn on_batch_end(&mut self, epoch: usize, batch: usize, loss: f64) -> Control {
+        if loss.is_finite() {
+            return Control::Continue;
+        }
+        // Numbered from 1 like `ProgressLogger` prints them.
+        println!(
+            "Epoch {} batch {}: loss is {}, stopping.",
+            epoch + 1,
+            batch + 1,
+            loss
+        );
+        self.terminated_at = Some((epoch, batch));
+        Control::Stop
+    }
+}
+
+#[test]
+fn test_terminate_on_nan() {
+    let mut terminate_on_nan = TerminateOnNaN::new();
+    // The callback works with models of any precision, so one is picked here.
+    let callback: &mut dyn Callback = &mut terminate_on_nan;
+    assert_eq!(callback.on_batch_end(0, 0, 2.3), Control::Continue);
+    assert_eq!(callback.on_batch_end(0, 1, f64::NAN), Control::Stop);
+    assert_eq!(terminate_on_nan.terminated_at(), Some((0, 1)));
+}
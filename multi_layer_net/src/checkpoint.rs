@@ -0,0 +1,321 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    float::{cast_matrix, Float},
+    parameters::NamedParameters,
+};
+
+const MAGIC: &[u8; 4] = b"DLCK";
+pub const VERSION: u32 = 1;
+
+// A versioned binary container of string metadata (architecture,
+// hyperparameters, ...) and named f64 matrices. Every integer and float is
+// stored little-endian and matrices are stored column-major.
+//
+//   magic "DLCK" | version u32
+//   metadata count u32 | (key string, value string)*
+//   tensor count u32 | (name string, nrows u64, ncols u64, data f64*)*
+//
+// where a string is a u32 byte length followed by UTF-8 bytes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Checkpoint {
+    pub metadata: BTreeMap<String, String>,
+    pub tensors: BTreeMap<String, na::DMatrix<f64>>,
+}
+
+impl Checkpoint {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert_metadata(&mut self, key: &str, value: impl ToString) {
+        self.metadata.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn metadata<T: FromStr>(&self, key: &str) -> io::Result<T> {
+        let value = self
+            .metadata
+            .get(key)
+            .ok_or_else(|| invalid_data(format!("metadata {} is missing.", key)))?;
+        value
+            .parse::<T>()
+            .map_err(|_| invalid_data(format!("metadata {} has an invalid value {}.", key, value)))
+    }
+
+    pub fn tensor(&self, key: &str) -> io::Result<&na::DMatrix<f64>> {
+        self.tensors
+            .get(key)
+            .ok_or_else(|| invalid_data(format!("tensor {} is missing.", key)))
+    }
+
+    // Stores every non-empty parameter under its own name prefixed with
+    // `prefix`, converted to `f64`.
+    pub fn insert_parameters<T: Float>(&mut self, prefix: &str, params: &dyn NamedParameters<T>) {
+        for (key, param) in params.named_parameters() {
+            if !param.is_empty() {
+                self.tensors.insert(
+                    format!("{}{}", prefix, key),
+                    cast_matrix(&param.to_matrix()),
+                );
+            }
+        }
+    }
+
+    // Copies the stored tensors into `params` in place, so layers sharing the
+    // parameters see the loaded values.
+    pub fn load_parameters<T: Float>(
+        &self,
+        prefix: &str,
+        params: &dyn NamedParameters<T>,
+    ) -> io::Result<()> {
+        for (key, param) in params.named_parameters() {
+            if param.is_empty() {
+                continue;
+            }
+            let key = format!("{}{}", prefix, key);
+            let tensor = self.tensor(&key)?;
+            if tensor.shape() != param.shape() {
+                return Err(invalid_data(format!(
+                    "tensor {} has shape {:?} but {:?} was expected.",
+                    key,
+                    tensor.shape(),
+                    param.shape()
+                )));
+            }
+            param.with_view_mut(|mut view| view.copy_from(&cast_matrix::<f64, T>(tensor)));
+        }
+        Ok(())
+    }
+
+    // Metric histories and similar lists are stored as single-column tensors.
+    pub fn insert_list(&mut self, key: &str, list: &[f64]) {
+        self.tensors.insert(
+            key.to_string(),
+            na::DMatrix::<f64>::from_column_slice(list.len(), 1, list),
+        );
+    }
+
+    pub fn list(&self, key: &str) -> io::Result<Vec<f64>> {
+        Ok(self.tensor(key)?.as_slice().to_vec())
+    }
+
+    // Records the seed, stream and position of `rng` so that it can be
+    // restored to produce exactly the same numbers from where it left off.
+    pub fn insert_rng(&mut self, key: &str, rng: &ChaCha8Rng) {
+        let seed = rng
+            .get_seed()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        self.insert_metadata(&format!("{}.seed", key), seed);
+        self.insert_metadata(&format!("{}.stream", key), rng.get_stream());
+        self.insert_metadata(&format!("{}.word_pos", key), rng.get_word_pos());
+    }
+
+    pub fn rng(&self, key: &str) -> io::Result<ChaCha8Rng> {
+        let seed_hex: String = self.metadata(&format!("{}.seed", key))?;
+        if seed_hex.len() != 64 {
+            return Err(invalid_data(format!("seed of {} is malformed.", key)));
+        }
+        let mut seed = [0u8; 32];
+        for (i, byte) in seed.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&seed_hex[i * 2..i * 2 + 2], 16)
+                .map_err(|e| invalid_data(e.to_string()))?;
+        }
+        let mut rng = <ChaCha8Rng as rand::SeedableRng>::from_seed(seed);
+        rng.set_stream(self.metadata(&format!("{}.stream", key))?);
+        rng.set_word_pos(self.metadata(&format!("{}.word_pos", key))?);
+        Ok(rng)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.metadata.len() as u32).to_le_bytes())?;
+        for (key, value) in self.metadata.iter() {
+            write_string(writer, key)?;
+            write_string(writer, value)?;
+        }
+        writer.write_all(&(self.tensors.len() as u32).to_le_bytes())?;
+        for (key, tensor) in self.tensors.iter() {
+            write_string(writer, key)?;
+            writer.write_all(&(tensor.nrows() as u64).to_le_bytes())?;
+            writer.write_all(&(tensor.ncols() as u64).to_le_bytes())?;
+            for value in tensor.iter() {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a checkpoint file.".to_string()));
+        }
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "checkpoint version {} is not supported.",
+                version
+            )));
+        }
+        let mut checkpoint = Self::new();
+        for _ in 0..read_u32(reader)? {
+            let key = read_string(reader)?;
+            let value = read_string(reader)?;
+            checkpoint.metadata.insert(key, value);
+        }
+        for _ in 0..read_u32(reader)? {
+            let key = read_string(reader)?;
+            let nrows = read_u64(reader)?;
+            let ncols = read_u64(reader)?;
+            let len = nrows
+                .checked_mul(ncols)
+                .and_then(|len| len.checked_mul(8))
+                .ok_or_else(|| invalid_data(format!("tensor {} is too large.", key)))?;
+            let data: Vec<f64> = read_bytes(reader, len)?
+                .chunks_exact(8)
+                .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()))
+                .collect();
+            let (nrows, ncols) = (nrows as usize, ncols as usize);
+            checkpoint
+                .tensors
+                .insert(key, na::DMatrix::<f64>::from_vec(nrows, ncols, data));
+        }
+        Ok(checkpoint)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read_from(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+pub(crate) fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_string<W: Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    writer.write_all(&(value.len() as u32).to_le_bytes())?;
+    writer.write_all(value.as_bytes())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+// Reads `len` bytes, growing the buffer as they arrive rather than trusting a
+// length read from the file, so that a corrupt one fails instead of
+// allocating whatever it claims.
+pub(crate) fn read_bytes<R: Read>(reader: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+    reader.take(len).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < len {
+        return Err(invalid_data(format!(
+            "expected {} bytes but the file ends after {}.",
+            len,
+            buf.len()
+        )));
+    }
+    Ok(buf)
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let len = read_u32(reader)?;
+    let buf = read_bytes(reader, len as u64)?;
+    String::from_utf8(buf).map_err(|e| invalid_data(e.to_string()))
+}
+
+#[test]
+fn test_checkpoint_round_trip() {
+    let mut checkpoint = Checkpoint::new();
+    checkpoint.insert_metadata("model", "Test");
+    checkpoint.insert_metadata("hidden_size_list", "3,4");
+    checkpoint
+        .tensors
+        .insert("W1".to_string(), na::dmatrix![1.0, 2.0, 3.0; 4.0, 5.0, 6.0]);
+    checkpoint
+        .tensors
+        .insert("b1".to_string(), na::DMatrix::<f64>::zeros(3, 1));
+    let mut buf = vec![];
+    checkpoint.write_to(&mut buf).unwrap();
+    assert_eq!(&buf[0..4], b"DLCK");
+    let loaded = Checkpoint::read_from(&mut buf.as_slice()).unwrap();
+    assert_eq!(loaded, checkpoint);
+    assert_eq!(loaded.metadata::<String>("model").unwrap(), "Test");
+    assert!(loaded.metadata::<usize>("model").is_err());
+    assert!(loaded.tensor("W2").is_err());
+}
+
+#[test]
+fn test_checkpoint_rejects_unknown_version() {
+    let mut buf = vec![];
+    Checkpoint::new().write_to(&mut buf).unwrap();
+    buf[4] = 2;
+    let error = Checkpoint::read_from(&mut buf.as_slice()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_checkpoint_rejects_corrupt_sizes() {
+    let mut checkpoint = Checkpoint::new();
+    checkpoint
+        .tensors
+        .insert("W".to_string(), na::dmatrix![1.0, 2.0]);
+    let mut buf = vec![];
+    checkpoint.write_to(&mut buf).unwrap();
+    // magic, version, no metadata, one tensor, the name "W", then nrows.
+    let nrows = 4 + 4 + 4 + 4 + 4 + 1;
+    for size in [u64::MAX, 1 << 40] {
+        let mut corrupt = buf.clone();
+        corrupt[nrows..nrows + 8].copy_from_slice(&size.to_le_bytes());
+        let error = Checkpoint::read_from(&mut corrupt.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+    // A string length beyond the end of the file.
+    let mut corrupt = buf.clone();
+    corrupt[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(Checkpoint::read_from(&mut corrupt.as_slice()).is_err());
+}
+
+#[test]
+fn test_checkpoint_rng_and_list() {
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = ChaCha8Rng::seed_from_u64(42);
+    let _: f64 = rng.gen();
+    let mut checkpoint = Checkpoint::new();
+    checkpoint.insert_rng("rng", &rng);
+    checkpoint.insert_list("loss", &[0.5, 0.25]);
+    let mut buf = vec![];
+    checkpoint.write_to(&mut buf).unwrap();
+    let loaded = Checkpoint::read_from(&mut buf.as_slice()).unwrap();
+    let mut restored = loaded.rng("rng").unwrap();
+    let expected: Vec<u64> = (0..4).map(|_| rng.gen()).collect();
+    let actual: Vec<u64> = (0..4).map(|_| restored.gen()).collect();
+    assert_eq!(actual, expected);
+    assert_eq!(loaded.list("loss").unwrap(), vec![0.5, 0.25]);
+}
@@ -0,0 +1,238 @@
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    metrics::ConfusionMatrix,
+    optimiser::Optimizer,
+    trainer::{Dataset, Evaluation, History, Model, Trainer},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    pub mean: f64,
+    // The population standard deviation over the folds.
+    pub std: f64,
+}
+
+impl Summary {
+    pub fn new(values: &[f64]) -> Self {
+        let n = values.len().max(1) as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let std = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+        Self { mean, std }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fold {
+    pub history: History,
+    // Measured on the held-out part after training.
+    pub evaluation: Evaluation,
+    pub confusion: ConfusionMatrix,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrossValidation {
+    pub folds: Vec<Fold>,
+}
+
+impl CrossValidation {
+    pub fn loss(&self) -> Summary {
+        self.summarise(|fold| fold.evaluation.loss)
+    }
+
+    pub fn accuracy(&self) -> Summary {
+        self.summarise(|fold| fold.evaluation.accuracy)
+    }
+
+    pub fn macro_f1(&self) -> Summary {
+        self.summarise(|fold| fold.confusion.macro_average().f1)
+    }
+
+    fn summarise(&self, f: impl Fn(&Fold) -> f64) -> Summary {
+        Summary::new(&self.folds.iter().map(f).collect::<Vec<f64>>())
+    }
+
+    // One line per fold followed by the mean and standard deviation.
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "{:>6} {:>9} {:>9} {:>9}\n",
+            "fold", "loss", "accuracy", "macro f1"
+        );
+        for (idx, fold) in self.folds.iter().enumerate() {
+            report += &format!(
+                "{:>6} {:>9.4} {:>9.4} {:>9.4}\n",
+                idx + 1,
+                fold.evaluation.loss,
+                fold.evaluation.accuracy,
+                fold.confusion.macro_average().f1
+            );
+        }
+        let (loss, accuracy, f1) = (self.loss(), self.accuracy(), self.macro_f1());
+        report += &format!(
+            "{:>6} {:>9.4} {:>9.4} {:>9.4}\n",
+            "mean", loss.mean, accuracy.mean, f1.mean
+        );
+        report += &format!(
+            "{:>6} {:>9.4} {:>9.4} {:>9.4}\n",
+            "std", loss.std, accuracy.std, f1.std
+        );
+        report
+    }
+}
+
+// k-fold cross-validation: the data is shuffled once and split into `k`
+// folds, and for every fold a fresh model is trained with `Trainer` on the
+// others and evaluated on it. Fold `i` draws from a generator seeded with
+// `seed + i`, both to build its model and to shuffle its batches.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KFold {
+    k: usize,
+    epochs: usize,
+    batch_size: usize,
+    seed: u64,
+}
+
+impl KFold {
+    pub fn new(k: usize) -> Self {
+        assert!(k >= 2, "k-fold cross-validation needs at least 2 folds.");
+        Self {
+            k,
+            epochs: 10,
+            batch_size: 100,
+            seed: 0,
+        }
+    }
+
+    pub fn epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // The training and validation indices of every fold. The first
+    // `len % k` folds hold one sample more than the others.
+    pub fn splits(&self, len: usize) -> Vec<(Vec<usize>, Vec<usize>)> {
+        assert!(len >= self.k, "there are fewer samples than folds.");
+        let mut indices: Vec<usize> = (0..len).collect();
+        indices.shuffle(&mut ChaCha8Rng::seed_from_u64(self.seed));
+        let mut start = 0;
+        (0..self.k)
+            .map(|fold| {
+                let size = len / self.k + usize::from(fold < len % self.k);
+                let val = indices[start..start + size].to_vec();
+                let train = [&indices[..start], &indices[start + size..]].concat();
+                start += size;
+                (train, val)
+            })
+            .collect()
+    }
+
+    // `build` returns a fresh model and optimiser for the given fold.
+    pub fn run<M, O>(
+        &self,
+        data: &Dataset,
+        mut build: impl FnMut(usize, &mut ChaCha8Rng) -> (M, O),
+    ) -> CrossValidation
+    where
+        M: Model,
+        O: Optimizer,
+    {
+        let folds = self
+            .splits(data.len())
+            .into_iter()
+            .enumerate()
+            .map(|(fold, (train, val))| {
+                let (x, t) = data.batch(&train);
+                let train = Dataset::new(x, t);
+                let (x, t) = data.batch(&val);
+                let val = Dataset::new(x, t);
+                let mut rng = ChaCha8Rng::seed_from_u64(self.seed.wrapping_add(fold as u64));
+                let (mut model, mut optimiser) = build(fold, &mut rng);
+                let mut trainer = Trainer::new().with_rng(rng);
+                let history = trainer.fit(
+                    &mut model,
+                    &mut optimiser,
+                    &train,
+                    Some(&val),
+                    self.epochs,
+                    self.batch_size,
+                    &mut [],
+                );
+                let evaluation = trainer.evaluate(&mut model, &val, self.batch_size);
+                let y = trainer.predict(&mut model, &val.x, self.batch_size);
+                Fold {
+                    history,
+                    evaluation,
+                    confusion: ConfusionMatrix::new(&y, &val.t),
+                }
+            })
+            .collect();
+        CrossValidation { folds }
+    }
+}
+
+#[test]
+fn test_splits() {
+    let splits = KFold::new(3).splits(10);
+    assert_eq!(
+        splits
+            .iter()
+            .map(|(_, val)| val.len())
+            .collect::<Vec<usize>>(),
+        vec![4, 3, 3]
+    );
+    let mut all_val = splits
+        .iter()
+        .flat_map(|(_, val)| val.clone())
+        .collect::<Vec<usize>>();
+    all_val.sort();
+    assert_eq!(all_val, (0..10).collect::<Vec<usize>>());
+    for (train, val) in splits.iter() {
+        assert_eq!(train.len() + val.len(), 10);
+        assert!(train.iter().all(|i| !val.contains(i)));
+    }
+    assert_eq!(KFold::new(3).splits(10), splits);
+    assert_ne!(KFold::new(3).seed(1).splits(10), splits);
+}
+
+#[test]
+fn test_run() {
+    use crate::{multi_layer_net_extended::MultiLayerNetExtended, optimiser::sgd::SGD};
+
+    let data = crate::trainer::blobs(100, 0);
+    let k_fold = KFold::new(4).epochs(5).batch_size(10);
+    let build = |_, rng: &mut ChaCha8Rng| {
+        (
+            MultiLayerNetExtended::new_with_rng(2, vec![4], 2, 0.0, "he", "relu", rng),
+            SGD::new(0.1),
+        )
+    };
+    let result = k_fold.run(&data, build);
+    assert_eq!(result.folds.len(), 4);
+    for fold in result.folds.iter() {
+        assert_eq!(fold.history.epochs(), 5);
+        assert_eq!(fold.confusion.total(), 25);
+        assert_eq!(fold.evaluation.accuracy, fold.confusion.accuracy());
+    }
+    assert!(result.accuracy().mean > 0.9);
+    assert!(result.accuracy().std < 0.1);
+    assert_eq!(result.report().lines().count(), 7);
+    assert_eq!(result, k_fold.run(&data, build));
+}
+
+#[test]
+fn test_summary() {
+    let summary = Summary::new(&[1.0, 2.0, 3.0, 4.0]);
+    assert_eq!(summary.mean, 2.5);
+    assert!((summary.std - 1.25f64.sqrt()).abs() < 1e-12);
+}
@@ -0,0 +1,306 @@
+use std::{io, path::Path};
+
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+
+use crate::{
+    float::Float, grads_exteded::GradsExt, multi_layer_net_extended::MultiLayerNetExtended,
+    optimiser::Optimizer, parameters::NamedParameters, shared::Shared, trainer::Model,
+};
+
+// Trains a network on several threads: every mini-batch is split into one
+// chunk of rows per replica, the replicas compute the gradients of their
+// chunks in parallel from copies of the network's parameters, and the network
+// gets their average weighted by chunk size. As the loss is the mean over
+// the batch this is the gradient of the whole batch, except that batch
+// normalisation uses the statistics of each chunk rather than of the batch.
+// The running statistics are averaged the same way. With dropout, every
+// replica draws its masks from a stream of its own, derived from the
+// network's generators, which then move past everything the replicas used, so
+// that the network's checkpoint is all a resumed run needs.
+pub struct DataParallel<T: Float = f64> {
+    network: MultiLayerNetExtended<T>,
+    replicas: Vec<MultiLayerNetExtended<T>>,
+}
+
+impl<T: Float> DataParallel<T> {
+    // One replica per thread of rayon's pool.
+    pub fn new(network: MultiLayerNetExtended<T>) -> Self {
+        Self::with_replicas(network, rayon::current_num_threads())
+    }
+
+    pub fn with_replicas(network: MultiLayerNetExtended<T>, replicas: usize) -> Self {
+        assert!(replicas > 0, "at least one replica is needed.");
+        let checkpoint = network.to_checkpoint();
+        let replicas = (0..replicas)
+            .map(|_| {
+                MultiLayerNetExtended::from_checkpoint(&checkpoint)
+                    .expect("a network can load its own checkpoint.")
+            })
+            .collect();
+        Self { network, replicas }
+    }
+
+    pub fn network(&self) -> &MultiLayerNetExtended<T> {
+        &self.network
+    }
+
+    pub fn network_mut(&mut self) -> &mut MultiLayerNetExtended<T> {
+        &mut self.network
+    }
+
+    pub fn into_inner(self) -> MultiLayerNetExtended<T> {
+        self.network
+    }
+
+    pub fn replicas(&self) -> usize {
+        self.replicas.len()
+    }
+
+    // Leaves the averaged gradients in `network().grads`. Batches with fewer
+    // rows than replicas leave the remaining replicas idle.
+    pub fn gradient(&mut self, x: &na::DMatrix<T>, t: &na::DMatrix<u8>) {
+        assert_eq!(x.nrows(), t.nrows(), "x and t differ in length.");
+        assert!(x.nrows() > 0, "the batch is empty.");
+        let n = x.nrows();
+        let k = self.replicas.len().min(n);
+        let mut chunks = Vec::with_capacity(k);
+        let mut start = 0;
+        for i in 0..k {
+            let len = n / k + usize::from(i < n % k);
+            chunks.push((start, len));
+            start += len;
+        }
+
+        let network = &self.network;
+        let rngs = network.dropout_rngs();
+        self.replicas[..k]
+            .par_iter_mut()
+            .zip(chunks.par_iter())
+            .enumerate()
+            .for_each(|(idx, (replica, &(start, len)))| {
+                replica.copy_state_from(network);
+                replica.set_dropout_rngs(replica_rngs(&rngs, idx));
+                replica.gradient(
+                    &x.rows(start, len).into_owned(),
+                    &t.rows(start, len).into_owned(),
+                );
+            });
+
+        let weights: Vec<T> = chunks
+            .iter()
+            .map(|&(_, len)| T::of_f64(len as f64 / n as f64))
+            .collect();
+        let grads = scaled(&self.replicas[0].grads, weights[0]);
+        let sum = grads.named_parameters();
+        for (replica, &weight) in self.replicas[1..k].iter().zip(weights[1..].iter()) {
+            for ((_, total), (_, grad)) in sum.iter().zip(replica.grads.named_parameters()) {
+                grad.with_view(|grad| {
+                    total.with_view_mut(|mut total| total.zip_apply(&grad, |a, b| *a += b * weight))
+                });
+            }
+        }
+        self.network.grads = grads;
+
+        let mut stats = self.replicas[0].running_stats();
+        for (mean, var) in stats.iter_mut() {
+            *mean *= weights[0];
+            *var *= weights[0];
+        }
+        for (replica, &weight) in self.replicas[1..k].iter().zip(weights[1..].iter()) {
+            for ((mean, var), (replica_mean, replica_var)) in
+                stats.iter_mut().zip(replica.running_stats())
+            {
+                mean.axpy(weight, &replica_mean, T::one());
+                var.axpy(weight, &replica_var, T::one());
+            }
+        }
+        self.network.set_running_stats(stats);
+
+        let rngs = rngs
+            .into_iter()
+            .enumerate()
+            .map(|(layer, mut rng)| {
+                let word_pos = self.replicas[..k]
+                    .iter()
+                    .map(|replica| replica.dropout_rngs()[layer].get_word_pos())
+                    .max()
+                    .unwrap_or(rng.get_word_pos());
+                rng.set_word_pos(word_pos);
+                rng
+            })
+            .collect();
+        self.network.set_dropout_rngs(rngs);
+    }
+}
+
+impl<T: Float> Model<T> for DataParallel<T> {
+    fn predict(&mut self, x: &na::DMatrix<T>, train_flg: bool) -> na::DMatrix<T> {
+        self.network.predict(x, train_flg)
+    }
+
+    fn loss(&mut self, x: &na::DMatrix<T>, t: &na::DMatrix<u8>, train_flg: bool) -> f64 {
+        self.network.loss(x, t, train_flg)
+    }
+
+    fn gradient(&mut self, x: &na::DMatrix<T>, t: &na::DMatrix<u8>) {
+        DataParallel::gradient(self, x, t)
+    }
+
+    fn update(&mut self, optimiser: &mut dyn Optimizer<T>) {
+        optimiser.update(&self.network.params, &self.network.grads);
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        self.network.save(path)
+    }
+}
+
+// The network's dropout generators at the same position on another stream
+// for each replica, so that no two replicas, nor the network, draw the same
+// masks.
+fn replica_rngs(rngs: &[ChaCha8Rng], replica: usize) -> Vec<ChaCha8Rng> {
+    rngs.iter()
+        .map(|rng| {
+            let mut rng = rng.clone();
+            rng.set_stream(rng.get_stream().wrapping_add(replica as u64 + 1));
+            rng
+        })
+        .collect()
+}
+
+// A copy of `grads` with fresh tensors, multiplied by `weight`.
+fn scaled<T: Float>(grads: &GradsExt<T>, weight: T) -> GradsExt<T> {
+    let matrices = |list: &[Shared<na::DMatrix<T>>]| {
+        list.iter()
+            .map(|m| Shared::new(&*m.borrow() * weight))
+            .collect()
+    };
+    let vectors = |list: &[Shared<na::DVector<T>>]| {
+        list.iter()
+            .map(|v| Shared::new(&*v.borrow() * weight))
+            .collect()
+    };
+    GradsExt {
+        d_weight_list: matrices(&grads.d_weight_list),
+        d_bias_list: vectors(&grads.d_bias_list),
+        d_gamma_list: vectors(&grads.d_gamma_list),
+        d_beta_list: vectors(&grads.d_beta_list),
+    }
+}
+
+#[cfg(test)]
+fn network(input_size: usize, output_size: usize) -> MultiLayerNetExtended {
+    use rand::SeedableRng;
+
+    MultiLayerNetExtended::new_with_rng(
+        input_size,
+        vec![6, 5],
+        output_size,
+        0.1,
+        "he",
+        "relu",
+        &mut rand_chacha::ChaCha8Rng::seed_from_u64(0),
+    )
+}
+
+#[test]
+fn test_send_sync() {
+    fn assert_send_sync<S: Send + Sync>() {}
+    assert_send_sync::<MultiLayerNetExtended>();
+    assert_send_sync::<MultiLayerNetExtended<f32>>();
+    assert_send_sync::<DataParallel>();
+}
+
+#[test]
+fn test_gradient_matches_network() {
+    use rand::SeedableRng;
+
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(1);
+    let x = crate::init_matrix_with_standard_normal(5, 4, &mut rng);
+    let t = crate::trainer::one_hot_targets(5, 3);
+    let mut single = network(4, 3);
+    single.gradient(&x, &t);
+
+    // Each of the three replicas gets a copy of the whole batch, so even the
+    // batch normalisation statistics agree.
+    let mut parallel = DataParallel::with_replicas(network(4, 3), 3);
+    assert_eq!(parallel.replicas(), 3);
+    parallel.gradient(
+        &na::DMatrix::from_fn(15, 4, |i, j| x[(i % 5, j)]),
+        &na::DMatrix::from_fn(15, 3, |i, j| t[(i % 5, j)]),
+    );
+
+    let expected = single.grads.named_parameters();
+    let actual = parallel.network().grads.named_parameters();
+    assert_eq!(expected.len(), actual.len());
+    for ((key, e), (_, a)) in expected.iter().zip(actual.iter()) {
+        assert_eq!(e.shape(), a.shape(), "{}", key);
+        assert!((e.to_matrix() - a.to_matrix()).amax() < 1e-12, "{}", key);
+    }
+    for ((mean, var), (p_mean, p_var)) in single
+        .running_stats()
+        .iter()
+        .zip(parallel.network().running_stats())
+    {
+        assert!((mean - p_mean).amax() < 1e-12);
+        assert!((var - p_var).amax() < 1e-12);
+    }
+}
+
+#[test]
+fn test_fit() {
+    use crate::{
+        optimiser::sgd::SGD,
+        trainer::{blobs, Trainer},
+    };
+
+    let train = blobs(200, 0);
+    let val = blobs(100, 1);
+    let mut parallel = DataParallel::with_replicas(network(2, 2), 4);
+    let history = Trainer::seeded(42).fit(
+        &mut parallel,
+        &mut SGD::new(0.1),
+        &train,
+        Some(&val),
+        5,
+        32,
+        &mut [],
+    );
+    assert!(*history.val_accuracy.last().unwrap() > 0.9);
+    // The trained network is the one which was wrapped.
+    let mut network = parallel.into_inner();
+    let evaluation = Trainer::seeded(0).evaluate(&mut network, &val, 30);
+    assert_eq!(evaluation.accuracy, *history.val_accuracy.last().unwrap());
+}
+
+#[test]
+fn test_dropout_streams() {
+    use rand::SeedableRng;
+
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(2);
+    let network = network(4, 3).dropout(0.5, &mut rng);
+    let mut parallel = DataParallel::with_replicas(network, 2);
+    let before = parallel.network().to_checkpoint();
+    // Both replicas get the same rows, so only their masks tell them apart.
+    let rows = crate::init_matrix_with_standard_normal(2, 4, &mut rng);
+    let x = na::DMatrix::from_fn(4, 4, |i, j| rows[(i % 2, j)]);
+    let t = na::DMatrix::<u8>::from_fn(4, 3, |i, j| u8::from(i % 2 == j));
+    parallel.gradient(&x, &t);
+    let grads = |replica: &MultiLayerNetExtended| replica.grads.d_weight_list[0].borrow().clone();
+    assert_ne!(grads(&parallel.replicas[0]), grads(&parallel.replicas[1]));
+
+    // The network's generators have moved on, so its checkpoint resumes with
+    // new masks.
+    let after = parallel.network().to_checkpoint();
+    let key = "dropout1.rng.word_pos";
+    assert!(after.metadata::<u128>(key).unwrap() > before.metadata::<u128>(key).unwrap());
+    let rngs = parallel.network().dropout_rngs();
+    let mut masks = vec![];
+    for _ in 0..2 {
+        parallel.gradient(&x, &t);
+        masks.push(grads(&parallel.replicas[0]));
+    }
+    assert_ne!(masks[0], masks[1]);
+    assert_ne!(rngs, parallel.network().dropout_rngs());
+}
@@ -0,0 +1,53 @@
+use std::fmt::{Debug, Display};
+
+// The scalar type of parameters, activations and gradients. Hyperparameters,
+// losses and metrics stay `f64` and are converted where they meet tensors,
+// as are the tensors written to checkpoints and other files.
+pub trait Float: na::RealField + Copy + Debug + Display + Send + Sync + 'static {
+    fn of_f64(value: f64) -> Self;
+
+    fn as_f64(self) -> f64;
+}
+
+impl Float for f32 {
+    fn of_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn as_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Float for f64 {
+    fn of_f64(value: f64) -> Self {
+        value
+    }
+
+    fn as_f64(self) -> f64 {
+        self
+    }
+}
+
+// Converts between precisions, e.g. to store an `f32` parameter in a
+// checkpoint, which always holds `f64`.
+pub fn cast_matrix<A: Float, B: Float>(matrix: &na::DMatrix<A>) -> na::DMatrix<B> {
+    matrix.map(|value| B::of_f64(value.as_f64()))
+}
+
+pub fn cast_vector<A: Float, B: Float>(vector: &na::DVector<A>) -> na::DVector<B> {
+    vector.map(|value| B::of_f64(value.as_f64()))
+}
+
+#[test]
+fn test_cast() {
+    let matrix = na::dmatrix![1.5f64, -2.0; 0.1, 3.0];
+    let single: na::DMatrix<f32> = cast_matrix(&matrix);
+    assert_eq!(single, na::dmatrix![1.5f32, -2.0; 0.1, 3.0]);
+    assert_eq!(cast_matrix::<f32, f64>(&single)[(0, 0)], 1.5);
+    assert!((cast_matrix::<f32, f64>(&single)[(1, 0)] - 0.1).abs() < 1e-7);
+    assert_eq!(
+        cast_vector::<f64, f32>(&na::dvector![0.25, 4.0]),
+        na::dvector![0.25f32, 4.0]
+    );
+}
@@ -0,0 +1,262 @@
+use std::fmt;
+
+use crate::{
+    layers::Layer,
+    parameters::{NamedParameters, Parameter},
+    shared::Shared,
+};
+
+#[cfg(test)]
+use crate::random_matrix;
+
+// The step of the centred differences, small enough for the truncation error
+// to stay well below the tolerances used in the tests.
+pub const H: f64 = 1e-5;
+
+// The largest relative error between the analytic and the numerical gradient
+// of every parameter, in the order the parameters were checked.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GradientCheck {
+    pub errors: Vec<(String, f64)>,
+}
+
+impl GradientCheck {
+    pub fn max_error(&self) -> f64 {
+        self.errors.iter().map(|(_, e)| *e).fold(0.0, f64::max)
+    }
+
+    pub fn error(&self, key: &str) -> Option<f64> {
+        self.errors.iter().find(|(k, _)| k == key).map(|(_, e)| *e)
+    }
+
+    pub fn passes(&self, tolerance: f64) -> bool {
+        self.errors.iter().all(|(_, e)| *e <= tolerance)
+    }
+
+    // Panics with the parameters whose error exceeds `tolerance`, for use in
+    // unit tests.
+    pub fn assert_within(&self, tolerance: f64) {
+        let failed: Vec<String> = self
+            .errors
+            .iter()
+            .filter(|(_, e)| e.is_nan() || *e > tolerance)
+            .map(|(k, e)| format!("{} ({:e})", k, e))
+            .collect();
+        assert!(
+            failed.is_empty(),
+            "gradient check failed with tolerance {:e} for {}",
+            tolerance,
+            failed.join(", ")
+        );
+    }
+}
+
+impl fmt::Display for GradientCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, error) in self.errors.iter() {
+            writeln!(f, "{:>8} {:e}", key, error)?;
+        }
+        Ok(())
+    }
+}
+
+// |a - b| / (|a| + |b|). The denominator is kept above 1e-8 so that round-off
+// in gradients which are zero in theory, like those of a bias followed by
+// batch normalisation, does not count as a failure.
+pub fn relative_error(a: f64, b: f64) -> f64 {
+    (a - b).abs() / (a.abs() + b.abs()).max(1e-8)
+}
+
+pub fn max_relative_error(analytic: &na::DMatrix<f64>, numerical: &na::DMatrix<f64>) -> f64 {
+    assert_eq!(
+        analytic.shape(),
+        numerical.shape(),
+        "the analytic and numerical gradients differ in shape."
+    );
+    analytic
+        .iter()
+        .zip(numerical.iter())
+        .map(|(&a, &n)| relative_error(a, n))
+        .fold(0.0, f64::max)
+}
+
+// Centred differences of `loss` with respect to every element of `param`,
+// which is perturbed in place and restored afterwards.
+pub fn numerical_gradient(param: &Parameter, mut loss: impl FnMut() -> f64) -> na::DMatrix<f64> {
+    let (nrows, ncols) = param.shape();
+    na::DMatrix::<f64>::from_fn(nrows, ncols, |i, j| {
+        let tmp_val = param.with_view(|view| view[(i, j)]);
+        param.with_view_mut(|mut view| view[(i, j)] = tmp_val + H);
+        let fxh1 = loss();
+        param.with_view_mut(|mut view| view[(i, j)] = tmp_val - H);
+        let fxh2 = loss();
+        param.with_view_mut(|mut view| view[(i, j)] = tmp_val);
+        (fxh1 - fxh2) / (2.0 * H)
+    })
+}
+
+// Compares `grads` with the numerical gradients of `loss` for the parameters
+// under the same keys. `grads` must already hold the analytic gradients of
+// `loss` at the current parameters, e.g. after `gradient(x, t)`. Parameters
+// with an empty gradient, like the unused "gamma" and "beta" of the output
+// layer, are skipped.
+pub fn check_parameters(
+    params: &dyn NamedParameters,
+    grads: &dyn NamedParameters,
+    mut loss: impl FnMut() -> f64,
+) -> GradientCheck {
+    let grads = grads.named_parameters();
+    let errors = params
+        .named_parameters()
+        .into_iter()
+        .filter_map(|(key, param)| {
+            let analytic = match grads.iter().find(|(k, _)| *k == key) {
+                Some((_, grad)) => grad.to_matrix(),
+                None => panic!("there is no gradient for {}.", key),
+            };
+            if param.is_empty() || analytic.is_empty() {
+                return None;
+            }
+            let numerical = numerical_gradient(&param, &mut loss);
+            Some((key, max_relative_error(&analytic, &numerical)))
+        })
+        .collect();
+    GradientCheck { errors }
+}
+
+// Checks the gradient `layer.backwards` returns for its input under "x", and
+// the gradients of `params` which `grads` reads from the layer after the
+// backward pass, in the same order. The loss is sum(forwards(x) ⊙ dout),
+// whose gradient with respect to the output is `dout`.
+pub fn check_layer<L: Layer>(
+    layer: &mut L,
+    x: &na::DMatrix<f64>,
+    dout: &na::DMatrix<f64>,
+    params: &[(&str, Parameter)],
+    grads: impl FnOnce(&L) -> Vec<na::DMatrix<f64>>,
+) -> GradientCheck {
+    layer.forwards(x, true);
+    let dx = layer.backwards(dout);
+    let analytic = grads(layer);
+    assert_eq!(
+        analytic.len(),
+        params.len(),
+        "there should be one gradient per parameter."
+    );
+
+    let input = Parameter::Matrix(Shared::new(x.clone()));
+    let mut errors = vec![(
+        "x".to_string(),
+        max_relative_error(
+            &dx,
+            &numerical_gradient(&input, || {
+                layer.forwards(&input.to_matrix(), true).dot(dout)
+            }),
+        ),
+    )];
+    for ((key, param), grad) in params.iter().zip(analytic.iter()) {
+        let numerical = numerical_gradient(param, || layer.forwards(x, true).dot(dout));
+        errors.push((key.to_string(), max_relative_error(grad, &numerical)));
+    }
+    GradientCheck { errors }
+}
+
+#[test]
+fn test_relative_error() {
+    assert_eq!(relative_error(0.0, 0.0), 0.0);
+    assert_eq!(relative_error(1.0, -1.0), 1.0);
+    assert!(relative_error(1e-18, 0.0) < 1e-9);
+    assert!((relative_error(1.0, 1.1) - 0.1 / 2.1).abs() < 1e-15);
+}
+
+#[test]
+fn test_check_layer() {
+    use crate::layers::{
+        affine_layer::Affine, batch_normalisation_layer::BatchNormalisationLayer,
+        sigmoid_layer::Sigmoid,
+    };
+
+    let x = random_matrix(5, 4, 0);
+    let w = Shared::new(random_matrix(4, 3, 1));
+    let b = Shared::new(na::DVector::<f64>::from_column_slice(&[0.1, -0.2, 0.3]));
+    let mut affine = Affine::new(w.clone(), b.clone());
+    let params = [("W", Parameter::Matrix(w)), ("b", Parameter::Vector(b))];
+    let result = check_layer(
+        &mut affine,
+        &x,
+        &random_matrix(5, 3, 2),
+        &params,
+        |affine| {
+            vec![
+                affine.dw.clone(),
+                na::DMatrix::from_column_slice(3, 1, affine.db.as_slice()),
+            ]
+        },
+    );
+    assert_eq!(
+        result
+            .errors
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<Vec<&str>>(),
+        vec!["x", "W", "b"]
+    );
+    result.assert_within(1e-7);
+
+    check_layer(
+        &mut Sigmoid::new(),
+        &x,
+        &random_matrix(5, 4, 3),
+        &[],
+        |_| vec![],
+    )
+    .assert_within(1e-7);
+
+    let gamma = Shared::new(na::DVector::<f64>::from_column_slice(&[1.0, 0.5, 2.0, 1.5]));
+    let beta = Shared::new(na::DVector::<f64>::zeros(4));
+    let mut batch_norm = BatchNormalisationLayer::new(gamma.clone(), beta.clone(), 0.9);
+    let params = [
+        ("gamma", Parameter::Vector(gamma)),
+        ("beta", Parameter::Vector(beta)),
+    ];
+    check_layer(
+        &mut batch_norm,
+        &x,
+        &random_matrix(5, 4, 4),
+        &params,
+        |layer| {
+            vec![
+                na::DMatrix::from_column_slice(4, 1, layer.dgamma.as_slice()),
+                na::DMatrix::from_column_slice(4, 1, layer.dbeta.as_slice()),
+            ]
+        },
+    )
+    .assert_within(1e-5);
+}
+
+#[test]
+fn test_check_parameters() {
+    use crate::multi_layer_net_extended::MultiLayerNetExtended;
+    use rand::SeedableRng;
+
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+    let mut network =
+        MultiLayerNetExtended::new_with_rng(4, vec![5, 3], 3, 0.1, "sigmoid", "sigmoid", &mut rng);
+    let x = random_matrix(6, 4, 1);
+    let t = crate::trainer::one_hot_targets(6, 3);
+    network.gradient(&x, &t);
+    let params = network.params.clone();
+    let grads = network.grads.clone();
+    let result = check_parameters(&params, &grads, || network.loss(&x, &t, true));
+    assert_eq!(result.errors.len(), 10);
+    assert!(result.error("gamma2").is_some());
+    result.assert_within(1e-5);
+    assert_eq!(result.to_string().lines().count(), 10);
+
+    // A gradient that is off by a factor is reported under its key.
+    grads.d_weight_list[1].borrow_mut().scale_mut(1.1);
+    let result = check_parameters(&params, &grads, || network.loss(&x, &t, true));
+    assert!(!result.passes(1e-5));
+    assert!(result.error("W2").unwrap() > 0.04);
+    assert_eq!(result.max_error(), result.error("W2").unwrap());
+}
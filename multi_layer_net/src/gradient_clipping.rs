@@ -0,0 +1,292 @@
+use std::io;
+
+use crate::{
+    checkpoint::Checkpoint,
+    float::Float,
+    optimiser::{Optimizer, OptimizerBase},
+    parameters::NamedParameters,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Clipping {
+    // Rescales all gradients together so that their joint L2 norm does not
+    // exceed the given value.
+    GlobalNorm(f64),
+    // Rescales every gradient on its own so that its L2 norm does not exceed
+    // the given value.
+    PerTensorNorm(f64),
+    // Clamps every gradient element into [-value, value].
+    Value(f64),
+}
+
+// L2 norm of every non-empty gradient, in the order of `named_parameters`.
+pub fn gradient_norms<T: Float>(grads: &dyn NamedParameters<T>) -> Vec<(String, f64)> {
+    grads
+        .named_parameters()
+        .into_iter()
+        .filter(|(_, grad)| !grad.is_empty())
+        .map(|(key, grad)| {
+            let norm = grad.with_view(|view| view.norm()).as_f64();
+            (key, norm)
+        })
+        .collect()
+}
+
+pub fn global_norm<T: Float>(grads: &dyn NamedParameters<T>) -> f64 {
+    gradient_norms(grads)
+        .iter()
+        .map(|(_, norm)| norm * norm)
+        .sum::<f64>()
+        .sqrt()
+}
+
+// Returns the global norm measured before clipping.
+pub fn clip_grad_norm<T: Float>(grads: &dyn NamedParameters<T>, max_norm: f64) -> f64 {
+    let total_norm = global_norm(grads);
+    if total_norm > max_norm {
+        let scale = T::of_f64(max_norm / (total_norm + 1e-6));
+        for (_, grad) in grads.named_parameters() {
+            grad.with_view_mut(|mut view| view *= scale);
+        }
+    }
+    total_norm
+}
+
+// Returns whether any gradient was clipped.
+pub fn clip_grad_norm_per_tensor<T: Float>(grads: &dyn NamedParameters<T>, max_norm: f64) -> bool {
+    let mut clipped = false;
+    for (_, grad) in grads.named_parameters() {
+        clipped |= grad.with_view_mut(|view| clip_tensor_norm(view, max_norm));
+    }
+    clipped
+}
+
+fn clip_tensor_norm<T: Float>(mut grad: na::DMatrixViewMut<T>, max_norm: f64) -> bool {
+    let norm = grad.norm().as_f64();
+    if norm > max_norm {
+        grad *= T::of_f64(max_norm / (norm + 1e-6));
+    }
+    norm > max_norm
+}
+
+pub fn clip_grad_value<T: Float>(grads: &dyn NamedParameters<T>, clip_value: f64) {
+    let clip_value = T::of_f64(clip_value);
+    for (_, grad) in grads.named_parameters() {
+        grad.with_view_mut(|mut view| view.apply(|g| *g = g.clamp(-clip_value, clip_value)));
+    }
+}
+
+// Clips the gradients in place before handing them to the wrapped optimiser.
+pub struct ClipGradients<O> {
+    optimiser: O,
+    clipping: Clipping,
+    last_norm: f64,
+    updates: usize,
+    report_every: usize,
+}
+
+impl<O> ClipGradients<O> {
+    pub fn new(optimiser: O, clipping: Clipping) -> Self {
+        Self {
+            optimiser,
+            clipping,
+            last_norm: 0.0,
+            updates: 0,
+            report_every: 0,
+        }
+    }
+
+    // Prints the norms of the weight gradients every `report_every` updates,
+    // and whether they were clipped. 0, the default, prints nothing.
+    pub fn report(mut self, report_every: usize) -> Self {
+        self.report_every = report_every;
+        self
+    }
+
+    // Global norm of the gradients passed to the last `update`, before clipping.
+    pub fn last_norm(&self) -> f64 {
+        self.last_norm
+    }
+
+    pub fn optimiser(&self) -> &O {
+        &self.optimiser
+    }
+
+    pub fn into_inner(self) -> O {
+        self.optimiser
+    }
+}
+
+impl<T: Float, O: Optimizer<T>> Optimizer<T> for ClipGradients<O> {
+    // The per-tensor path, which `step_matrix` and `step_vector` take, sees
+    // one gradient at a time, so it cannot measure a global norm.
+    // `last_norm` is left alone.
+    fn step(&mut self, key: &str, param: na::DMatrixViewMut<T>, grad: na::DMatrixView<T>) {
+        let mut grad = grad.clone_owned();
+        match self.clipping {
+            Clipping::GlobalNorm(_) => panic!(
+                "Clipping::GlobalNorm needs every gradient at once, so it only works through \
+                 `update`. Use Clipping::PerTensorNorm to clip gradients one at a time."
+            ),
+            Clipping::PerTensorNorm(max_norm) => {
+                clip_tensor_norm(grad.as_view_mut(), max_norm);
+            }
+            Clipping::Value(clip_value) => {
+                let clip_value = T::of_f64(clip_value);
+                grad.apply(|g| *g = g.clamp(-clip_value, clip_value));
+            }
+        }
+        self.optimiser.step(key, param, grad.as_view());
+    }
+
+    fn update(&mut self, params: &dyn NamedParameters<T>, grads: &dyn NamedParameters<T>) {
+        self.updates += 1;
+        let report = self.report_every > 0 && self.updates.is_multiple_of(self.report_every);
+        if report {
+            let norms = gradient_norms(grads)
+                .iter()
+                .filter(|(key, _)| key.starts_with('W'))
+                .map(|(key, norm)| format!("{} {:.2e}", key, norm))
+                .collect::<Vec<String>>();
+            println!(
+                "Update {} gradient norms: {}",
+                self.updates,
+                norms.join(" ")
+            );
+        }
+        let clipped = match self.clipping {
+            Clipping::GlobalNorm(max_norm) => {
+                self.last_norm = clip_grad_norm(grads, max_norm);
+                self.last_norm > max_norm
+            }
+            Clipping::PerTensorNorm(max_norm) => {
+                self.last_norm = global_norm(grads);
+                clip_grad_norm_per_tensor(grads, max_norm)
+            }
+            Clipping::Value(clip_value) => {
+                self.last_norm = global_norm(grads);
+                let clipped = grads.named_parameters().iter().any(|(_, grad)| {
+                    grad.with_view(|view| view.iter().any(|g| g.abs().as_f64() > clip_value))
+                });
+                clip_grad_value(grads, clip_value);
+                clipped
+            }
+        };
+        if report && clipped {
+            println!("Clipped gradients with global norm {:.2e}", self.last_norm);
+        }
+        self.optimiser.update(params, grads);
+    }
+}
+
+impl<O: OptimizerBase> OptimizerBase for ClipGradients<O> {
+    fn learning_rate(&self) -> f64 {
+        self.optimiser.learning_rate()
+    }
+
+    fn set_learning_rate(&mut self, lr: f64) {
+        self.optimiser.set_learning_rate(lr);
+    }
+
+    fn save_state(&self, checkpoint: &mut Checkpoint, prefix: &str) {
+        self.optimiser.save_state(checkpoint, prefix);
+    }
+
+    fn load_state(&mut self, checkpoint: &Checkpoint, prefix: &str) -> io::Result<()> {
+        self.optimiser.load_state(checkpoint, prefix)
+    }
+}
+
+#[cfg(test)]
+fn test_grads() -> crate::grads::Grads {
+    use crate::shared::Shared;
+
+    let mut grads = crate::grads::Grads::new(2);
+    grads.d_weight_list[0] = Shared::new(na::dmatrix![3.0, 0.0; 0.0, 0.0]);
+    grads.d_bias_list[0] = Shared::new(na::dvector![0.0, -4.0]);
+    grads
+}
+
+#[test]
+fn test_gradient_norms() {
+    let grads = test_grads();
+    assert_eq!(
+        gradient_norms(&grads),
+        vec![("W1".to_string(), 3.0), ("b1".to_string(), 4.0)]
+    );
+    assert_eq!(global_norm(&grads), 5.0);
+}
+
+#[test]
+fn test_clip_grad_norm() {
+    let grads = test_grads();
+    assert_eq!(clip_grad_norm(&grads, 1.0), 5.0);
+    assert!((global_norm(&grads) - 1.0).abs() < 1e-6);
+    assert!((grads.d_bias_list[0].borrow()[1] + 0.8).abs() < 1e-6);
+    assert_eq!(clip_grad_norm(&grads, 10.0), global_norm(&grads));
+}
+
+#[test]
+fn test_clip_gradients_by_value() {
+    use crate::shared::Shared;
+
+    use crate::{optimiser::sgd::SGD, params::Params};
+
+    let grads = test_grads();
+    let mut params = Params::new(2);
+    params.weight_list[0] = Shared::new(na::DMatrix::<f64>::zeros(2, 2));
+    params.bias_list[0] = Shared::new(na::DVector::<f64>::zeros(2));
+    let mut optimiser = ClipGradients::new(SGD::new(1.0), Clipping::Value(1.0));
+    optimiser.update(&params, &grads);
+    assert_eq!(optimiser.last_norm(), 5.0);
+    assert_eq!(
+        *params.weight_list[0].borrow(),
+        na::dmatrix![-1.0, 0.0; 0.0, 0.0]
+    );
+    assert_eq!(*params.bias_list[0].borrow(), na::dvector![0.0, 1.0]);
+}
+
+#[test]
+fn test_clip_gradients_per_tensor() {
+    use crate::optimiser::sgd::SGD;
+
+    // Like a model which keeps plain matrices and updates them one by one.
+    let mut w = na::DMatrix::<f64>::zeros(2, 2);
+    let mut b = na::DVector::<f64>::zeros(2);
+    let mut optimiser = ClipGradients::new(SGD::new(1.0), Clipping::Value(1.0));
+    optimiser.step_matrix("W1", &mut w, &na::dmatrix![3.0, -0.5; 0.0, -2.0]);
+    optimiser.step_vector("b1", &mut b, &na::dvector![0.0, -4.0]);
+    assert_eq!(w, na::dmatrix![-1.0, 0.5; 0.0, 1.0]);
+    assert_eq!(b, na::dvector![0.0, 1.0]);
+
+    let mut w = na::DMatrix::<f64>::zeros(2, 2);
+    let mut b = na::DVector::<f64>::zeros(2);
+    let mut optimiser = ClipGradients::new(SGD::new(1.0), Clipping::PerTensorNorm(1.0));
+    optimiser.step_matrix("W1", &mut w, &na::dmatrix![3.0, 0.0; 0.0, 4.0]);
+    optimiser.step_vector("b1", &mut b, &na::dvector![0.0, -0.5]);
+    assert!((w.norm() - 1.0).abs() < 1e-6);
+    assert!((w[(1, 1)] + 0.8).abs() < 1e-6);
+    assert_eq!(b, na::dvector![0.0, 0.5]);
+}
+
+#[test]
+#[should_panic(expected = "Clipping::GlobalNorm needs every gradient at once")]
+fn test_clip_gradients_global_norm_per_tensor() {
+    use crate::optimiser::sgd::SGD;
+
+    let mut w = na::DMatrix::<f64>::zeros(2, 2);
+    let mut optimiser = ClipGradients::new(SGD::new(1.0), Clipping::GlobalNorm(1.0));
+    optimiser.step_matrix("W1", &mut w, &na::dmatrix![3.0, 0.0; 0.0, 4.0]);
+}
+
+#[test]
+fn test_clip_grad_norm_per_tensor() {
+    let grads = test_grads();
+    assert!(clip_grad_norm_per_tensor(&grads, 3.5));
+    assert_eq!(
+        *grads.d_weight_list[0].borrow(),
+        na::dmatrix![3.0, 0.0; 0.0, 0.0]
+    );
+    assert!((grads.d_bias_list[0].borrow()[1] + 3.5).abs() < 1e-5);
+    assert!(!clip_grad_norm_per_tensor(&grads, 10.0));
+}
@@ -1,16 +1,16 @@
-use std::{cell::RefCell, rc::Rc};
+use crate::{float::Float, shared::Shared};
 
 #[derive(Clone, Debug)]
-pub struct Grads {
-    pub d_weight_list: Vec<Rc<RefCell<na::DMatrix<f64>>>>,
-    pub d_bias_list: Vec<Rc<RefCell<na::DVector<f64>>>>,
+pub struct Grads<T: Float = f64> {
+    pub d_weight_list: Vec<Shared<na::DMatrix<T>>>,
+    pub d_bias_list: Vec<Shared<na::DVector<T>>>,
 }
 
-impl Grads {
+impl<T: Float> Grads<T> {
     pub fn new(size: usize) -> Self {
         Self {
-            d_weight_list: vec![Rc::new(RefCell::new(na::DMatrix::<f64>::zeros(0, 0))); size],
-            d_bias_list: vec![Rc::new(RefCell::new(na::DVector::<f64>::zeros(0))); size],
+            d_weight_list: vec![Shared::new(na::DMatrix::<T>::zeros(0, 0)); size],
+            d_bias_list: vec![Shared::new(na::DVector::<T>::zeros(0)); size],
         }
     }
 }
@@ -1,20 +1,20 @@
-use std::{cell::RefCell, rc::Rc};
+use crate::{float::Float, shared::Shared};
 
 #[derive(Clone, Debug)]
-pub struct GradsExt {
-    pub d_weight_list: Vec<Rc<RefCell<na::DMatrix<f64>>>>,
-    pub d_bias_list: Vec<Rc<RefCell<na::DVector<f64>>>>,
-    pub d_gamma_list: Vec<Rc<RefCell<na::DVector<f64>>>>,
-    pub d_beta_list: Vec<Rc<RefCell<na::DVector<f64>>>>,
+pub struct GradsExt<T: Float = f64> {
+    pub d_weight_list: Vec<Shared<na::DMatrix<T>>>,
+    pub d_bias_list: Vec<Shared<na::DVector<T>>>,
+    pub d_gamma_list: Vec<Shared<na::DVector<T>>>,
+    pub d_beta_list: Vec<Shared<na::DVector<T>>>,
 }
 
-impl GradsExt {
+impl<T: Float> GradsExt<T> {
     pub fn new(size: usize) -> Self {
         Self {
-            d_weight_list: vec![Rc::new(RefCell::new(na::DMatrix::<f64>::zeros(0, 0))); size],
-            d_bias_list: vec![Rc::new(RefCell::new(na::DVector::<f64>::zeros(0))); size],
-            d_gamma_list: vec![Rc::new(RefCell::new(na::DVector::<f64>::zeros(0))); size],
-            d_beta_list: vec![Rc::new(RefCell::new(na::DVector::<f64>::zeros(0))); size],
+            d_weight_list: vec![Shared::new(na::DMatrix::<T>::zeros(0, 0)); size],
+            d_bias_list: vec![Shared::new(na::DVector::<T>::zeros(0)); size],
+            d_gamma_list: vec![Shared::new(na::DVector::<T>::zeros(0)); size],
+            d_beta_list: vec![Shared::new(na::DVector::<T>::zeros(0)); size],
         }
     }
 }
@@ -0,0 +1,384 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+
+use crate::{
+    callbacks::terminate_on_nan::TerminateOnNaN,
+    multi_layer_net_extended::MultiLayerNetExtended,
+    optimiser::sgd::SGD,
+    plot::{LinePlot, Series},
+    trainer::{Dataset, History, Trainer},
+};
+
+// A range sampled uniformly in log space, e.g. 1e-6..1e-2 for the learning
+// rate, so that every order of magnitude is equally likely.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LogUniform {
+    pub low: f64,
+    pub high: f64,
+}
+
+impl LogUniform {
+    pub fn new(low: f64, high: f64) -> Self {
+        assert!(
+            0.0 < low && low <= high,
+            "a log-uniform range needs 0 < low <= high."
+        );
+        Self { low, high }
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        10f64.powf(rng.gen_range(self.low.log10()..=self.high.log10()))
+    }
+
+    // `n` values evenly spaced in log space from `low` to `high`.
+    pub fn grid(&self, n: usize) -> Vec<f64> {
+        let (low, high) = (self.low.log10(), self.high.log10());
+        match n {
+            0 => vec![],
+            1 => vec![self.low],
+            _ => (0..n)
+                .map(|i| 10f64.powf(low + (high - low) * i as f64 / (n - 1) as f64))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hyperparameters {
+    pub learning_rate: f64,
+    pub weight_decay: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchSpace {
+    pub learning_rate: LogUniform,
+    pub weight_decay: LogUniform,
+}
+
+impl SearchSpace {
+    // The ranges searched in section 6.5 of the book.
+    pub fn book() -> Self {
+        Self {
+            learning_rate: LogUniform::new(1e-6, 1e-2),
+            weight_decay: LogUniform::new(1e-8, 1e-4),
+        }
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Hyperparameters {
+        Hyperparameters {
+            learning_rate: self.learning_rate.sample(rng),
+            weight_decay: self.weight_decay.sample(rng),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trial {
+    pub id: usize,
+    pub hyperparameters: Hyperparameters,
+    pub history: History,
+}
+
+impl Trial {
+    pub fn epochs(&self) -> usize {
+        self.history.epochs()
+    }
+
+    // The validation accuracy after the last epoch. Runs which diverged score 0.
+    pub fn score(&self) -> f64 {
+        let diverged = self.history.loss.iter().any(|loss| !loss.is_finite());
+        match self.history.val_accuracy.last() {
+            Some(&accuracy) if !diverged => accuracy,
+            _ => 0.0,
+        }
+    }
+}
+
+// Trials ranked best first: those trained for more epochs come first, which
+// only matters for successive halving, and then those with a higher score.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchResult {
+    pub trials: Vec<Trial>,
+}
+
+impl SearchResult {
+    pub fn new(mut trials: Vec<Trial>) -> Self {
+        trials.sort_by(|a, b| {
+            b.epochs()
+                .cmp(&a.epochs())
+                .then(b.score().total_cmp(&a.score()))
+                .then(a.id.cmp(&b.id))
+        });
+        Self { trials }
+    }
+
+    pub fn best(&self) -> Option<&Trial> {
+        self.trials.first()
+    }
+
+    pub fn table(&self) -> String {
+        let mut table = format!(
+            "{:>5} {:>6} {:>13} {:>13} {:>7} {:>13}\n",
+            "rank", "trial", "learning rate", "weight decay", "epochs", "val accuracy"
+        );
+        for (rank, trial) in self.trials.iter().enumerate() {
+            table += &format!(
+                "{:>5} {:>6} {:>13.3e} {:>13.3e} {:>7} {:>12.2}%\n",
+                rank + 1,
+                trial.id,
+                trial.hyperparameters.learning_rate,
+                trial.hyperparameters.weight_decay,
+                trial.epochs(),
+                trial.score() * 100.0
+            );
+        }
+        table
+    }
+
+    // The validation accuracy of the `top` best trials over their epochs,
+    // like figure 6-24 of the book.
+    pub fn plot(&self, top: usize, title: &str) -> LinePlot {
+        self.trials.iter().take(top).enumerate().fold(
+            LinePlot::new(title)
+                .x_desc("Epochs")
+                .y_desc("% Validation Accuracy")
+                .y_range(0.0..100.0),
+            |plot, (rank, trial)| {
+                plot.series(
+                    Series::new(
+                        &format!(
+                            "#{} lr {:.1e} decay {:.1e}",
+                            rank + 1,
+                            trial.hyperparameters.learning_rate,
+                            trial.hyperparameters.weight_decay
+                        ),
+                        &trial.history.val_accuracy,
+                    )
+                    .scaled(100.0),
+                )
+            },
+        )
+    }
+}
+
+// Trains short runs of `MultiLayerNetExtended` with SGD, as in the book, one
+// per set of hyperparameters and in parallel. Every trial is seeded from the
+// search seed and its id, so a search gives the same result every time and a
+// trial trained for more epochs repeats its shorter runs exactly.
+pub struct HyperparameterSearch<'a> {
+    train: &'a Dataset,
+    val: &'a Dataset,
+    hidden_size_list: Vec<usize>,
+    weight_init_std: String,
+    activation: String,
+    epochs: usize,
+    batch_size: usize,
+    seed: u64,
+}
+
+impl<'a> HyperparameterSearch<'a> {
+    pub fn new(train: &'a Dataset, val: &'a Dataset, hidden_size_list: Vec<usize>) -> Self {
+        Self {
+            train,
+            val,
+            hidden_size_list,
+            weight_init_std: "he".to_string(),
+            activation: "relu".to_string(),
+            epochs: 50,
+            batch_size: 100,
+            seed: 0,
+        }
+    }
+
+    pub fn weight_init_std(mut self, weight_init_std: &str) -> Self {
+        self.weight_init_std = weight_init_std.to_string();
+        self
+    }
+
+    pub fn activation(mut self, activation: &str) -> Self {
+        self.activation = activation.to_string();
+        self
+    }
+
+    // The number of epochs of every trial, or of the longest ones for
+    // successive halving.
+    pub fn epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // Every combination of the given learning rates and weight decays.
+    pub fn grid(&self, learning_rates: &[f64], weight_decays: &[f64]) -> SearchResult {
+        let candidates = learning_rates
+            .iter()
+            .flat_map(|&learning_rate| {
+                weight_decays
+                    .iter()
+                    .map(move |&weight_decay| Hyperparameters {
+                        learning_rate,
+                        weight_decay,
+                    })
+            })
+            .enumerate()
+            .collect::<Vec<(usize, Hyperparameters)>>();
+        SearchResult::new(self.run(&candidates, self.epochs))
+    }
+
+    pub fn random(&self, space: &SearchSpace, trials: usize) -> SearchResult {
+        SearchResult::new(self.run(&self.sample(space, trials), self.epochs))
+    }
+
+    // Trains `trials` random candidates for `min_epochs`, keeps the best
+    // 1 / `eta` of them, trains those `eta` times longer and so on until a
+    // single candidate is left or the trials reach the configured epochs.
+    pub fn successive_halving(
+        &self,
+        space: &SearchSpace,
+        trials: usize,
+        min_epochs: usize,
+        eta: usize,
+    ) -> SearchResult {
+        assert!(eta >= 2, "eta must be at least 2.");
+        assert!(min_epochs > 0, "min_epochs must be positive.");
+        let mut candidates = self.sample(space, trials);
+        let mut epochs = min_epochs.min(self.epochs);
+        let mut finished = vec![];
+        loop {
+            let mut rung = SearchResult::new(self.run(&candidates, epochs)).trials;
+            if rung.len() <= 1 || epochs >= self.epochs {
+                finished.extend(rung);
+                break;
+            }
+            finished.extend(rung.split_off((rung.len() / eta).max(1)));
+            candidates = rung
+                .iter()
+                .map(|trial| (trial.id, trial.hyperparameters))
+                .collect();
+            epochs = (epochs * eta).min(self.epochs);
+        }
+        SearchResult::new(finished)
+    }
+
+    fn sample(&self, space: &SearchSpace, trials: usize) -> Vec<(usize, Hyperparameters)> {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        (0..trials).map(|id| (id, space.sample(&mut rng))).collect()
+    }
+
+    fn run(&self, candidates: &[(usize, Hyperparameters)], epochs: usize) -> Vec<Trial> {
+        candidates
+            .par_iter()
+            .map(|&(id, hyperparameters)| self.trial(id, hyperparameters, epochs))
+            .collect()
+    }
+
+    fn trial(&self, id: usize, hyperparameters: Hyperparameters, epochs: usize) -> Trial {
+        let seed = self.seed.wrapping_add(id as u64);
+        let mut network = MultiLayerNetExtended::new_with_rng(
+            self.train.x.ncols(),
+            self.hidden_size_list.clone(),
+            self.train.t.ncols(),
+            hyperparameters.weight_decay,
+            &self.weight_init_std,
+            &self.activation,
+            &mut ChaCha8Rng::seed_from_u64(seed),
+        );
+        let history = Trainer::seeded(seed).fit(
+            &mut network,
+            &mut SGD::new(hyperparameters.learning_rate),
+            self.train,
+            Some(self.val),
+            epochs,
+            self.batch_size,
+            &mut [&mut TerminateOnNaN::new()],
+        );
+        Trial {
+            id,
+            hyperparameters,
+            history,
+        }
+    }
+}
+
+#[test]
+fn test_log_uniform() {
+    let range = LogUniform::new(1e-6, 1e-2);
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let samples = (0..1000)
+        .map(|_| range.sample(&mut rng))
+        .collect::<Vec<f64>>();
+    assert!(samples.iter().all(|s| (1e-6..=1e-2).contains(s)));
+    // Each of the four decades holds about a quarter of the samples.
+    let below_1e4 = samples.iter().filter(|&&s| s < 1e-4).count();
+    assert!((400..600).contains(&below_1e4));
+    let grid = range.grid(5);
+    assert_eq!(grid.len(), 5);
+    for (value, expected) in grid.iter().zip([1e-6, 1e-5, 1e-4, 1e-3, 1e-2]) {
+        assert!((value / expected - 1.0).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn test_grid_and_random_search() {
+    let train = crate::trainer::blobs(100, 0);
+    let val = crate::trainer::blobs(50, 1);
+    let search = HyperparameterSearch::new(&train, &val, vec![4])
+        .epochs(3)
+        .batch_size(20);
+    let result = search.grid(&[1e-5, 0.1], &[0.0, 1e-3]);
+    assert_eq!(result.trials.len(), 4);
+    // A learning rate of 1e-5 barely moves the weights.
+    assert_eq!(result.best().unwrap().hyperparameters.learning_rate, 0.1);
+    assert!(result.best().unwrap().score() > 0.9);
+    assert!(result
+        .trials
+        .windows(2)
+        .all(|pair| pair[0].score() >= pair[1].score()));
+    assert_eq!(result.table().lines().count(), 5);
+    assert!(result.plot(3, "Top trials").to_svg_string().is_ok());
+
+    let space = SearchSpace {
+        learning_rate: LogUniform::new(1e-3, 1.0),
+        weight_decay: LogUniform::new(1e-8, 1e-4),
+    };
+    let result = search.random(&space, 6);
+    assert_eq!(result.trials.len(), 6);
+    assert_eq!(result, search.random(&space, 6));
+    assert!(result.trials.iter().all(|trial| trial.epochs() == 3));
+}
+
+#[test]
+fn test_successive_halving() {
+    let train = crate::trainer::blobs(60, 0);
+    let val = crate::trainer::blobs(30, 1);
+    let search = HyperparameterSearch::new(&train, &val, vec![4])
+        .epochs(4)
+        .batch_size(20);
+    let result = search.successive_halving(&SearchSpace::book(), 8, 1, 2);
+    assert_eq!(result.trials.len(), 8);
+    // 8 candidates are halved to 4 after 1 epoch and to 2 after 2 epochs,
+    // which then train for the full 4.
+    let epochs = result
+        .trials
+        .iter()
+        .map(|trial| trial.epochs())
+        .collect::<Vec<usize>>();
+    assert_eq!(epochs, vec![4, 4, 2, 2, 1, 1, 1, 1]);
+    // A longer trial starts like the shorter run it was promoted from.
+    let best = result.best().unwrap();
+    let short = search.trial(best.id, best.hyperparameters, 1);
+    assert_eq!(
+        short.history.loss[..],
+        best.history.loss[..short.history.loss.len()]
+    );
+}
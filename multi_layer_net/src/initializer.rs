@@ -0,0 +1,177 @@
+use std::str::FromStr;
+
+use rand::Rng;
+
+// How the weights of an affine layer with `fan_in` inputs and `fan_out`
+// outputs are drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Initializer {
+    Constant(f64),
+    // A normal distribution with the given standard deviation.
+    Normal(f64),
+    // Glorot & Bengio: variance 2 / (fan_in + fan_out).
+    XavierNormal,
+    XavierUniform,
+    // He et al., for ReLU: variance 2 / fan_in.
+    HeNormal,
+    HeUniform,
+    // LeCun: variance 1 / fan_in. This is what the book calls Xavier.
+    LeCunNormal,
+    LeCunUniform,
+    // A (semi-)orthogonal matrix scaled by the given gain.
+    Orthogonal(f64),
+}
+
+impl Initializer {
+    pub fn initialise<R: Rng + ?Sized>(
+        &self,
+        fan_in: usize,
+        fan_out: usize,
+        rng: &mut R,
+    ) -> na::DMatrix<f64> {
+        let fan_avg = (fan_in + fan_out) as f64 / 2.0;
+        let fan_in_f = fan_in as f64;
+        match *self {
+            Initializer::Constant(value) => na::DMatrix::from_element(fan_in, fan_out, value),
+            Initializer::Normal(std) => normal(fan_in, fan_out, std, rng),
+            Initializer::XavierNormal => normal(fan_in, fan_out, (1.0 / fan_avg).sqrt(), rng),
+            Initializer::XavierUniform => uniform(fan_in, fan_out, (3.0 / fan_avg).sqrt(), rng),
+            Initializer::HeNormal => normal(fan_in, fan_out, (2.0 / fan_in_f).sqrt(), rng),
+            Initializer::HeUniform => uniform(fan_in, fan_out, (6.0 / fan_in_f).sqrt(), rng),
+            Initializer::LeCunNormal => normal(fan_in, fan_out, (1.0 / fan_in_f).sqrt(), rng),
+            Initializer::LeCunUniform => uniform(fan_in, fan_out, (3.0 / fan_in_f).sqrt(), rng),
+            Initializer::Orthogonal(gain) => orthogonal(fan_in, fan_out, rng) * gain,
+        }
+    }
+}
+
+// Accepts the names used by `MultiLayerNetExtended::new` in the book ("relu"
+// or "he", "sigmoid" or "xavier", or a standard deviation such as "0.01") as
+// well as the name of every variant in snake case, e.g. "he_uniform".
+impl FromStr for Initializer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "relu" | "he" | "he_normal" => Ok(Initializer::HeNormal),
+            "he_uniform" => Ok(Initializer::HeUniform),
+            "sigmoid" | "xavier" | "lecun" | "lecun_normal" => Ok(Initializer::LeCunNormal),
+            "lecun_uniform" => Ok(Initializer::LeCunUniform),
+            "xavier_normal" | "glorot_normal" => Ok(Initializer::XavierNormal),
+            "xavier_uniform" | "glorot_uniform" => Ok(Initializer::XavierUniform),
+            "orthogonal" => Ok(Initializer::Orthogonal(1.0)),
+            "zeros" => Ok(Initializer::Constant(0.0)),
+            "ones" => Ok(Initializer::Constant(1.0)),
+            _ => s
+                .parse::<f64>()
+                .map(Initializer::Normal)
+                .map_err(|_| format!("unknown weight initialisation {}.", s)),
+        }
+    }
+}
+
+fn normal<R: Rng + ?Sized>(rows: usize, columns: usize, std: f64, rng: &mut R) -> na::DMatrix<f64> {
+    na::DMatrix::from_fn(rows, columns, |_, _| {
+        std * rng.sample::<f64, _>(rand_distr::StandardNormal)
+    })
+}
+
+fn uniform<R: Rng + ?Sized>(
+    rows: usize,
+    columns: usize,
+    limit: f64,
+    rng: &mut R,
+) -> na::DMatrix<f64> {
+    na::DMatrix::from_fn(rows, columns, |_, _| rng.gen_range(-limit..=limit))
+}
+
+// The Q of the QR decomposition of a Gaussian matrix, with the signs fixed by
+// the diagonal of R so that the result is uniformly distributed (Saxe et al.).
+// Its columns are orthonormal when fan_in >= fan_out and its rows otherwise.
+fn orthogonal<R: Rng + ?Sized>(fan_in: usize, fan_out: usize, rng: &mut R) -> na::DMatrix<f64> {
+    let (rows, columns) = (fan_in.max(fan_out), fan_in.min(fan_out));
+    let qr = normal(rows, columns, 1.0, rng).qr();
+    let mut q = qr.q();
+    let r = qr.r();
+    for (idx, mut column) in q.column_iter_mut().enumerate() {
+        if r[(idx, idx)] < 0.0 {
+            column.neg_mut();
+        }
+    }
+    if fan_in < fan_out {
+        q.transpose()
+    } else {
+        q
+    }
+}
+
+#[test]
+fn test_initialise_scales() {
+    use rand::SeedableRng;
+
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+    let std = |m: &na::DMatrix<f64>| (m.map(|w| w * w).mean()).sqrt();
+    let (fan_in, fan_out) = (400, 200);
+    for (initializer, expected) in [
+        (Initializer::Normal(0.01), 0.01),
+        (Initializer::XavierNormal, (2.0 / 600.0f64).sqrt()),
+        (Initializer::XavierUniform, (2.0 / 600.0f64).sqrt()),
+        (Initializer::HeNormal, (2.0 / 400.0f64).sqrt()),
+        (Initializer::HeUniform, (2.0 / 400.0f64).sqrt()),
+        (Initializer::LeCunNormal, (1.0 / 400.0f64).sqrt()),
+        (Initializer::LeCunUniform, (1.0 / 400.0f64).sqrt()),
+    ] {
+        let w = initializer.initialise(fan_in, fan_out, &mut rng);
+        assert_eq!(w.shape(), (fan_in, fan_out));
+        assert!(
+            (std(&w) / expected - 1.0).abs() < 0.02,
+            "{:?} has a standard deviation of {} rather than {}",
+            initializer,
+            std(&w),
+            expected
+        );
+    }
+    let limit = (6.0 / 400.0f64).sqrt();
+    let w = Initializer::HeUniform.initialise(fan_in, fan_out, &mut rng);
+    assert!(w.iter().all(|w| w.abs() <= limit));
+    assert_eq!(
+        Initializer::Constant(0.5).initialise(2, 3, &mut rng),
+        na::DMatrix::from_element(2, 3, 0.5)
+    );
+}
+
+#[test]
+fn test_orthogonal() {
+    use rand::SeedableRng;
+
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+    for (fan_in, fan_out) in [(6, 6), (8, 3), (3, 8)] {
+        let w = Initializer::Orthogonal(2.0).initialise(fan_in, fan_out, &mut rng);
+        assert_eq!(w.shape(), (fan_in, fan_out));
+        let gram = if fan_in >= fan_out {
+            w.transpose() * &w
+        } else {
+            &w * w.transpose()
+        };
+        let n = fan_in.min(fan_out);
+        assert!((gram - na::DMatrix::<f64>::identity(n, n) * 4.0).amax() < 1e-10);
+    }
+}
+
+#[test]
+fn test_seeded_initialise_is_reproducible() {
+    use rand::SeedableRng;
+
+    let a = Initializer::HeNormal.initialise(4, 3, &mut rand_chacha::ChaCha8Rng::seed_from_u64(7));
+    let b = Initializer::HeNormal.initialise(4, 3, &mut rand_chacha::ChaCha8Rng::seed_from_u64(7));
+    assert_eq!(a, b);
+}
+
+#[test]
+fn test_from_str() {
+    assert_eq!("relu".parse(), Ok(Initializer::HeNormal));
+    assert_eq!("xavier".parse(), Ok(Initializer::LeCunNormal));
+    assert_eq!("glorot_uniform".parse(), Ok(Initializer::XavierUniform));
+    assert_eq!("0.01".parse(), Ok(Initializer::Normal(0.01)));
+    assert!("gaussian".parse::<Initializer>().is_err());
+}
@@ -1,14 +1,48 @@
-use mopa::mopafy;
+use std::any::Any;
+
+use crate::float::Float;
 
 pub mod affine_layer;
 pub mod batch_normalisation_layer;
+pub mod dropout_layer;
 pub mod relu_layer;
 pub mod sigmoid_layer;
 pub mod softmax_with_loss_layer;
 
-pub trait Layer: mopa::Any {
-    fn forwards(&mut self, x: &na::DMatrix<f64>, train_flg: bool) -> na::DMatrix<f64>;
-    fn backwards(&mut self, x: &na::DMatrix<f64>) -> na::DMatrix<f64>;
+// `Send` so that a network can be trained on another thread, see
+// `DataParallel`.
+pub trait Layer<T: Float = f64>: Send + 'static {
+    fn forwards(&mut self, x: &na::DMatrix<T>, train_flg: bool) -> na::DMatrix<T>;
+    fn backwards(&mut self, x: &na::DMatrix<T>) -> na::DMatrix<T>;
+
+    // Both return `self`, which lets a network get its layers back from
+    // `dyn Layer`.
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Float> dyn Layer<T> {
+    pub fn is<L: Layer<T>>(&self) -> bool {
+        self.as_any().is::<L>()
+    }
+
+    pub fn downcast_ref<L: Layer<T>>(&self) -> Option<&L> {
+        self.as_any().downcast_ref::<L>()
+    }
+
+    pub fn downcast_mut<L: Layer<T>>(&mut self) -> Option<&mut L> {
+        self.as_any_mut().downcast_mut::<L>()
+    }
 }
 
-mopafy!(Layer);
+#[test]
+fn test_downcast() {
+    use self::{relu_layer::Relu, sigmoid_layer::Sigmoid};
+
+    let mut layer: Box<dyn Layer<f32>> = Box::new(Relu::<f32>::new());
+    assert!(layer.is::<Relu<f32>>());
+    assert!(layer.downcast_ref::<Sigmoid<f32>>().is_none());
+    assert!(layer.downcast_mut::<Relu<f32>>().is_some());
+    let layer: Box<dyn Layer> = Box::new(Sigmoid::new());
+    assert!(layer.downcast_ref::<Sigmoid>().is_some());
+}
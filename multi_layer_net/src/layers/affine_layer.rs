@@ -1,48 +1,52 @@
-use std::{cell::RefCell, ops::Deref, rc::Rc};
+use std::any::Any;
 
 use super::Layer;
+use crate::{float::Float, shared::Shared};
 
-pub struct Affine {
-    pub w: Rc<RefCell<na::DMatrix<f64>>>,
-    pub b: Rc<RefCell<na::DVector<f64>>>,
-    x: na::DMatrix<f64>,
-    pub dw: na::DMatrix<f64>,
-    pub db: na::DVector<f64>,
+pub struct Affine<T: Float = f64> {
+    pub w: Shared<na::DMatrix<T>>,
+    pub b: Shared<na::DVector<T>>,
+    x: na::DMatrix<T>,
+    pub dw: na::DMatrix<T>,
+    pub db: na::DVector<T>,
 }
 
-impl Layer for Affine {
-    fn forwards(&mut self, x: &na::DMatrix<f64>, train_flg: bool) -> na::DMatrix<f64> {
+impl<T: Float> Layer<T> for Affine<T> {
+    fn forwards(&mut self, x: &na::DMatrix<T>, train_flg: bool) -> na::DMatrix<T> {
         self.x = x.clone();
         #[allow(non_snake_case)]
-        let B = na::DMatrix::<f64>::from_row_slice(
+        let B = na::DMatrix::<T>::from_row_slice(
             self.x.nrows(),
-            self.b.deref().borrow().nrows(),
-            self.b
-                .deref()
-                .borrow()
-                .as_slice()
-                .repeat(self.x.nrows())
-                .as_slice(),
+            self.b.borrow().nrows(),
+            self.b.borrow().as_slice().repeat(self.x.nrows()).as_slice(),
         );
-        &self.x * self.w.deref().borrow().deref() + B
+        &self.x * &*self.w.borrow() + B
     }
 
-    fn backwards(&mut self, dout: &na::DMatrix<f64>) -> na::DMatrix<f64> {
-        let dx = dout * &self.w.deref().borrow().transpose();
+    fn backwards(&mut self, dout: &na::DMatrix<T>) -> na::DMatrix<T> {
+        let dx = dout * &self.w.borrow().transpose();
         self.dw = &self.x.transpose() * dout;
-        self.db = na::DVector::<f64>::from_fn(dout.ncols(), |i, _| dout.column(i).sum());
+        self.db = na::DVector::<T>::from_fn(dout.ncols(), |i, _| dout.column(i).sum());
         dx
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
-impl Affine {
-    pub fn new(w: Rc<RefCell<na::DMatrix<f64>>>, b: Rc<RefCell<na::DVector<f64>>>) -> Self {
+impl<T: Float> Affine<T> {
+    pub fn new(w: Shared<na::DMatrix<T>>, b: Shared<na::DVector<T>>) -> Self {
         Self {
             w,
             b,
-            x: na::DMatrix::<f64>::from_element(0, 0, 0.0),
-            dw: na::DMatrix::<f64>::from_element(0, 0, 0.0),
-            db: na::DVector::<f64>::from_element(0, 0.0),
+            x: na::DMatrix::<T>::zeros(0, 0),
+            dw: na::DMatrix::<T>::zeros(0, 0),
+            db: na::DVector::<T>::zeros(0),
         }
     }
 }
@@ -52,7 +56,7 @@ fn test_affine() {
     let x = na::DMatrix::<f64>::from_element(20, 50, 0.0);
     let w = na::DMatrix::<f64>::from_element(50, 10, 0.0);
     let b = na::DVector::<f64>::from_element(10, 0.0);
-    let mut affine = Affine::new(Rc::new(RefCell::new(w)), Rc::new(RefCell::new(b)));
+    let mut affine = Affine::new(Shared::new(w), Shared::new(b));
     dbg!(affine.forwards(&x, false).shape());
     let dy = na::DMatrix::<f64>::from_element(20, 10, 0.0);
     dbg!(affine.backwards(&dy).shape());
@@ -1,51 +1,63 @@
-use std::{cell::RefCell, rc::Rc};
+use std::any::Any;
 
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
 use super::Layer;
+use crate::{float::Float, shared::Shared};
 
-pub struct BatchNormalisationLayer {
-    gamma: Rc<RefCell<na::DVector<f64>>>,
-    beta: Rc<RefCell<na::DVector<f64>>>,
-    momentum: f64,
-    running_mean: na::DVector<f64>,
-    running_var: na::DVector<f64>,
+pub struct BatchNormalisationLayer<T: Float = f64> {
+    gamma: Shared<na::DVector<T>>,
+    beta: Shared<na::DVector<T>>,
+    momentum: T,
+    running_mean: na::DVector<T>,
+    running_var: na::DVector<T>,
     batch_size: usize,
-    xc: na::DMatrix<f64>,
-    xn: na::DMatrix<f64>,
-    std: na::DVector<f64>,
-    pub dgamma: na::DVector<f64>,
-    pub dbeta: na::DVector<f64>,
+    xc: na::DMatrix<T>,
+    xn: na::DMatrix<T>,
+    std: na::DVector<T>,
+    pub dgamma: na::DVector<T>,
+    pub dbeta: na::DVector<T>,
 }
 
-impl BatchNormalisationLayer {
-    pub fn new(
-        gamma: Rc<RefCell<na::DVector<f64>>>,
-        beta: Rc<RefCell<na::DVector<f64>>>,
-        momentum: f64,
-    ) -> Self {
+impl<T: Float> BatchNormalisationLayer<T> {
+    pub fn new(gamma: Shared<na::DVector<T>>, beta: Shared<na::DVector<T>>, momentum: f64) -> Self {
         Self {
             gamma,
             beta,
-            momentum,
-            running_mean: na::DVector::<f64>::zeros(0),
-            running_var: na::DVector::<f64>::zeros(0),
+            momentum: T::of_f64(momentum),
+            running_mean: na::DVector::<T>::zeros(0),
+            running_var: na::DVector::<T>::zeros(0),
             batch_size: 0,
-            xc: na::DMatrix::<f64>::zeros(0, 0),
-            xn: na::DMatrix::<f64>::zeros(0, 0),
-            std: na::DVector::<f64>::zeros(0),
-            dgamma: na::DVector::<f64>::zeros(0),
-            dbeta: na::DVector::<f64>::zeros(0),
+            xc: na::DMatrix::<T>::zeros(0, 0),
+            xn: na::DMatrix::<T>::zeros(0, 0),
+            std: na::DVector::<T>::zeros(0),
+            dgamma: na::DVector::<T>::zeros(0),
+            dbeta: na::DVector::<T>::zeros(0),
         }
     }
+
+    pub fn running_mean(&self) -> &na::DVector<T> {
+        &self.running_mean
+    }
+
+    pub fn running_var(&self) -> &na::DVector<T> {
+        &self.running_var
+    }
+
+    pub fn set_running_stats(&mut self, mean: na::DVector<T>, var: na::DVector<T>) {
+        assert_eq!(mean.len(), var.len());
+        self.running_mean = mean;
+        self.running_var = var;
+    }
 }
 
-impl Layer for BatchNormalisationLayer {
-    fn forwards(&mut self, x: &na::DMatrix<f64>, train_flg: bool) -> na::DMatrix<f64> {
+impl<T: Float> Layer<T> for BatchNormalisationLayer<T> {
+    fn forwards(&mut self, x: &na::DMatrix<T>, train_flg: bool) -> na::DMatrix<T> {
+        let eps = T::of_f64(10e-7);
         if self.running_mean.is_empty() {
             let d = x.ncols();
-            self.running_mean = na::DVector::<f64>::zeros(d);
-            self.running_var = na::DVector::<f64>::zeros(d);
+            self.running_mean = na::DVector::<T>::zeros(d);
+            self.running_var = na::DVector::<T>::zeros(d);
         }
         let mut xc = x.clone();
         let mut xn;
@@ -59,7 +71,7 @@ impl Layer for BatchNormalisationLayer {
             }
             let var = x.row_variance_tr();
             let mut std = var.clone();
-            std.apply(|a| *a = (*a + 10e-7).sqrt());
+            std.apply(|a| *a = (*a + eps).sqrt());
             xn = xc.clone();
             for i in 0..xn.nrows() {
                 for j in 0..xn.ncols() {
@@ -71,14 +83,15 @@ impl Layer for BatchNormalisationLayer {
             self.xc = xc;
             self.xn = xn.clone();
             self.std = std;
-            self.running_mean = self.momentum * &self.running_mean + (1.0 - self.momentum) * mu;
-            self.running_var = self.momentum * &self.running_var + (1.0 - self.momentum) * var;
+            self.running_mean =
+                &self.running_mean * self.momentum + mu * (T::one() - self.momentum);
+            self.running_var = &self.running_var * self.momentum + var * (T::one() - self.momentum);
         } else {
             xc.par_column_iter_mut()
                 .zip(self.running_mean.as_slice().par_iter())
-                .for_each(|(mut col, a)| col.add_scalar_mut(-a));
+                .for_each(|(mut col, a)| col.add_scalar_mut(-*a));
             let mut std = self.running_var.clone();
-            std.apply(|a| *a = (*a + 10e-7).sqrt());
+            std.apply(|a| *a = (*a + eps).sqrt());
             xn = xc.clone();
             xn.par_column_iter_mut()
                 .zip(std.as_slice().par_iter())
@@ -89,13 +102,14 @@ impl Layer for BatchNormalisationLayer {
             .zip(self.gamma.borrow().as_slice().par_iter())
             .zip(self.beta.borrow().as_slice().par_iter())
             .for_each(|((mut col, a), b)| -> () {
-                col /= *a;
+                col *= *a;
                 col.add_scalar_mut(*b);
             });
         out
     }
 
-    fn backwards(&mut self, dout: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    fn backwards(&mut self, dout: &na::DMatrix<T>) -> na::DMatrix<T> {
+        let batch_size = T::of_f64(self.batch_size as f64);
         let dbeta = dout.row_sum_tr();
         let dgamma = (&self.xn.component_mul(dout)).row_sum_tr();
         let mut dxn = dout.clone();
@@ -119,18 +133,13 @@ impl Layer for BatchNormalisationLayer {
                 tmp[index] = tmp[index] / (self.std[j] * self.std[j]);
             }
         }
-        let dstd = tmp.row_sum_tr() * (-1.0);
-        let mut dvar = dstd.clone();
-        for i in 0..dvar.nrows() {
-            for j in 0..dvar.ncols() {
-                let index = (i, j);
-                dvar[index] = 0.5 * dstd[index] / self.std[j];
-            }
-        }
+        let dstd = -tmp.row_sum_tr();
+        // Both are column vectors with one entry per feature.
+        let dvar = (dstd * T::of_f64(0.5)).component_div(&self.std);
         for i in 0..dxc.nrows() {
             for j in 0..dxc.ncols() {
                 let index = (i, j);
-                dxc[index] += (2.0 / (self.batch_size as f64)) * self.xc[index] * dvar[j];
+                dxc[index] += T::of_f64(2.0) / batch_size * self.xc[index] * dvar[j];
             }
         }
         let dmu = dxc.row_sum_tr();
@@ -138,11 +147,32 @@ impl Layer for BatchNormalisationLayer {
         for i in 0..dxc.nrows() {
             for j in 0..dxc.ncols() {
                 let index = (i, j);
-                dx[index] = dxc[index] - dmu[j] / (self.batch_size as f64);
+                dx[index] = dxc[index] - dmu[j] / batch_size;
             }
         }
         self.dgamma = dgamma;
         self.dbeta = dbeta;
         dx
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[test]
+fn test_forwards_scales_by_gamma() {
+    let gamma = Shared::new(na::dvector![2.0, 3.0]);
+    let beta = Shared::new(na::dvector![1.0, -1.0]);
+    let mut layer = BatchNormalisationLayer::new(gamma, beta, 0.9);
+    // Both columns normalise to [-1, 1], so the output is gamma * xn + beta.
+    let x = na::dmatrix![1.0, 10.0; 3.0, 30.0];
+    let expected = na::dmatrix![-1.0, -4.0; 3.0, 2.0];
+    assert!((layer.forwards(&x, true) - &expected).amax() < 1e-5);
+    layer.set_running_stats(na::dvector![2.0, 20.0], na::dvector![1.0, 100.0]);
+    assert!((layer.forwards(&x, false) - &expected).amax() < 1e-5);
 }
@@ -0,0 +1,90 @@
+use std::{any::Any, marker::PhantomData};
+
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use super::Layer;
+use crate::float::Float;
+
+// Drops every unit with probability `dropout_ratio` while training and scales
+// the output by the keep probability at inference, as in the book. The layer
+// owns its generator so that a seeded one reproduces the same masks.
+pub struct Dropout<T: Float = f64> {
+    dropout_ratio: f64,
+    mask: na::DMatrix<bool>,
+    rng: ChaCha8Rng,
+    _scalar: PhantomData<T>,
+}
+
+impl<T: Float> Layer<T> for Dropout<T> {
+    fn forwards(&mut self, x: &na::DMatrix<T>, train_flg: bool) -> na::DMatrix<T> {
+        if train_flg {
+            self.mask = na::DMatrix::<bool>::from_fn(x.nrows(), x.ncols(), |_, _| {
+                self.rng.gen::<f64>() > self.dropout_ratio
+            });
+            x.zip_map(&self.mask, |x, keep| if keep { x } else { T::zero() })
+        } else {
+            x * T::of_f64(1.0 - self.dropout_ratio)
+        }
+    }
+
+    fn backwards(&mut self, dout: &na::DMatrix<T>) -> na::DMatrix<T> {
+        dout.zip_map(&self.mask, |dout, keep| if keep { dout } else { T::zero() })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl<T: Float> Dropout<T> {
+    pub fn new(dropout_ratio: f64, rng: ChaCha8Rng) -> Self {
+        assert!(
+            (0.0..1.0).contains(&dropout_ratio),
+            "dropout_ratio must be in [0, 1)."
+        );
+        Self {
+            dropout_ratio,
+            mask: na::DMatrix::<bool>::from_element(0, 0, false),
+            rng,
+            _scalar: PhantomData,
+        }
+    }
+
+    // The generator in its current state, e.g. to store it in a checkpoint.
+    pub fn rng(&self) -> &ChaCha8Rng {
+        &self.rng
+    }
+
+    pub fn set_rng(&mut self, rng: ChaCha8Rng) {
+        self.rng = rng;
+    }
+}
+
+#[test]
+fn test_dropout() {
+    use rand::SeedableRng;
+
+    let x = na::DMatrix::<f64>::from_element(50, 40, 2.0);
+    let mut dropout = Dropout::new(0.3, ChaCha8Rng::seed_from_u64(0));
+    let y = dropout.forwards(&x, true);
+    let dropped = y.iter().filter(|&&y| y == 0.0).count() as f64 / y.len() as f64;
+    assert!((dropped - 0.3).abs() < 0.03);
+    assert!(y.iter().all(|&y| y == 0.0 || y == 2.0));
+    let dx = dropout.backwards(&na::DMatrix::<f64>::from_element(50, 40, 1.0));
+    assert_eq!(dx.map(|d| d * 2.0), y);
+    assert_eq!(
+        dropout.forwards(&x, false),
+        na::DMatrix::<f64>::from_element(50, 40, 1.4)
+    );
+
+    // The same seed drops the same units.
+    let mut again = Dropout::new(0.3, ChaCha8Rng::seed_from_u64(0));
+    assert_eq!(again.forwards(&x, true), y);
+    let mut other = Dropout::new(0.3, ChaCha8Rng::seed_from_u64(1));
+    assert_ne!(other.forwards(&x, true), y);
+}
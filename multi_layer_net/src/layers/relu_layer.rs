@@ -1,43 +1,56 @@
+use std::{any::Any, marker::PhantomData};
+
 use super::Layer;
+use crate::float::Float;
 
-pub struct Relu {
+pub struct Relu<T: Float = f64> {
     mask: na::DMatrix<bool>,
+    _scalar: PhantomData<T>,
 }
 
-impl Layer for Relu {
-    fn forwards(&mut self, x: &na::DMatrix<f64>, train_flg: bool) -> na::DMatrix<f64> {
+impl<T: Float> Layer<T> for Relu<T> {
+    fn forwards(&mut self, x: &na::DMatrix<T>, train_flg: bool) -> na::DMatrix<T> {
         self.mask = na::DMatrix::<bool>::from_element(x.shape().0, x.shape().1, false);
         let mut output = x.clone();
         for i in 0..x.shape().0 {
             for j in 0..x.shape().1 {
                 let index = (i, j);
-                if x[index] <= 0.0 {
+                if x[index] <= T::zero() {
                     self.mask[index] = true;
-                    output[index] = 0.0;
+                    output[index] = T::zero();
                 }
             }
         }
         output
     }
 
-    fn backwards(&mut self, dout: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    fn backwards(&mut self, dout: &na::DMatrix<T>) -> na::DMatrix<T> {
         let mut dout = dout.clone();
         for i in 0..dout.shape().0 {
             for j in 0..dout.shape().1 {
                 let index = (i, j);
                 if self.mask[index] == true {
-                    dout[index] = 0.0;
+                    dout[index] = T::zero();
                 }
             }
         }
         dout
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
-impl Relu {
+impl<T: Float> Relu<T> {
     pub fn new() -> Self {
         Self {
             mask: na::DMatrix::<bool>::from_element(0, 0, false),
+            _scalar: PhantomData,
         }
     }
 }
@@ -62,3 +75,17 @@ fn test_relu() {
         na::DMatrix::<f64>::from_vec(2, 2, vec![1.0, 0.0, 0.0, 1.0])
     );
 }
+
+#[test]
+fn test_relu_f32() {
+    let mut relu_layer = Relu::<f32>::new();
+    let x = na::DMatrix::<f32>::from_vec(2, 2, vec![1.0, 0.0, -0.5, 3.0]);
+    assert_eq!(
+        relu_layer.forwards(&x, false),
+        na::DMatrix::<f32>::from_vec(2, 2, vec![1.0, 0.0, 0.0, 3.0])
+    );
+    assert_eq!(
+        relu_layer.backwards(&na::DMatrix::<f32>::from_element(2, 2, 2.0)),
+        na::DMatrix::<f32>::from_vec(2, 2, vec![2.0, 0.0, 0.0, 2.0])
+    );
+}
@@ -1,27 +1,38 @@
+use std::any::Any;
+
 use super::Layer;
+use crate::float::Float;
 
-pub struct Sigmoid {
-    out: na::DMatrix<f64>,
+pub struct Sigmoid<T: Float = f64> {
+    out: na::DMatrix<T>,
 }
 
-impl Layer for Sigmoid {
-    fn forwards(&mut self, x: &na::DMatrix<f64>, train_flg: bool) -> na::DMatrix<f64> {
+impl<T: Float> Layer<T> for Sigmoid<T> {
+    fn forwards(&mut self, x: &na::DMatrix<T>, train_flg: bool) -> na::DMatrix<T> {
         self.out = x.clone();
-        self.out.apply(|a| *a = 1.0 / (1.0 + (-*a).exp()));
+        self.out.apply(|a| *a = T::one() / (T::one() + (-*a).exp()));
         self.out.clone()
     }
 
-    fn backwards(&mut self, dout: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    fn backwards(&mut self, dout: &na::DMatrix<T>) -> na::DMatrix<T> {
         let mut tmp = self.out.clone();
-        tmp.apply(|a| *a = 1.0 - *a);
+        tmp.apply(|a| *a = T::one() - *a);
         dout.component_mul(&self.out).component_mul(&tmp)
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
-impl Sigmoid {
+impl<T: Float> Sigmoid<T> {
     pub fn new() -> Self {
         Self {
-            out: na::DMatrix::<f64>::from_element(0, 0, 0.0),
+            out: na::DMatrix::<T>::zeros(0, 0),
         }
     }
 }
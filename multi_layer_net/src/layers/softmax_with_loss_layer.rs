@@ -1,38 +1,40 @@
-pub struct SoftmaxWithLoss {
-    y: na::DMatrix<f64>,
+use crate::float::Float;
+
+pub struct SoftmaxWithLoss<T: Float = f64> {
+    y: na::DMatrix<T>,
     t: na::DMatrix<u8>,
-    loss: f64,
+    loss: T,
 }
 
-impl SoftmaxWithLoss {
+impl<T: Float> SoftmaxWithLoss<T> {
     pub fn new() -> Self {
         Self {
-            y: na::DMatrix::<f64>::from_element(0, 0, 0.0),
+            y: na::DMatrix::<T>::zeros(0, 0),
             t: na::DMatrix::<u8>::from_element(0, 0, 0),
-            loss: 0.0f64,
+            loss: T::zero(),
         }
     }
 
-    pub fn forwards(&mut self, x: &na::DMatrix<f64>, t: &na::DMatrix<u8>) -> f64 {
+    pub fn forwards(&mut self, x: &na::DMatrix<T>, t: &na::DMatrix<u8>) -> T {
         self.t = t.clone();
         self.y = Self::softmax(&x);
         self.loss = Self::cross_entropy_error(&self.y, &self.t);
         self.loss
     }
 
-    pub fn backwards(&self, dout: f64) -> na::DMatrix<f64> {
-        let batch_size = self.t.shape().0;
-        let mut tmp = &self.y - &self.t.clone().cast::<f64>();
-        tmp.apply(|a| *a = *a / batch_size as f64);
+    pub fn backwards(&self, dout: T) -> na::DMatrix<T> {
+        let batch_size = T::of_f64(self.t.shape().0 as f64);
+        let mut tmp = &self.y - &self.t.map(|t| T::of_f64(t as f64));
+        tmp.apply(|a| *a = *a / batch_size);
         tmp
     }
 
-    fn softmax(x: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    fn softmax(x: &na::DMatrix<T>) -> na::DMatrix<T> {
         let mut matrix = x.clone();
         matrix.row_iter_mut().for_each(|mut column| -> () {
             let c = column.max();
-            let exp_x = column.iter().map(|&t| (t - c).exp()).collect::<Vec<f64>>();
-            let sum = exp_x.iter().sum::<f64>();
+            let exp_x = column.iter().map(|&t| (t - c).exp()).collect::<Vec<T>>();
+            let sum = exp_x.iter().fold(T::zero(), |sum, &x| sum + x);
             column
                 .iter_mut()
                 .zip(exp_x.iter())
@@ -41,15 +43,13 @@ impl SoftmaxWithLoss {
         matrix
     }
 
-    fn cross_entropy_error(y: &na::DMatrix<f64>, t: &na::DMatrix<u8>) -> f64 {
-        let delta = 1e-7;
-        let batch_size = y.shape().0 as f64;
+    fn cross_entropy_error(y: &na::DMatrix<T>, t: &na::DMatrix<u8>) -> T {
+        let delta = T::of_f64(1e-7);
+        let batch_size = T::of_f64(y.shape().0 as f64);
         -t.iter()
             .zip(y.iter())
-            .map(|(a, b)| (b + delta).ln() * (*a as f64))
-            .collect::<Vec<f64>>()
-            .iter()
-            .sum::<f64>()
+            .map(|(a, &b)| (b + delta).ln() * T::of_f64(*a as f64))
+            .fold(T::zero(), |sum, x| sum + x)
             / batch_size
     }
 }
@@ -76,3 +76,15 @@ fn test_softmax_with_loss() {
     dbg!(net.forwards(&x, &t));
     dbg!(net.backwards(1.0f64));
 }
+
+#[test]
+fn test_softmax_with_loss_f32() {
+    let x = na::dmatrix![1.0, 2.0, 3.0; 0.0, 0.0, 0.0];
+    let t = na::dmatrix![0u8, 0, 1; 1, 0, 0];
+    let mut double = SoftmaxWithLoss::<f64>::new();
+    let mut single = SoftmaxWithLoss::<f32>::new();
+    let loss = double.forwards(&x, &t);
+    assert!((single.forwards(&x.cast::<f32>(), &t) as f64 - loss).abs() < 1e-6);
+    let dx = double.backwards(1.0);
+    assert!((single.backwards(1.0).cast::<f64>() - dx).amax() < 1e-6);
+}
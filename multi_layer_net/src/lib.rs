@@ -1,24 +1,70 @@
 use std::fmt::Debug;
 
 use na::Scalar;
-use rand::Rng;
 
 extern crate nalgebra as na;
 
+pub mod autodiff;
+pub mod callbacks;
+pub mod checkpoint;
+pub mod cross_validation;
+pub mod data_parallel;
+pub mod float;
+pub mod gradient_check;
+pub mod gradient_clipping;
 pub mod grads;
 pub mod grads_exteded;
+pub mod hyperparameter_search;
+pub mod initializer;
 pub mod layers;
+pub mod metrics;
 pub mod multi_layer_net;
 pub mod multi_layer_net_extended;
+pub mod numerical;
+pub mod numpy;
+pub mod onnx;
+pub mod optimiser;
+pub mod parameters;
 pub mod params;
 pub mod params_extended;
+pub mod pickle;
+pub mod plot;
+pub mod safetensors;
+pub mod scheduler;
+pub mod shared;
+pub mod trainer;
 
-pub(crate) fn init_matrix_with_standard_normal(row: usize, column: usize) -> na::DMatrix<f64> {
-    let mut matrix = na::DMatrix::<f64>::zeros(row, column);
-    matrix
-        .iter_mut()
-        .for_each(|t| *t = rand::thread_rng().sample(rand_distr::StandardNormal));
-    matrix
+#[cfg(test)]
+pub(crate) fn init_matrix_with_standard_normal<R: rand::Rng + ?Sized>(
+    row: usize,
+    column: usize,
+    rng: &mut R,
+) -> na::DMatrix<f64> {
+    na::DMatrix::<f64>::from_fn(row, column, |_, _| rng.sample(rand_distr::StandardNormal))
+}
+
+// A standard normal matrix which only depends on `seed`.
+#[cfg(test)]
+pub(crate) fn random_matrix(row: usize, column: usize, seed: u64) -> na::DMatrix<f64> {
+    use rand::SeedableRng;
+
+    init_matrix_with_standard_normal(
+        row,
+        column,
+        &mut rand_chacha::ChaCha8Rng::seed_from_u64(seed),
+    )
+}
+
+#[cfg(test)]
+pub(crate) fn assert_close(a: &na::DMatrix<f64>, b: &na::DMatrix<f64>, tolerance: f64) {
+    assert_eq!(a.shape(), b.shape());
+    assert!(
+        (a - b).amax() < tolerance,
+        "{} and {} differ by more than {}",
+        a,
+        b,
+        tolerance
+    );
 }
 
 pub(crate) fn broadcast_vector_rowwise<T: Scalar + Copy + Debug + rand_distr::num_traits::Zero>(
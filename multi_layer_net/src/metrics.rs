@@ -0,0 +1,331 @@
+use std::fmt;
+
+use crate::float::Float;
+
+// Predictions are rows of class scores or probabilities, one row per sample.
+// Labels are either one-hot rows or class indices.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Labels<'a> {
+    OneHot(&'a na::DMatrix<u8>),
+    Index(&'a [usize]),
+}
+
+impl<'a> Labels<'a> {
+    pub fn indices(&self) -> Vec<usize> {
+        match self {
+            Labels::OneHot(t) => argmax_rows(t),
+            Labels::Index(t) => t.to_vec(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Labels::OneHot(t) => t.nrows(),
+            Labels::Index(t) => t.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'a> From<&'a na::DMatrix<u8>> for Labels<'a> {
+    fn from(t: &'a na::DMatrix<u8>) -> Self {
+        Labels::OneHot(t)
+    }
+}
+
+impl<'a> From<&'a [usize]> for Labels<'a> {
+    fn from(t: &'a [usize]) -> Self {
+        Labels::Index(t)
+    }
+}
+
+impl<'a> From<&'a Vec<usize>> for Labels<'a> {
+    fn from(t: &'a Vec<usize>) -> Self {
+        Labels::Index(t)
+    }
+}
+
+// The index of the largest element of every row.
+pub fn argmax_rows<T>(matrix: &na::DMatrix<T>) -> Vec<usize>
+where
+    T: na::Scalar + PartialOrd,
+{
+    matrix
+        .row_iter()
+        .map(|row| row.transpose().argmax().0)
+        .collect()
+}
+
+pub fn accuracy<'a, T>(y: &na::DMatrix<T>, t: impl Into<Labels<'a>>) -> f64
+where
+    T: na::Scalar + PartialOrd,
+{
+    top_k_accuracy(y, t, 1)
+}
+
+// The fraction of samples whose label is among the `k` highest scores.
+pub fn top_k_accuracy<'a, T>(y: &na::DMatrix<T>, t: impl Into<Labels<'a>>, k: usize) -> f64
+where
+    T: na::Scalar + PartialOrd,
+{
+    let t = t.into().indices();
+    assert_eq!(y.nrows(), t.len(), "one label per prediction is needed.");
+    if t.is_empty() {
+        return 0.0;
+    }
+    let correct = y
+        .row_iter()
+        .zip(t.iter())
+        .filter(|(row, &label)| {
+            let score = &row[label];
+            // Ties are resolved in favour of the label.
+            row.iter().filter(|&other| other > score).count() < k
+        })
+        .count();
+    correct as f64 / t.len() as f64
+}
+
+// The mean negative log-probability of the labels, i.e. the cross entropy
+// error of the book with its delta of 1e-7 against log(0).
+pub fn log_loss<'a, T: Float>(y: &na::DMatrix<T>, t: impl Into<Labels<'a>>) -> f64 {
+    let t = t.into().indices();
+    assert_eq!(y.nrows(), t.len(), "one label per prediction is needed.");
+    let delta = 1e-7;
+    -t.iter()
+        .enumerate()
+        .map(|(i, &label)| (y[(i, label)].as_f64() + delta).ln())
+        .sum::<f64>()
+        / t.len().max(1) as f64
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scores {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+}
+
+// Rows are the true classes and columns the predicted ones.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfusionMatrix {
+    pub matrix: na::DMatrix<usize>,
+}
+
+impl ConfusionMatrix {
+    // The number of classes is taken from the columns of `y`.
+    pub fn new<'a, T>(y: &na::DMatrix<T>, t: impl Into<Labels<'a>>) -> Self
+    where
+        T: na::Scalar + PartialOrd,
+    {
+        Self::from_indices(&argmax_rows(y), &t.into().indices(), y.ncols())
+    }
+
+    pub fn from_indices(predicted: &[usize], actual: &[usize], num_classes: usize) -> Self {
+        assert_eq!(
+            predicted.len(),
+            actual.len(),
+            "one label per prediction is needed."
+        );
+        let mut matrix = na::DMatrix::<usize>::zeros(num_classes, num_classes);
+        for (&p, &a) in predicted.iter().zip(actual.iter()) {
+            matrix[(a, p)] += 1;
+        }
+        Self { matrix }
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.matrix.nrows()
+    }
+
+    pub fn total(&self) -> usize {
+        self.matrix.sum()
+    }
+
+    // The number of samples of `class`.
+    pub fn support(&self, class: usize) -> usize {
+        self.matrix.row(class).sum()
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        ratio(self.matrix.diagonal().sum(), self.total())
+    }
+
+    // Classes without any prediction or sample score 0, as in scikit-learn.
+    pub fn precision(&self, class: usize) -> f64 {
+        ratio(self.matrix[(class, class)], self.matrix.column(class).sum())
+    }
+
+    pub fn recall(&self, class: usize) -> f64 {
+        ratio(self.matrix[(class, class)], self.support(class))
+    }
+
+    pub fn f1(&self, class: usize) -> f64 {
+        f1(self.precision(class), self.recall(class))
+    }
+
+    pub fn scores(&self, class: usize) -> Scores {
+        Scores {
+            precision: self.precision(class),
+            recall: self.recall(class),
+            f1: self.f1(class),
+        }
+    }
+
+    // The unweighted mean of the per-class scores.
+    pub fn macro_average(&self) -> Scores {
+        let n = self.num_classes().max(1) as f64;
+        let (precision, recall, f1) = (0..self.num_classes())
+            .map(|class| self.scores(class))
+            .fold((0.0, 0.0, 0.0), |(p, r, f), s| {
+                (p + s.precision, r + s.recall, f + s.f1)
+            });
+        Scores {
+            precision: precision / n,
+            recall: recall / n,
+            f1: f1 / n,
+        }
+    }
+
+    // Scores from the counts summed over all classes. With one label per
+    // sample all three equal the accuracy.
+    pub fn micro_average(&self) -> Scores {
+        let true_positives = self.matrix.diagonal().sum();
+        let precision = ratio(true_positives, self.matrix.sum());
+        let recall = ratio(true_positives, self.total());
+        Scores {
+            precision,
+            recall,
+            f1: f1(precision, recall),
+        }
+    }
+
+    // A per-class table in the style of scikit-learn's classification_report.
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "{:>12} {:>9} {:>9} {:>9} {:>9}\n\n",
+            "", "precision", "recall", "f1-score", "support"
+        );
+        for class in 0..self.num_classes() {
+            let s = self.scores(class);
+            report += &format!(
+                "{:>12} {:>9.4} {:>9.4} {:>9.4} {:>9}\n",
+                class,
+                s.precision,
+                s.recall,
+                s.f1,
+                self.support(class)
+            );
+        }
+        report += &format!(
+            "\n{:>12} {:>9} {:>9} {:>9.4} {:>9}\n",
+            "accuracy",
+            "",
+            "",
+            self.accuracy(),
+            self.total()
+        );
+        for (name, s) in [
+            ("macro avg", self.macro_average()),
+            ("micro avg", self.micro_average()),
+        ] {
+            report += &format!(
+                "{:>12} {:>9.4} {:>9.4} {:>9.4} {:>9}\n",
+                name,
+                s.precision,
+                s.recall,
+                s.f1,
+                self.total()
+            );
+        }
+        report
+    }
+}
+
+impl fmt::Display for ConfusionMatrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.matrix.max().max(1).to_string().len().max(3);
+        write!(f, "{:>5}", "t\\y")?;
+        for class in 0..self.num_classes() {
+            write!(f, " {:>width$}", class)?;
+        }
+        writeln!(f)?;
+        for (class, row) in self.matrix.row_iter().enumerate() {
+            write!(f, "{:>5}", class)?;
+            for count in row.iter() {
+                write!(f, " {:>width$}", count)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+fn f1(precision: f64, recall: f64) -> f64 {
+    if precision + recall == 0.0 {
+        0.0
+    } else {
+        2.0 * precision * recall / (precision + recall)
+    }
+}
+
+#[test]
+fn test_accuracy() {
+    let y = na::dmatrix![0.1, 0.7, 0.2; 0.5, 0.3, 0.2; 0.2, 0.3, 0.5; 0.4, 0.35, 0.25];
+    let t = na::dmatrix![0u8, 1, 0; 0, 1, 0; 0, 0, 1; 0, 1, 0];
+    assert_eq!(accuracy(&y, &t), 0.5);
+    assert_eq!(accuracy(&y, &vec![1, 0, 2, 0]), 1.0);
+    assert_eq!(top_k_accuracy(&y, &t, 2), 1.0);
+    assert_eq!(top_k_accuracy(&y, &vec![2, 2, 0, 2], 2), 0.25);
+    assert_eq!(top_k_accuracy(&y, &t, 3), 1.0);
+}
+
+#[test]
+fn test_log_loss() {
+    let y = na::dmatrix![0.5, 0.5; 0.25, 0.75];
+    let expected = -((0.5f64 + 1e-7).ln() + (0.75f64 + 1e-7).ln()) / 2.0;
+    assert!((log_loss(&y, &vec![0, 1]) - expected).abs() < 1e-12);
+    assert!(log_loss(&na::dmatrix![1.0, 0.0], &vec![1]).is_finite());
+}
+
+#[test]
+fn test_confusion_matrix() {
+    // scikit-learn: y_true = [0, 0, 1, 1, 1, 2], y_pred = [0, 1, 1, 1, 2, 2]
+    let confusion = ConfusionMatrix::from_indices(&[0, 1, 1, 1, 2, 2], &[0, 0, 1, 1, 1, 2], 3);
+    assert_eq!(confusion.matrix, na::dmatrix![1, 1, 0; 0, 2, 1; 0, 0, 1]);
+    assert_eq!(confusion.support(1), 3);
+    assert_eq!(confusion.precision(1), 2.0 / 3.0);
+    assert_eq!(confusion.recall(0), 0.5);
+    assert_eq!(confusion.f1(2), 2.0 / 3.0);
+    let macro_average = confusion.macro_average();
+    assert!((macro_average.precision - (1.0 + 2.0 / 3.0 + 0.5) / 3.0).abs() < 1e-12);
+    assert!((macro_average.recall - (0.5 + 2.0 / 3.0 + 1.0) / 3.0).abs() < 1e-12);
+    let micro_average = confusion.micro_average();
+    assert_eq!(micro_average.f1, confusion.accuracy());
+    assert_eq!(confusion.accuracy(), 4.0 / 6.0);
+
+    let report = confusion.report();
+    assert!(report.contains("precision"));
+    assert!(report.contains("macro avg"));
+    assert_eq!(report.lines().count(), 9);
+    assert_eq!(confusion.to_string().lines().count(), 4);
+
+    let y = na::dmatrix![0.9, 0.1; 0.2, 0.8; 0.6, 0.4];
+    let t = na::dmatrix![1u8, 0; 0, 1; 0, 1];
+    let confusion = ConfusionMatrix::new(&y, &t);
+    assert_eq!(confusion.matrix, na::dmatrix![1, 0; 1, 1]);
+    // A class which is never predicted has a precision of 0.
+    let never = ConfusionMatrix::from_indices(&[0, 0], &[0, 1], 2);
+    assert_eq!(never.precision(1), 0.0);
+    assert_eq!(never.f1(1), 0.0);
+}
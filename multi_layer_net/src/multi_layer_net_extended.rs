@@ -1,31 +1,48 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    io,
+    path::Path,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
 
-use nalgebra::Scalar;
-use rand_distr::num_traits::Pow;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 use crate::{
+    checkpoint::{invalid_data, Checkpoint},
+    float::{cast_matrix, Float},
     grads_exteded::GradsExt,
-    init_matrix_with_standard_normal,
+    initializer::Initializer,
     layers::{
-        affine_layer::Affine, batch_normalisation_layer::BatchNormalisationLayer, relu_layer::Relu,
-        sigmoid_layer::Sigmoid, softmax_with_loss_layer::SoftmaxWithLoss, Layer,
+        affine_layer::Affine, batch_normalisation_layer::BatchNormalisationLayer,
+        dropout_layer::Dropout, relu_layer::Relu, sigmoid_layer::Sigmoid,
+        softmax_with_loss_layer::SoftmaxWithLoss, Layer,
     },
+    metrics,
+    onnx::{self, Attribute, Linear, Node, Tensor, ValueInfo},
+    parameters::NamedParameters,
     params_extended::ParamsExt,
+    shared::Shared,
 };
 
-pub struct MultiLayerNetExtended {
+pub struct MultiLayerNetExtended<T: Float = f64> {
     input_size: usize,
     hidden_size_list: Vec<usize>,
     output_size: usize,
     hidden_layer_num: usize,
-    pub params: Rc<RefCell<ParamsExt>>,
-    pub grads: GradsExt,
-    layers: Vec<Rc<RefCell<dyn Layer>>>,
-    last_layer: SoftmaxWithLoss,
+    pub params: Shared<ParamsExt<T>>,
+    pub grads: GradsExt<T>,
+    layers: Vec<Mutex<Box<dyn Layer<T>>>>,
+    // One per hidden layer after its activation, or none at all.
+    dropout_layers: Vec<Mutex<Dropout<T>>>,
+    last_layer: SoftmaxWithLoss<T>,
     weight_decay_lambda: f64,
+    dropout_ratio: f64,
+    activation: String,
 }
 
-impl MultiLayerNetExtended {
+impl<T: Float> MultiLayerNetExtended<T> {
+    // Draws the weights from the thread's generator. Use `new_with_rng` for a
+    // reproducible network.
     pub fn new(
         input_size: usize,
         hidden_size_list: Vec<usize>,
@@ -34,29 +51,83 @@ impl MultiLayerNetExtended {
         weight_init_std: &str,
         activation: &str,
     ) -> Self {
-        let mut layers: Vec<Rc<RefCell<dyn Layer>>> = vec![];
-        let params = Rc::new(RefCell::new(init_weight(
+        Self::new_with_rng(
             input_size,
-            &hidden_size_list,
+            hidden_size_list,
             output_size,
+            weight_decay_lambda,
             weight_init_std,
-        )));
+            activation,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    // `weight_init_std` is parsed as an `Initializer`, e.g. "he", "xavier" or
+    // "0.01", and used for every layer.
+    pub fn new_with_rng<R: Rng + ?Sized>(
+        input_size: usize,
+        hidden_size_list: Vec<usize>,
+        output_size: usize,
+        weight_decay_lambda: f64,
+        weight_init_std: &str,
+        activation: &str,
+        rng: &mut R,
+    ) -> Self {
+        let initializer = weight_init_std
+            .parse::<Initializer>()
+            .unwrap_or_else(|e| panic!("{}", e));
+        let initializers = vec![initializer; hidden_size_list.len() + 1];
+        Self::with_initializers(
+            input_size,
+            hidden_size_list,
+            output_size,
+            weight_decay_lambda,
+            &initializers,
+            activation,
+            rng,
+        )
+    }
+
+    // Draws the weights of the idx-th affine layer with `initializers[idx]`
+    // from `rng`, so that a seeded generator gives the same network each time.
+    pub fn with_initializers<R: Rng + ?Sized>(
+        input_size: usize,
+        hidden_size_list: Vec<usize>,
+        output_size: usize,
+        weight_decay_lambda: f64,
+        initializers: &[Initializer],
+        activation: &str,
+        rng: &mut R,
+    ) -> Self {
+        assert_eq!(
+            initializers.len(),
+            hidden_size_list.len() + 1,
+            "one initializer per affine layer is needed."
+        );
+        let mut layers: Vec<Mutex<Box<dyn Layer<T>>>> = vec![];
+        let params = Shared::new(init_weight(
+            input_size,
+            &hidden_size_list,
+            output_size,
+            initializers,
+            rng,
+        ));
         for idx in 0..hidden_size_list.len() {
-            layers.push(Rc::new(RefCell::new(Affine::new(
+            layers.push(boxed(Affine::new(
                 params.borrow().weight_list[idx].clone(),
                 params.borrow().bias_list[idx].clone(),
-            ))));
-            layers.push(Rc::new(RefCell::new(BatchNormalisationLayer::new(
+            )));
+            layers.push(boxed(BatchNormalisationLayer::new(
                 params.borrow().gamma_list[idx].clone(),
                 params.borrow().beta_list[idx].clone(),
                 0.9,
-            ))));
-            layers.push(activation_layer(activation));
+            )));
+            layers.push(activation_layer::<T>(activation));
         }
-        layers.push(Rc::new(RefCell::new(Affine::new(
+        layers.push(boxed(Affine::new(
             params.borrow().weight_list[hidden_size_list.len()].clone(),
             params.borrow().bias_list[hidden_size_list.len()].clone(),
-        ))));
+        )));
         let grads = GradsExt::new(params.borrow().weight_list.len());
         Self {
             input_size,
@@ -64,140 +135,681 @@ impl MultiLayerNetExtended {
             hidden_layer_num: hidden_size_list.len(),
             hidden_size_list,
             weight_decay_lambda,
+            dropout_ratio: 0.0,
+            activation: activation.to_string(),
             last_layer: SoftmaxWithLoss::new(),
             layers,
+            dropout_layers: vec![],
             params,
             grads,
         }
     }
 
-    pub fn predict(&self, x: &na::DMatrix<f64>, train_flg: bool) -> na::DMatrix<f64> {
-        let mut x = x.clone();
-        for layer in self.layers.iter() {
-            x = layer.borrow_mut().forwards(&x, train_flg);
+    // Adds dropout after every hidden activation. Each layer gets its own
+    // generator seeded from `rng`, e.g. the one passed to `new_with_rng`.
+    pub fn dropout<R: Rng + ?Sized>(mut self, dropout_ratio: f64, rng: &mut R) -> Self {
+        self.dropout_layers = (0..self.hidden_layer_num)
+            .map(|_| {
+                Mutex::new(Dropout::new(
+                    dropout_ratio,
+                    ChaCha8Rng::seed_from_u64(rng.gen()),
+                ))
+            })
+            .collect();
+        self.dropout_ratio = dropout_ratio;
+        self
+    }
+
+    pub fn to_checkpoint(&self) -> Checkpoint {
+        let mut checkpoint = Checkpoint::new();
+        checkpoint.insert_metadata("model", "MultiLayerNetExtended");
+        checkpoint.insert_metadata("input_size", self.input_size);
+        checkpoint.insert_metadata(
+            "hidden_size_list",
+            self.hidden_size_list
+                .iter()
+                .map(|size| size.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+        );
+        checkpoint.insert_metadata("output_size", self.output_size);
+        checkpoint.insert_metadata("weight_decay_lambda", self.weight_decay_lambda);
+        checkpoint.insert_metadata("activation", &self.activation);
+        // Left out without dropout so that older checkpoints still load.
+        if !self.dropout_layers.is_empty() {
+            checkpoint.insert_metadata("dropout_ratio", self.dropout_ratio);
+            for (idx, dropout) in self.dropout_layers.iter().enumerate() {
+                checkpoint.insert_rng(&format!("dropout{}.rng", idx + 1), lock(dropout).rng());
+            }
         }
-        x
+        checkpoint.insert_parameters("", &self.params);
+        for idx in 0..self.hidden_layer_num {
+            match lock(&self.layers[(idx * 3) + 1]).downcast_ref::<BatchNormalisationLayer<T>>() {
+                Some(batch_layer) => {
+                    checkpoint.tensors.insert(
+                        format!("running_mean{}", idx + 1),
+                        na::DMatrix::<f64>::from_iterator(
+                            batch_layer.running_mean().len(),
+                            1,
+                            batch_layer.running_mean().iter().map(|x| x.as_f64()),
+                        ),
+                    );
+                    checkpoint.tensors.insert(
+                        format!("running_var{}", idx + 1),
+                        na::DMatrix::<f64>::from_iterator(
+                            batch_layer.running_var().len(),
+                            1,
+                            batch_layer.running_var().iter().map(|x| x.as_f64()),
+                        ),
+                    );
+                }
+                None => panic!("downcasting could not be performed."),
+            }
+        }
+        checkpoint
     }
 
-    pub fn loss(&mut self, x: &na::DMatrix<f64>, t: &na::DMatrix<u8>, train_flg: bool) -> f64 {
-        let y = self.predict(x, train_flg);
-        let mut weight_decay = 0.0;
-        for idx in 0..=self.hidden_layer_num {
-            weight_decay += 0.5
-                * self.weight_decay_lambda
-                * self.params.borrow().weight_list[idx].borrow().norm().pow(2);
-        }
-        self.last_layer.forwards(&y, t) + weight_decay
-    }
-
-    pub fn accuracy(&self, x: &na::DMatrix<f64>, t: &na::DMatrix<u8>) -> f64 {
-        let y = self.predict(x, false);
-        fn max_index_by_column<T>(matrix: &na::DMatrix<T>) -> Vec<(usize, usize)>
-        where
-            T: Scalar + PartialOrd,
-        {
-            matrix
-                .row_iter()
-                .enumerate()
-                .map(|(i, column)| -> (usize, usize) {
-                    let tmp = column.transpose().argmax().0;
-                    (i, tmp)
+    pub fn from_checkpoint(checkpoint: &Checkpoint) -> io::Result<Self> {
+        let model: String = checkpoint.metadata("model")?;
+        if model != "MultiLayerNetExtended" {
+            return Err(invalid_data(format!(
+                "checkpoint holds a {} rather than a MultiLayerNetExtended.",
+                model
+            )));
+        }
+        let hidden_size_list: String = checkpoint.metadata("hidden_size_list")?;
+        let hidden_size_list = hidden_size_list
+            .split(',')
+            .filter(|size| !size.is_empty())
+            .map(|size| size.parse::<usize>())
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|e| invalid_data(e.to_string()))?;
+        let activation: String = checkpoint.metadata("activation")?;
+        if activation != "relu" && activation != "sigmoid" {
+            return Err(invalid_data(format!("unknown activation {}.", activation)));
+        }
+        let mut network = Self::new(
+            checkpoint.metadata("input_size")?,
+            hidden_size_list,
+            checkpoint.metadata("output_size")?,
+            checkpoint.metadata("weight_decay_lambda")?,
+            "relu",
+            &activation,
+        );
+        if checkpoint.metadata.contains_key("dropout_ratio") {
+            let dropout_ratio: f64 = checkpoint.metadata("dropout_ratio")?;
+            if !(0.0..1.0).contains(&dropout_ratio) {
+                return Err(invalid_data(format!(
+                    "dropout_ratio {} is not in [0, 1).",
+                    dropout_ratio
+                )));
+            }
+            network.dropout_layers = (0..network.hidden_layer_num)
+                .map(|idx| {
+                    let rng = checkpoint.rng(&format!("dropout{}.rng", idx + 1))?;
+                    Ok(Mutex::new(Dropout::new(dropout_ratio, rng)))
                 })
-                .collect()
+                .collect::<io::Result<_>>()?;
+            network.dropout_ratio = dropout_ratio;
         }
-        let y_max: Vec<(usize, usize)> = max_index_by_column(&y);
-        let t_max: Vec<(usize, usize)> = max_index_by_column(t);
-        let result = y_max
-            .iter()
-            .zip(t_max.iter())
-            .map(|(_y, _t)| -> i32 {
-                if _y == _t {
-                    1
-                } else {
-                    0
+        checkpoint.load_parameters("", &network.params)?;
+        for idx in 0..network.hidden_layer_num {
+            let mean = checkpoint.tensor(&format!("running_mean{}", idx + 1))?;
+            let var = checkpoint.tensor(&format!("running_var{}", idx + 1))?;
+            match lock(&network.layers[(idx * 3) + 1]).downcast_mut::<BatchNormalisationLayer<T>>()
+            {
+                Some(batch_layer) => batch_layer.set_running_stats(
+                    na::DVector::<T>::from_iterator(mean.len(), mean.iter().map(|&x| T::of_f64(x))),
+                    na::DVector::<T>::from_iterator(var.len(), var.iter().map(|&x| T::of_f64(x))),
+                ),
+                None => panic!("downcasting could not be performed."),
+            }
+        }
+        Ok(network)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        self.to_checkpoint().save(path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_checkpoint(&Checkpoint::load(path)?)
+    }
+
+    // The running mean and variance of every batch normalisation layer, which
+    // are empty until the first forward pass in training mode.
+    pub fn running_stats(&self) -> Vec<(na::DVector<T>, na::DVector<T>)> {
+        (0..self.hidden_layer_num)
+            .map(|idx| {
+                match lock(&self.layers[(idx * 3) + 1]).downcast_ref::<BatchNormalisationLayer<T>>()
+                {
+                    Some(batch_layer) => (
+                        batch_layer.running_mean().clone(),
+                        batch_layer.running_var().clone(),
+                    ),
+                    None => panic!("downcasting could not be performed."),
                 }
             })
-            .collect::<Vec<i32>>()
+            .collect()
+    }
+
+    pub fn set_running_stats(&mut self, stats: Vec<(na::DVector<T>, na::DVector<T>)>) {
+        assert_eq!(
+            stats.len(),
+            self.hidden_layer_num,
+            "one pair of running statistics per hidden layer is needed."
+        );
+        for (idx, (mean, var)) in stats.into_iter().enumerate() {
+            match lock(&self.layers[(idx * 3) + 1]).downcast_mut::<BatchNormalisationLayer<T>>() {
+                Some(batch_layer) => batch_layer.set_running_stats(mean, var),
+                None => panic!("downcasting could not be performed."),
+            }
+        }
+    }
+
+    // Copies the parameters and running statistics of a network of the same
+    // shape into this one, which keeps its own tensors.
+    pub fn copy_state_from(&mut self, other: &Self) {
+        assert!(
+            self.input_size == other.input_size
+                && self.hidden_size_list == other.hidden_size_list
+                && self.output_size == other.output_size,
+            "the networks differ in shape."
+        );
+        let params = self.params.named_parameters();
+        for ((_, param), (_, source)) in params.iter().zip(other.params.named_parameters()) {
+            source.with_view(|source| param.with_view_mut(|mut view| view.copy_from(&source)));
+        }
+        self.set_running_stats(other.running_stats());
+    }
+
+    // The generators of the dropout layers, one per hidden layer, or none
+    // without dropout.
+    pub(crate) fn dropout_rngs(&self) -> Vec<ChaCha8Rng> {
+        self.dropout_layers
             .iter()
-            .sum::<i32>();
-        (result as f64 / y.shape().0 as f64).into()
+            .map(|dropout| lock(dropout).rng().clone())
+            .collect()
+    }
+
+    pub(crate) fn set_dropout_rngs(&mut self, rngs: Vec<ChaCha8Rng>) {
+        assert_eq!(
+            rngs.len(),
+            self.dropout_layers.len(),
+            "one generator per dropout layer is needed."
+        );
+        for (dropout, rng) in self.dropout_layers.iter().zip(rngs) {
+            lock(dropout).set_rng(rng);
+        }
     }
 
-    pub fn gradient(&mut self, x: &na::DMatrix<f64>, t: &na::DMatrix<u8>) {
+    // Exports the inference graph: every hidden layer becomes an affine
+    // layer, BatchNormalization over the running statistics and the
+    // activation, and the output layer is followed by Softmax so that the
+    // model returns probabilities.
+    pub fn to_onnx(&self, linear: Linear) -> onnx::Model {
+        let params = self.params.borrow();
+        let mut graph = onnx::Graph {
+            name: "MultiLayerNetExtended".to_string(),
+            inputs: vec![ValueInfo {
+                name: "input".to_string(),
+                dims: vec![None, Some(self.input_size)],
+            }],
+            outputs: vec![ValueInfo {
+                name: "output".to_string(),
+                dims: vec![None, Some(self.output_size)],
+            }],
+            ..Default::default()
+        };
+        let mut x = "input".to_string();
+        for idx in 0..=self.hidden_layer_num {
+            let n = idx + 1;
+            let (w, b, affine) = (format!("W{}", n), format!("b{}", n), format!("affine{}", n));
+            graph
+                .initializers
+                .push(Tensor::from_matrix(&w, &params.weight_list[idx].borrow()));
+            graph
+                .initializers
+                .push(Tensor::from_vector(&b, &params.bias_list[idx].borrow()));
+            match linear {
+                Linear::Gemm => {
+                    graph.nodes.push(Node::new(
+                        "Gemm",
+                        &format!("Gemm{}", n),
+                        &[&x, &w, &b],
+                        &[&affine],
+                    ));
+                }
+                Linear::MatMulAdd => {
+                    let matmul = format!("matmul{}", n);
+                    graph.nodes.push(Node::new(
+                        "MatMul",
+                        &format!("MatMul{}", n),
+                        &[&x, &w],
+                        &[&matmul],
+                    ));
+                    graph.nodes.push(Node::new(
+                        "Add",
+                        &format!("Add{}", n),
+                        &[&matmul, &b],
+                        &[&affine],
+                    ));
+                }
+            }
+            x = affine;
+            if idx == self.hidden_layer_num {
+                break;
+            }
+            let names =
+                ["gamma", "beta", "running_mean", "running_var"].map(|s| format!("{}{}", s, n));
+            match lock(&self.layers[(idx * 3) + 1]).downcast_ref::<BatchNormalisationLayer<T>>() {
+                Some(batch_layer) => {
+                    for (name, vector) in names.iter().zip([
+                        &*params.gamma_list[idx].borrow(),
+                        &*params.beta_list[idx].borrow(),
+                        batch_layer.running_mean(),
+                        batch_layer.running_var(),
+                    ]) {
+                        graph.initializers.push(Tensor::from_vector(name, vector));
+                    }
+                }
+                None => panic!("downcasting could not be performed."),
+            }
+            let batch_norm = format!("batch_norm{}", n);
+            graph.nodes.push(
+                Node::new(
+                    "BatchNormalization",
+                    &format!("BatchNormalization{}", n),
+                    &[&x, &names[0], &names[1], &names[2], &names[3]],
+                    &[&batch_norm],
+                )
+                .with_attribute(Attribute::Float("epsilon".to_string(), 10e-7)),
+            );
+            let op_type = if self.activation == "relu" {
+                "Relu"
+            } else {
+                "Sigmoid"
+            };
+            let activation = format!("activation{}", n);
+            graph.nodes.push(Node::new(
+                op_type,
+                &format!("{}{}", op_type, n),
+                &[&batch_norm],
+                &[&activation],
+            ));
+            x = activation;
+            // ONNX's Dropout is the identity at inference, whereas this one
+            // scales by the keep probability.
+            if !self.dropout_layers.is_empty() {
+                let (keep, dropout) = (format!("keep{}", n), format!("dropout{}", n));
+                graph.initializers.push(Tensor::from_vector(
+                    &keep,
+                    &na::DVector::<f64>::from_element(
+                        self.hidden_size_list[idx],
+                        1.0 - self.dropout_ratio,
+                    ),
+                ));
+                graph.nodes.push(Node::new(
+                    "Mul",
+                    &format!("Mul{}", n),
+                    &[&x, &keep],
+                    &[&dropout],
+                ));
+                x = dropout;
+            }
+        }
+        graph.nodes.push(
+            Node::new("Softmax", "Softmax", &[&x], &["output"])
+                .with_attribute(Attribute::Int("axis".to_string(), -1)),
+        );
+        onnx::Model::new("multi_layer_net", graph)
+    }
+
+    pub fn save_onnx(&self, path: &Path, linear: Linear) -> io::Result<()> {
+        self.to_onnx(linear).save(path)
+    }
+
+    pub fn predict(&self, x: &na::DMatrix<T>, train_flg: bool) -> na::DMatrix<T> {
+        let mut x = x.clone();
+        for (idx, layer) in self.layers.iter().enumerate() {
+            x = lock(layer).forwards(&x, train_flg);
+            if let Some(dropout) = self.dropout_after(idx) {
+                x = lock(dropout).forwards(&x, train_flg);
+            }
+        }
+        x
+    }
+
+    // The output of every hidden activation layer for `x`. Batch normalisation
+    // can be skipped to see the effect of the weight initialisation alone.
+    pub fn hidden_activations(
+        &self,
+        x: &na::DMatrix<T>,
+        train_flg: bool,
+        use_batch_norm: bool,
+    ) -> Vec<na::DMatrix<T>> {
+        let mut x = x.clone();
+        let mut activations = vec![];
+        for (idx, layer) in self.layers[..self.hidden_layer_num * 3].iter().enumerate() {
+            if idx % 3 == 1 && !use_batch_norm {
+                continue;
+            }
+            x = lock(layer).forwards(&x, train_flg);
+            if idx % 3 == 2 {
+                activations.push(x.clone());
+            }
+            if let Some(dropout) = self.dropout_after(idx) {
+                x = lock(dropout).forwards(&x, train_flg);
+            }
+        }
+        activations
+    }
+
+    // The loss is accumulated in `T` but returned as `f64` like every other
+    // metric.
+    pub fn loss(&mut self, x: &na::DMatrix<T>, t: &na::DMatrix<u8>, train_flg: bool) -> f64 {
+        let y = self.predict(x, train_flg);
+        let mut weight_decay = T::zero();
+        for idx in 0..=self.hidden_layer_num {
+            weight_decay += T::of_f64(0.5 * self.weight_decay_lambda)
+                * self.params.borrow().weight_list[idx]
+                    .borrow()
+                    .norm_squared();
+        }
+        (self.last_layer.forwards(&y, t) + weight_decay).as_f64()
+    }
+
+    pub fn accuracy(&self, x: &na::DMatrix<T>, t: &na::DMatrix<u8>) -> f64 {
+        metrics::accuracy(&self.predict(x, false), t)
+    }
+
+    pub fn gradient(&mut self, x: &na::DMatrix<T>, t: &na::DMatrix<u8>) {
         self.loss(x, t, true);
-        let mut dout = self.last_layer.backwards(1.0);
-        for layer in self.layers.iter().rev() {
-            dout = layer.borrow_mut().backwards(&dout);
+        let mut dout = self.last_layer.backwards(T::one());
+        for (idx, layer) in self.layers.iter().enumerate().rev() {
+            if let Some(dropout) = self.dropout_after(idx) {
+                dout = lock(dropout).backwards(&dout);
+            }
+            dout = lock(layer).backwards(&dout);
         }
         for idx in 0..=self.hidden_layer_num {
-            match self.layers[idx * 3].borrow().downcast_ref::<Affine>() {
+            match lock(&self.layers[idx * 3]).downcast_ref::<Affine<T>>() {
                 Some(affine) => {
-                    self.grads.d_weight_list[idx] = Rc::new(RefCell::new(
-                        &affine.dw + &*affine.w.borrow() * self.weight_decay_lambda,
-                    ));
-                    self.grads.d_bias_list[idx] = Rc::new(RefCell::new(affine.db.clone()));
+                    self.grads.d_weight_list[idx] = Shared::new(
+                        &affine.dw + &*affine.w.borrow() * T::of_f64(self.weight_decay_lambda),
+                    );
+                    self.grads.d_bias_list[idx] = Shared::new(affine.db.clone());
                 }
                 None => panic!("downcasting could not be performed."),
             }
             if idx != self.hidden_layer_num {
-                match self.layers[(idx * 3) + 1]
-                    .borrow()
-                    .downcast_ref::<BatchNormalisationLayer>()
+                match lock(&self.layers[(idx * 3) + 1]).downcast_ref::<BatchNormalisationLayer<T>>()
                 {
                     Some(batch_layer) => {
-                        self.grads.d_gamma_list[idx] =
-                            Rc::new(RefCell::new(batch_layer.dgamma.clone()));
-                        self.grads.d_beta_list[idx] =
-                            Rc::new(RefCell::new(batch_layer.dbeta.clone()));
+                        self.grads.d_gamma_list[idx] = Shared::new(batch_layer.dgamma.clone());
+                        self.grads.d_beta_list[idx] = Shared::new(batch_layer.dbeta.clone());
                     }
                     None => panic!("downcasting could not be performed."),
                 }
             }
         }
     }
+
+    // The dropout layer following `self.layers[idx]`, if any.
+    fn dropout_after(&self, idx: usize) -> Option<&Mutex<Dropout<T>>> {
+        if idx % 3 == 2 {
+            self.dropout_layers.get(idx / 3)
+        } else {
+            None
+        }
+    }
 }
 
-fn activation_layer(activation_layer: &str) -> Rc<RefCell<dyn Layer>> {
+fn activation_layer<T: Float>(activation_layer: &str) -> Mutex<Box<dyn Layer<T>>> {
     if activation_layer == "relu" {
-        Rc::new(RefCell::new(Relu::new()))
+        boxed(Relu::new())
     } else if activation_layer == "sigmoid" {
-        Rc::new(RefCell::new(Sigmoid::new()))
+        boxed(Sigmoid::new())
     } else {
         panic!("Unknown layer name was given.");
     }
 }
 
-fn init_weight(
+// Layers are behind a `Mutex` rather than a `RefCell` so that the network is
+// `Sync`. Poisoning is ignored as in `Shared`.
+fn boxed<T: Float>(layer: impl Layer<T>) -> Mutex<Box<dyn Layer<T>>> {
+    Mutex::new(Box::new(layer))
+}
+
+fn lock<L: ?Sized>(layer: &Mutex<L>) -> MutexGuard<'_, L> {
+    layer.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn init_weight<T: Float, R: Rng + ?Sized>(
     input_size: usize,
     hidden_size_list: &Vec<usize>,
     output_size: usize,
-    weight_init_std: &str,
-) -> ParamsExt {
+    initializers: &[Initializer],
+    rng: &mut R,
+) -> ParamsExt<T> {
     let mut all_size_list: Vec<usize> = vec![];
     all_size_list.push(input_size);
     all_size_list.extend(hidden_size_list);
     all_size_list.push(output_size);
     let mut params = ParamsExt::new(all_size_list.len());
     for idx in 0..all_size_list.len() - 1 {
-        let mut scale = 0.05;
-        if weight_init_std == "relu" || weight_init_std == "he" {
-            scale = (2.0 / all_size_list[idx] as f64).sqrt();
-        } else if weight_init_std == "sigmoid" || weight_init_std == "xavier" {
-            scale = (1.0 / all_size_list[idx] as f64).sqrt();
-        }
-        params.weight_list[idx] = Rc::new(RefCell::new(
-            scale * init_matrix_with_standard_normal(all_size_list[idx], all_size_list[idx + 1]),
-        ));
-        params.bias_list[idx] = Rc::new(RefCell::new(na::DVector::<f64>::zeros(
+        // Drawn in f64 so that a seed gives the same network in any precision.
+        params.weight_list[idx] = Shared::new(cast_matrix(&initializers[idx].initialise(
+            all_size_list[idx],
             all_size_list[idx + 1],
+            rng,
         )));
-        params.gamma_list[idx] = Rc::new(RefCell::new(na::DVector::<f64>::from_element(
+        params.bias_list[idx] = Shared::new(na::DVector::<T>::zeros(all_size_list[idx + 1]));
+        params.gamma_list[idx] = Shared::new(na::DVector::<T>::from_element(
             all_size_list[idx + 1],
-            1.0,
-        )));
-        params.beta_list[idx] = Rc::new(RefCell::new(na::DVector::<f64>::zeros(
-            all_size_list[idx + 1],
-        )));
+            T::one(),
+        ));
+        params.beta_list[idx] = Shared::new(na::DVector::<T>::zeros(all_size_list[idx + 1]));
     }
     params
 }
+
+#[test]
+fn test_checkpoint_round_trip() {
+    let mut rng = <rand_chacha::ChaCha8Rng as rand::SeedableRng>::seed_from_u64(0);
+    let mut network =
+        MultiLayerNetExtended::new_with_rng(6, vec![5, 4], 3, 0.1, "he", "relu", &mut rng);
+    let x = crate::init_matrix_with_standard_normal(8, 6, &mut rng);
+    let t = crate::trainer::one_hot_targets(8, 3);
+    network.gradient(&x, &t);
+    let path = std::env::temp_dir().join("multi_layer_net_extended_checkpoint.bin");
+    network.save(&path).unwrap();
+    let loaded = MultiLayerNetExtended::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.hidden_size_list, vec![5, 4]);
+    assert_eq!(loaded.to_checkpoint(), network.to_checkpoint());
+    assert_eq!(loaded.predict(&x, false), network.predict(&x, false));
+}
+
+#[test]
+fn test_checkpoint_f32() {
+    let mut rng = <rand_chacha::ChaCha8Rng as rand::SeedableRng>::seed_from_u64(0);
+    let mut network =
+        MultiLayerNetExtended::<f32>::new_with_rng(6, vec![5], 3, 0.1, "he", "relu", &mut rng);
+    let x = cast_matrix::<f64, f32>(&crate::init_matrix_with_standard_normal(8, 6, &mut rng));
+    let t = crate::trainer::one_hot_targets(8, 3);
+    network.gradient(&x, &t);
+    // Checkpoints hold f64, so they can be loaded in either precision.
+    let checkpoint = network.to_checkpoint();
+    let single = MultiLayerNetExtended::<f32>::from_checkpoint(&checkpoint).unwrap();
+    assert_eq!(single.predict(&x, false), network.predict(&x, false));
+    let double = MultiLayerNetExtended::<f64>::from_checkpoint(&checkpoint).unwrap();
+    let y = double.predict(&cast_matrix(&x), false);
+    assert!((y - cast_matrix::<f32, f64>(&network.predict(&x, false))).amax() < 1e-5);
+}
+
+#[test]
+fn test_onnx_export() {
+    let mut rng = <rand_chacha::ChaCha8Rng as rand::SeedableRng>::seed_from_u64(0);
+    let mut network =
+        MultiLayerNetExtended::new_with_rng(6, vec![5, 4], 3, 0.1, "he", "sigmoid", &mut rng);
+    let x = crate::init_matrix_with_standard_normal(8, 6, &mut rng);
+    let t = crate::trainer::one_hot_targets(8, 3);
+    network.gradient(&x, &t);
+    network.params.borrow().gamma_list[0].borrow_mut().fill(2.0);
+    let mut expected = network.predict(&x, false);
+    expected.row_iter_mut().for_each(|mut row| {
+        let c = row.max();
+        row.apply(|a| *a = (*a - c).exp());
+        let sum = row.sum();
+        row /= sum;
+    });
+    for (linear, op_types) in [
+        (
+            Linear::Gemm,
+            vec![
+                "Gemm",
+                "BatchNormalization",
+                "Sigmoid",
+                "Gemm",
+                "BatchNormalization",
+                "Sigmoid",
+                "Gemm",
+                "Softmax",
+            ],
+        ),
+        (
+            Linear::MatMulAdd,
+            vec![
+                "MatMul",
+                "Add",
+                "BatchNormalization",
+                "Sigmoid",
+                "MatMul",
+                "Add",
+                "BatchNormalization",
+                "Sigmoid",
+                "MatMul",
+                "Add",
+                "Softmax",
+            ],
+        ),
+    ] {
+        let model = network.to_onnx(linear);
+        let decoded = onnx::Model::decode(&model.encode()).unwrap();
+        assert_eq!(decoded, model);
+        let graph = &decoded.graph;
+        assert_eq!(
+            graph
+                .nodes
+                .iter()
+                .map(|node| node.op_type.as_str())
+                .collect::<Vec<&str>>(),
+            op_types
+        );
+        assert_eq!(graph.inputs[0].dims, vec![None, Some(6)]);
+        assert_eq!(graph.outputs[0].dims, vec![None, Some(3)]);
+        let initializers = graph
+            .initializers
+            .iter()
+            .map(|tensor| (tensor.name.as_str(), tensor.dims.clone()))
+            .collect::<Vec<(&str, Vec<usize>)>>();
+        assert_eq!(initializers.len(), 6 + 2 * 4);
+        assert!(initializers.contains(&("W1", vec![6, 5])));
+        assert!(initializers.contains(&("running_var2", vec![4])));
+        assert!(initializers.contains(&("b3", vec![3])));
+        let y = onnx::run(graph, &x);
+        assert!((y - &expected).amax() < 1e-5);
+    }
+}
+
+#[test]
+fn test_dropout() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    let build = |seed| {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        MultiLayerNetExtended::new_with_rng(6, vec![5, 4], 3, 0.0, "he", "relu", &mut rng)
+            .dropout(0.5, &mut rng)
+    };
+    let network = build(0);
+    let x = crate::init_matrix_with_standard_normal(8, 6, &mut ChaCha8Rng::seed_from_u64(1));
+    // The masks follow from the seed.
+    assert_eq!(network.predict(&x, true), build(0).predict(&x, true));
+    assert_ne!(network.predict(&x, true), network.predict(&x, true));
+    let plain = MultiLayerNetExtended::new_with_rng(
+        6,
+        vec![5, 4],
+        3,
+        0.0,
+        "he",
+        "relu",
+        &mut ChaCha8Rng::seed_from_u64(0),
+    );
+    assert_ne!(network.predict(&x, false), plain.predict(&x, false));
+
+    // A checkpoint carries on with the same masks.
+    let checkpoint = network.to_checkpoint();
+    let loaded = MultiLayerNetExtended::<f64>::from_checkpoint(&checkpoint).unwrap();
+    assert_eq!(loaded.to_checkpoint(), checkpoint);
+    assert_eq!(loaded.predict(&x, true), network.predict(&x, true));
+    assert!(!plain.to_checkpoint().metadata.contains_key("dropout_ratio"));
+
+    let graph = network.to_onnx(Linear::Gemm).graph;
+    let mut expected = network.predict(&x, false);
+    expected.row_iter_mut().for_each(|mut row| {
+        let c = row.max();
+        row.apply(|a| *a = (*a - c).exp());
+        let sum = row.sum();
+        row /= sum;
+    });
+    assert!((onnx::run(&graph, &x) - expected).amax() < 1e-5);
+}
+
+#[test]
+fn test_hidden_activations() {
+    let mut rng = <rand_chacha::ChaCha8Rng as rand::SeedableRng>::seed_from_u64(0);
+    let network =
+        MultiLayerNetExtended::new_with_rng(4, vec![3, 5], 2, 0.0, "0.01", "sigmoid", &mut rng);
+    let x = crate::init_matrix_with_standard_normal(6, 4, &mut rng);
+    let activations = network.hidden_activations(&x, false, false);
+    assert_eq!(activations.len(), 2);
+    assert_eq!(activations[0].shape(), (6, 3));
+    assert_eq!(activations[1].shape(), (6, 5));
+    // Tiny weights leave every sigmoid close to 0.5.
+    assert!(activations[1].iter().all(|a| (a - 0.5).abs() < 0.01));
+    let affine = lock(&network.layers[0]).forwards(&x, false);
+    let expected = affine.map(|a| 1.0 / (1.0 + (-a).exp()));
+    assert_eq!(activations[0], expected);
+}
+
+#[test]
+fn test_with_initializers() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    let initializers = [
+        Initializer::Orthogonal(1.0),
+        Initializer::HeUniform,
+        Initializer::Constant(0.0),
+    ];
+    let build = |seed| {
+        MultiLayerNetExtended::with_initializers(
+            5,
+            vec![4, 3],
+            2,
+            0.0,
+            &initializers,
+            "relu",
+            &mut ChaCha8Rng::seed_from_u64(seed),
+        )
+    };
+    let network = build(1);
+    assert_eq!(network.to_checkpoint(), build(1).to_checkpoint());
+    assert_ne!(network.to_checkpoint(), build(2).to_checkpoint());
+    let params = network.params.borrow();
+    let w1 = params.weight_list[0].borrow();
+    assert!((w1.transpose() * &*w1 - na::DMatrix::<f64>::identity(4, 4)).amax() < 1e-10);
+    assert!(params.weight_list[2].borrow().iter().all(|&w| w == 0.0));
+}
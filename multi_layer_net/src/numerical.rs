@@ -0,0 +1,254 @@
+use rayon::prelude::*;
+
+#[cfg(test)]
+use crate::assert_close;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    // (f(x + h) - f(x - h)) / 2h, with an error of order h².
+    Central,
+    // (f(x + h) - f(x)) / h, with an error of order h but one evaluation of
+    // `f` fewer per element.
+    Forward,
+}
+
+// Numerical derivatives of closures, so that unlike chapter 4's functions
+// they can capture a model and its data. Every element of a gradient, row of
+// a Jacobian or row of a Hessian is computed on its own copy of `x` with
+// rayon, which is why the closures have to be `Sync`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NumericalDiff {
+    h: f64,
+    scheme: Scheme,
+}
+
+impl Default for NumericalDiff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NumericalDiff {
+    // The book's h = 1e-4 with central differences.
+    pub fn new() -> Self {
+        Self {
+            h: 1e-4,
+            scheme: Scheme::Central,
+        }
+    }
+
+    pub fn step(mut self, h: f64) -> Self {
+        assert!(h > 0.0, "the step must be positive.");
+        self.h = h;
+        self
+    }
+
+    pub fn scheme(mut self, scheme: Scheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    pub fn derivative(&self, f: impl Fn(f64) -> f64, x: f64) -> f64 {
+        match self.scheme {
+            Scheme::Central => (f(x + self.h) - f(x - self.h)) / (2.0 * self.h),
+            Scheme::Forward => (f(x + self.h) - f(x)) / self.h,
+        }
+    }
+
+    // The gradient of a scalar function of a matrix, in the shape of `x`.
+    pub fn gradient<F>(&self, f: F, x: &na::DMatrix<f64>) -> na::DMatrix<f64>
+    where
+        F: Fn(&na::DMatrix<f64>) -> f64 + Sync,
+    {
+        let fx = match self.scheme {
+            Scheme::Central => 0.0,
+            Scheme::Forward => f(x),
+        };
+        let grad: Vec<f64> = (0..x.len())
+            .into_par_iter()
+            .map_init(|| x.clone(), |x, idx| self.partial(&f, x, idx, fx))
+            .collect();
+        na::DMatrix::<f64>::from_vec(x.nrows(), x.ncols(), grad)
+    }
+
+    // Row i holds the derivatives of the i-th element of f(x) and column j
+    // those with respect to the j-th element of `x`, both in column-major
+    // order.
+    pub fn jacobian<F>(&self, f: F, x: &na::DMatrix<f64>) -> na::DMatrix<f64>
+    where
+        F: Fn(&na::DMatrix<f64>) -> na::DMatrix<f64> + Sync,
+    {
+        let fx = f(x);
+        let columns: Vec<na::DMatrix<f64>> = (0..x.len())
+            .into_par_iter()
+            .map_init(
+                || x.clone(),
+                |x, idx| {
+                    let tmp_val = x[idx];
+                    x[idx] = tmp_val + self.h;
+                    let fxh1 = f(x);
+                    let column = match self.scheme {
+                        Scheme::Central => {
+                            x[idx] = tmp_val - self.h;
+                            (fxh1 - f(x)) / (2.0 * self.h)
+                        }
+                        Scheme::Forward => (fxh1 - &fx) / self.h,
+                    };
+                    x[idx] = tmp_val;
+                    column
+                },
+            )
+            .collect();
+        na::DMatrix::<f64>::from_fn(fx.len(), x.len(), |i, j| columns[j][i])
+    }
+
+    // The matrix of second derivatives of a scalar function, indexed like
+    // the columns of `jacobian`. Only the upper triangle is evaluated and
+    // mirrored, so the result is exactly symmetric.
+    pub fn hessian<F>(&self, f: F, x: &na::DMatrix<f64>) -> na::DMatrix<f64>
+    where
+        F: Fn(&na::DMatrix<f64>) -> f64 + Sync,
+    {
+        let n = x.len();
+        let h = self.h;
+        let fx = f(x);
+        let rows: Vec<Vec<f64>> = (0..n)
+            .into_par_iter()
+            .map_init(
+                || x.clone(),
+                |x, i| {
+                    (i..n)
+                        .map(|j| {
+                            let mut f_at = |di: f64, dj: f64| {
+                                let (xi, xj) = (x[i], x[j]);
+                                x[i] += di;
+                                x[j] += dj;
+                                let value = f(x);
+                                x[i] = xi;
+                                x[j] = xj;
+                                value
+                            };
+                            match self.scheme {
+                                Scheme::Central => {
+                                    (f_at(h, h) - f_at(h, -h) - f_at(-h, h) + f_at(-h, -h))
+                                        / (4.0 * h * h)
+                                }
+                                Scheme::Forward => {
+                                    (f_at(h, h) - f_at(h, 0.0) - f_at(0.0, h) + fx) / (h * h)
+                                }
+                            }
+                        })
+                        .collect()
+                },
+            )
+            .collect();
+        let mut hessian = na::DMatrix::<f64>::zeros(n, n);
+        for (i, row) in rows.iter().enumerate() {
+            for (offset, &value) in row.iter().enumerate() {
+                hessian[(i, i + offset)] = value;
+                hessian[(i + offset, i)] = value;
+            }
+        }
+        hessian
+    }
+
+    // Chapter 4's gradient descent: `step_num` steps of x -= lr * grad f(x).
+    pub fn gradient_descent<F>(
+        &self,
+        f: F,
+        init_x: &na::DMatrix<f64>,
+        lr: f64,
+        step_num: usize,
+    ) -> na::DMatrix<f64>
+    where
+        F: Fn(&na::DMatrix<f64>) -> f64 + Sync,
+    {
+        let mut x = init_x.clone();
+        for _ in 0..step_num {
+            x -= self.gradient(&f, &x) * lr;
+        }
+        x
+    }
+
+    // The derivative with respect to x[idx], leaving `x` as it was. `fx` is
+    // f(x), which only the forward scheme uses.
+    fn partial<F>(&self, f: &F, x: &mut na::DMatrix<f64>, idx: usize, fx: f64) -> f64
+    where
+        F: Fn(&na::DMatrix<f64>) -> f64,
+    {
+        let tmp_val = x[idx];
+        x[idx] = tmp_val + self.h;
+        let fxh1 = f(x);
+        let partial = match self.scheme {
+            Scheme::Central => {
+                x[idx] = tmp_val - self.h;
+                (fxh1 - f(x)) / (2.0 * self.h)
+            }
+            Scheme::Forward => (fxh1 - fx) / self.h,
+        };
+        x[idx] = tmp_val;
+        partial
+    }
+}
+
+#[test]
+fn test_derivative() {
+    // The book's function_1, whose derivative at 5 is 0.2.
+    let f = |x: f64| 0.01 * x.powi(2) + 0.1 * x;
+    assert!((NumericalDiff::new().derivative(f, 5.0) - 0.2).abs() < 1e-10);
+    let forward = NumericalDiff::new().scheme(Scheme::Forward);
+    assert!((forward.derivative(f, 5.0) - 0.2).abs() < 1e-5);
+    assert!((forward.derivative(f, 5.0) - 0.2).abs() > 1e-7);
+}
+
+#[test]
+fn test_gradient() {
+    // A closure capturing `a`, which chapter 4's `fn` pointers cannot do.
+    let a = na::dmatrix![1.0, 2.0; 3.0, 4.0];
+    let f = |x: &na::DMatrix<f64>| x.component_mul(&a).sum() + x.norm_squared();
+    let x = na::dmatrix![3.0, -4.0; 0.5, 2.0];
+    let expected = &a + &x * 2.0;
+    assert_close(&NumericalDiff::new().gradient(f, &x), &expected, 1e-8);
+    let forward = NumericalDiff::new().step(1e-6).scheme(Scheme::Forward);
+    assert_close(&forward.gradient(f, &x), &expected, 1e-4);
+
+    // Large enough to be split across threads.
+    let x = na::DMatrix::<f64>::from_fn(40, 30, |i, j| (i as f64 - j as f64) / 10.0);
+    let grad = NumericalDiff::new().gradient(|x| x.map(f64::sin).sum(), &x);
+    assert_close(&grad, &x.map(f64::cos), 1e-8);
+}
+
+#[test]
+fn test_jacobian() {
+    // f(x) = (x0 * x1, sin(x0), x1²) for x = [x0; x1].
+    let f = |x: &na::DMatrix<f64>| na::dmatrix![x[0] * x[1]; x[0].sin(); x[1] * x[1]];
+    let x = na::dmatrix![0.5; 2.0];
+    let expected = na::dmatrix![2.0, 0.5; 0.5f64.cos(), 0.0; 0.0, 4.0];
+    assert_close(&NumericalDiff::new().jacobian(f, &x), &expected, 1e-8);
+    let forward = NumericalDiff::new().step(1e-7).scheme(Scheme::Forward);
+    assert_close(&forward.jacobian(f, &x), &expected, 1e-5);
+}
+
+#[test]
+fn test_hessian() {
+    // f(x) = x0² x1 + 3 x1 x2 + x2³.
+    let f = |x: &na::DMatrix<f64>| x[0] * x[0] * x[1] + 3.0 * x[1] * x[2] + x[2].powi(3);
+    let x = na::dmatrix![1.0, 2.0, -1.0];
+    let expected = na::dmatrix![
+        4.0, 2.0, 0.0;
+        2.0, 0.0, 3.0;
+        0.0, 3.0, -6.0
+    ];
+    let hessian = NumericalDiff::new().hessian(f, &x);
+    assert_close(&hessian, &expected, 1e-6);
+    assert_eq!(hessian, hessian.transpose());
+    let forward = NumericalDiff::new().scheme(Scheme::Forward);
+    assert_close(&forward.hessian(f, &x), &expected, 1e-3);
+}
+
+#[test]
+fn test_gradient_descent() {
+    let f = |x: &na::DMatrix<f64>| x[0].powi(2) + x[1].powi(2);
+    let x = NumericalDiff::new().gradient_descent(f, &na::dmatrix![-3.0; 4.0], 0.1, 100);
+    assert!(x.amax() < 1e-8);
+}
@@ -0,0 +1,467 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    path::Path,
+};
+
+use crate::{
+    checkpoint::{invalid_data, read_bytes},
+    float::Float,
+    parameters::{NamedParameters, Parameter},
+};
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DType {
+    F32,
+    F64,
+}
+
+impl DType {
+    fn descr(&self) -> &str {
+        match self {
+            DType::F32 => "<f4",
+            DType::F64 => "<f8",
+        }
+    }
+
+    pub(crate) fn from_descr(descr: &str) -> io::Result<Self> {
+        match descr {
+            "<f4" => Ok(DType::F32),
+            "<f8" => Ok(DType::F64),
+            other => Err(invalid_data(format!(
+                "dtype {} is not supported; only little-endian f4 and f8 are.",
+                other
+            ))),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            DType::F32 => 4,
+            DType::F64 => 8,
+        }
+    }
+}
+
+// The size of the data of an array, which fails rather than overflows for a
+// shape read from a corrupt file.
+fn byte_len(shape: &[usize], dtype: DType) -> io::Result<usize> {
+    shape
+        .iter()
+        .try_fold(dtype.size(), |len, &n| len.checked_mul(n))
+        .ok_or_else(|| invalid_data(format!("an array of shape {:?} is too large.", shape)))
+}
+
+// An n-dimensional NumPy array with its elements kept in C (row-major) order
+// regardless of the order it was stored in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NpyArray {
+    pub shape: Vec<usize>,
+    pub data: Vec<f64>,
+}
+
+impl NpyArray {
+    // The values are kept as `f64` whatever the precision of the source or
+    // of the file.
+    pub fn from_matrix<T: Float>(matrix: &na::DMatrix<T>) -> Self {
+        Self {
+            shape: vec![matrix.nrows(), matrix.ncols()],
+            data: matrix.transpose().iter().map(|x| x.as_f64()).collect(),
+        }
+    }
+
+    pub fn from_vector<T: Float>(vector: &na::DVector<T>) -> Self {
+        Self {
+            shape: vec![vector.len()],
+            data: vector.iter().map(|x| x.as_f64()).collect(),
+        }
+    }
+
+    // 1-D arrays become a single column.
+    pub fn to_matrix<T: Float>(&self) -> io::Result<na::DMatrix<T>> {
+        let data = self.data.iter().map(|&x| T::of_f64(x));
+        match self.shape[..] {
+            [n] => Ok(na::DMatrix::<T>::from_iterator(n, 1, data)),
+            [nrows, ncols] => Ok(na::DMatrix::<T>::from_row_iterator(nrows, ncols, data)),
+            _ => Err(invalid_data(format!(
+                "an array of shape {:?} cannot be converted into a matrix.",
+                self.shape
+            ))),
+        }
+    }
+
+    // Any array with at most one dimension longer than 1 is accepted, so that
+    // (n,), (n, 1) and (1, n) all give a vector of length n.
+    pub fn to_vector<T: Float>(&self) -> io::Result<na::DVector<T>> {
+        if self.shape.iter().filter(|&&n| n != 1).count() > 1 {
+            return Err(invalid_data(format!(
+                "an array of shape {:?} cannot be converted into a vector.",
+                self.shape
+            )));
+        }
+        Ok(na::DVector::<T>::from_iterator(
+            self.data.len(),
+            self.data.iter().map(|&x| T::of_f64(x)),
+        ))
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0u8; 6];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a .npy file.".to_string()));
+        }
+        let mut version = [0u8; 2];
+        reader.read_exact(&mut version)?;
+        let header_len = match version[0] {
+            1 => {
+                let mut buf = [0u8; 2];
+                reader.read_exact(&mut buf)?;
+                u16::from_le_bytes(buf) as usize
+            }
+            2 | 3 => {
+                let mut buf = [0u8; 4];
+                reader.read_exact(&mut buf)?;
+                u32::from_le_bytes(buf) as usize
+            }
+            major => {
+                return Err(invalid_data(format!(
+                    ".npy version {} is not supported.",
+                    major
+                )))
+            }
+        };
+        let header = read_bytes(reader, header_len as u64)?;
+        let header = String::from_utf8(header).map_err(|e| invalid_data(e.to_string()))?;
+        let descr = header_value(&header, "descr")?;
+        let dtype = DType::from_descr(descr.trim_matches(|c| c == '\'' || c == '"'))?;
+        let fortran_order = match header_value(&header, "fortran_order")? {
+            "True" => true,
+            "False" => false,
+            other => {
+                return Err(invalid_data(format!(
+                    "fortran_order has an invalid value {}.",
+                    other
+                )))
+            }
+        };
+        let shape = header_value(&header, "shape")?
+            .trim_matches(|c| c == '(' || c == ')')
+            .split(',')
+            .map(|n| n.trim())
+            .filter(|n| !n.is_empty())
+            .map(|n| n.parse::<usize>())
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|e| invalid_data(e.to_string()))?;
+        let bytes = read_bytes(reader, byte_len(&shape, dtype)? as u64)?;
+        Self::from_bytes(shape, dtype, fortran_order, &bytes)
+    }
+
+    // Decodes the raw little-endian buffer of an array, as found after a .npy
+    // header or inside a pickled `ndarray`.
+    pub(crate) fn from_bytes(
+        shape: Vec<usize>,
+        dtype: DType,
+        fortran_order: bool,
+        bytes: &[u8],
+    ) -> io::Result<Self> {
+        if bytes.len() != byte_len(&shape, dtype)? {
+            return Err(invalid_data(format!(
+                "{} bytes cannot hold an array of shape {:?}.",
+                bytes.len(),
+                shape
+            )));
+        }
+        let data = match dtype {
+            DType::F32 => bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+                .collect::<Vec<f64>>(),
+            DType::F64 => bytes
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+                .collect::<Vec<f64>>(),
+        };
+        let data = if fortran_order {
+            fortran_to_c_order(&shape, &data)
+        } else {
+            data
+        };
+        Ok(Self { shape, data })
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W, dtype: DType) -> io::Result<()> {
+        let shape = match self.shape.len() {
+            1 => format!("({},)", self.shape[0]),
+            _ => format!(
+                "({})",
+                self.shape
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        };
+        let mut header = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+            dtype.descr(),
+            shape
+        );
+        // The header is padded with spaces and ends with a newline so that
+        // the data starts on a 64-byte boundary.
+        let unpadded = MAGIC.len() + 2 + 2 + header.len() + 1;
+        header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+        header.push('\n');
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[1, 0])?;
+        writer.write_all(&(header.len() as u16).to_le_bytes())?;
+        writer.write_all(header.as_bytes())?;
+        for value in self.data.iter() {
+            match dtype {
+                DType::F32 => writer.write_all(&(*value as f32).to_le_bytes())?,
+                DType::F64 => writer.write_all(&value.to_le_bytes())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Returns the raw text of `key` in a header such as
+// "{'descr': '<f8', 'fortran_order': False, 'shape': (3, 4), }".
+fn header_value<'a>(header: &'a str, key: &str) -> io::Result<&'a str> {
+    let missing = || invalid_data(format!("{} is missing in the .npy header.", key));
+    let start = header
+        .find(&format!("'{}'", key))
+        .or_else(|| header.find(&format!("\"{}\"", key)))
+        .ok_or_else(missing)?;
+    let rest = &header[start + key.len() + 2..];
+    let rest = rest.trim_start().strip_prefix(':').ok_or_else(missing)?;
+    let rest = rest.trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')').map(|i| i + 1)
+    } else {
+        rest.find(',')
+    }
+    .ok_or_else(missing)?;
+    Ok(rest[..end].trim())
+}
+
+fn fortran_to_c_order(shape: &[usize], data: &[f64]) -> Vec<f64> {
+    let len = data.len();
+    let mut c_data = vec![0.0f64; len];
+    let mut index = vec![0usize; shape.len()];
+    for value in data.iter() {
+        let offset = index
+            .iter()
+            .zip(shape.iter())
+            .fold(0, |offset, (i, n)| offset * n + i);
+        c_data[offset] = *value;
+        // Fortran order varies the first axis fastest.
+        for (i, n) in index.iter_mut().zip(shape.iter()) {
+            *i += 1;
+            if *i < *n {
+                break;
+            }
+            *i = 0;
+        }
+    }
+    c_data
+}
+
+pub fn load_npy(path: &Path) -> io::Result<NpyArray> {
+    NpyArray::read(&mut BufReader::new(File::open(path)?))
+}
+
+pub fn save_npy(path: &Path, array: &NpyArray, dtype: DType) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    array.write(&mut writer, dtype)?;
+    writer.flush()
+}
+
+// Reads every array of an archive written by `numpy.savez` or
+// `numpy.savez_compressed`, keyed without the ".npy" extension.
+pub fn read_npz<R: Read + Seek>(reader: R) -> io::Result<BTreeMap<String, NpyArray>> {
+    let mut archive = zip::ZipArchive::new(reader).map_err(io::Error::from)?;
+    let mut arrays = BTreeMap::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(io::Error::from)?;
+        let name = file.name().trim_end_matches(".npy").to_string();
+        arrays.insert(name, NpyArray::read(&mut file)?);
+    }
+    Ok(arrays)
+}
+
+pub fn write_npz<W: Write + Seek>(
+    writer: W,
+    arrays: &BTreeMap<String, NpyArray>,
+    dtype: DType,
+) -> io::Result<()> {
+    let mut archive = zip::ZipWriter::new(writer);
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (name, array) in arrays.iter() {
+        archive
+            .start_file(format!("{}.npy", name), options)
+            .map_err(io::Error::from)?;
+        array.write(&mut archive, dtype)?;
+    }
+    archive.finish().map_err(io::Error::from)?;
+    Ok(())
+}
+
+pub fn load_npz(path: &Path) -> io::Result<BTreeMap<String, NpyArray>> {
+    read_npz(BufReader::new(File::open(path)?))
+}
+
+pub fn save_npz(path: &Path, arrays: &BTreeMap<String, NpyArray>, dtype: DType) -> io::Result<()> {
+    write_npz(File::create(path)?, arrays, dtype)
+}
+
+// Every non-empty parameter is saved under its own name ("W1", "b1", ...).
+// Vectors are saved as 1-D arrays like the book's biases.
+pub fn save_parameters_npz<T: Float>(
+    path: &Path,
+    params: &dyn NamedParameters<T>,
+    dtype: DType,
+) -> io::Result<()> {
+    let arrays = params
+        .named_parameters()
+        .into_iter()
+        .filter(|(_, param)| !param.is_empty())
+        .map(|(key, param)| {
+            let array = match param {
+                Parameter::Matrix(m) => NpyArray::from_matrix(&m.borrow()),
+                Parameter::Vector(v) => NpyArray::from_vector(&v.borrow()),
+            };
+            (key, array)
+        })
+        .collect::<BTreeMap<String, NpyArray>>();
+    save_npz(path, &arrays, dtype)
+}
+
+// Copies the arrays into the already shaped `params` in place, so a network
+// built with the same sizes picks up the loaded values.
+pub fn load_parameters_npz<T: Float>(
+    path: &Path,
+    params: &dyn NamedParameters<T>,
+) -> io::Result<()> {
+    let arrays = load_npz(path)?;
+    for (key, param) in params.named_parameters() {
+        if param.is_empty() {
+            continue;
+        }
+        let array = arrays
+            .get(&key)
+            .ok_or_else(|| invalid_data(format!("array {} is missing.", key)))?;
+        let matrix = array.to_matrix::<T>()?;
+        if matrix.shape() != param.shape() {
+            return Err(invalid_data(format!(
+                "array {} has shape {:?} but {:?} was expected.",
+                key,
+                array.shape,
+                param.shape()
+            )));
+        }
+        param.with_view_mut(|mut view| view.copy_from(&matrix));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_npy_round_trip() {
+    let matrix = na::dmatrix![1.0, 2.0, 3.0; 4.0, 5.0, 6.0];
+    let array = NpyArray::from_matrix(&matrix);
+    assert_eq!(array.data, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    for dtype in [DType::F32, DType::F64] {
+        let mut buf = vec![];
+        array.write(&mut buf, dtype).unwrap();
+        let header_len = u16::from_le_bytes([buf[8], buf[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(buf[10 + header_len - 1], b'\n');
+        let loaded = NpyArray::read(&mut buf.as_slice()).unwrap();
+        assert_eq!(loaded, array);
+        assert_eq!(loaded.to_matrix().unwrap(), matrix);
+    }
+}
+
+#[test]
+fn test_npy_reads_fortran_order() {
+    // np.asfortranarray(np.arange(6, dtype='<f8').reshape(2, 3)) saved with np.save
+    let header = "{'descr': '<f8', 'fortran_order': True, 'shape': (2, 3), }";
+    let mut buf = MAGIC.to_vec();
+    buf.extend_from_slice(&[1, 0]);
+    buf.extend_from_slice(&(header.len() as u16 + 1).to_le_bytes());
+    buf.extend_from_slice(header.as_bytes());
+    buf.push(b'\n');
+    for value in [0.0f64, 3.0, 1.0, 4.0, 2.0, 5.0] {
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+    let array = NpyArray::read(&mut buf.as_slice()).unwrap();
+    assert_eq!(array.shape, vec![2, 3]);
+    assert_eq!(array.data, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+    assert_eq!(
+        array.to_matrix().unwrap(),
+        na::dmatrix![0.0, 1.0, 2.0; 3.0, 4.0, 5.0]
+    );
+}
+
+#[test]
+fn test_npy_rejects_big_endian() {
+    let header = "{'descr': '>f8', 'fortran_order': False, 'shape': (1,), }\n";
+    let mut buf = MAGIC.to_vec();
+    buf.extend_from_slice(&[1, 0]);
+    buf.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    buf.extend_from_slice(header.as_bytes());
+    buf.extend_from_slice(&1.0f64.to_be_bytes());
+    assert!(NpyArray::read(&mut buf.as_slice()).is_err());
+}
+
+#[test]
+fn test_npy_rejects_corrupt_sizes() {
+    let npy = |version: u8, header_len: &[u8], header: &str| {
+        let mut buf = MAGIC.to_vec();
+        buf.extend_from_slice(&[version, 0]);
+        buf.extend_from_slice(header_len);
+        buf.extend_from_slice(header.as_bytes());
+        buf.extend_from_slice(&1.0f64.to_le_bytes());
+        buf
+    };
+    let shape = |shape: &str| {
+        format!(
+            "{{'descr': '<f8', 'fortran_order': False, 'shape': {}, }}\n",
+            shape
+        )
+    };
+    for header in [shape("(4294967296, 4294967296)"), shape("(1000000000,)")] {
+        let buf = npy(1, &(header.len() as u16).to_le_bytes(), &header);
+        let error = NpyArray::read(&mut buf.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+    // A header which claims to be longer than the file.
+    let header = shape("(1,)");
+    let buf = npy(2, &u32::MAX.to_le_bytes(), &header);
+    let error = NpyArray::read(&mut buf.as_slice()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_npz_round_trip() {
+    let mut arrays = BTreeMap::new();
+    arrays.insert(
+        "W1".to_string(),
+        NpyArray::from_matrix(&na::dmatrix![1.0, 2.0; 3.0, 4.0]),
+    );
+    arrays.insert(
+        "b1".to_string(),
+        NpyArray::from_vector(&na::dvector![0.5, -0.5]),
+    );
+    let mut buf = io::Cursor::new(vec![]);
+    write_npz(&mut buf, &arrays, DType::F64).unwrap();
+    buf.set_position(0);
+    let loaded = read_npz(buf).unwrap();
+    assert_eq!(loaded, arrays);
+    assert_eq!(loaded["b1"].to_vector().unwrap(), na::dvector![0.5, -0.5]);
+}
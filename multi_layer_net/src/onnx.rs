@@ -0,0 +1,560 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{checkpoint::invalid_data, float::Float};
+
+pub const IR_VERSION: i64 = 8;
+pub const OPSET_VERSION: i64 = 13;
+
+// `TensorProto.DataType.FLOAT`; every initializer is exported as f32.
+const FLOAT: i64 = 1;
+
+// The subset of the ONNX protobuf messages needed to describe a feed-forward
+// network. Field numbers follow onnx.proto3.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Model {
+    pub ir_version: i64,
+    pub producer_name: String,
+    pub opset_version: i64,
+    pub graph: Graph,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Graph {
+    pub name: String,
+    pub nodes: Vec<Node>,
+    pub initializers: Vec<Tensor>,
+    pub inputs: Vec<ValueInfo>,
+    pub outputs: Vec<ValueInfo>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Node {
+    pub name: String,
+    pub op_type: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub attributes: Vec<Attribute>,
+}
+
+// How an affine layer is written out; some runtimes only fuse one of them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Linear {
+    Gemm,
+    MatMulAdd,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Attribute {
+    Float(String, f32),
+    Int(String, i64),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tensor {
+    pub name: String,
+    pub dims: Vec<usize>,
+    // Row-major, like NumPy.
+    pub data: Vec<f32>,
+}
+
+// A float tensor whose dimensions of `None` are left symbolic (the batch
+// size).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValueInfo {
+    pub name: String,
+    pub dims: Vec<Option<usize>>,
+}
+
+impl Node {
+    pub fn new(op_type: &str, name: &str, inputs: &[&str], outputs: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            op_type: op_type.to_string(),
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            attributes: vec![],
+        }
+    }
+
+    pub fn with_attribute(mut self, attribute: Attribute) -> Self {
+        self.attributes.push(attribute);
+        self
+    }
+}
+
+impl Tensor {
+    pub fn from_matrix<T: Float>(name: &str, matrix: &na::DMatrix<T>) -> Self {
+        Self {
+            name: name.to_string(),
+            dims: vec![matrix.nrows(), matrix.ncols()],
+            data: matrix
+                .transpose()
+                .iter()
+                .map(|&x| x.as_f64() as f32)
+                .collect(),
+        }
+    }
+
+    pub fn from_vector<T: Float>(name: &str, vector: &na::DVector<T>) -> Self {
+        Self {
+            name: name.to_string(),
+            dims: vec![vector.len()],
+            data: vector.iter().map(|&x| x.as_f64() as f32).collect(),
+        }
+    }
+}
+
+impl Model {
+    pub fn new(producer_name: &str, graph: Graph) -> Self {
+        Self {
+            ir_version: IR_VERSION,
+            producer_name: producer_name.to_string(),
+            opset_version: OPSET_VERSION,
+            graph,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        write_varint_field(&mut buf, 1, self.ir_version as u64);
+        write_bytes_field(&mut buf, 2, self.producer_name.as_bytes());
+        write_bytes_field(&mut buf, 7, &encode_graph(&self.graph));
+        // OperatorSetIdProto with the default domain.
+        let mut opset = vec![];
+        write_bytes_field(&mut opset, 1, b"");
+        write_varint_field(&mut opset, 2, self.opset_version as u64);
+        write_bytes_field(&mut buf, 8, &opset);
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        let mut model = Model::new("", Graph::default());
+        for (field, value) in fields(buf)? {
+            match (field, value) {
+                (1, Value::Varint(n)) => model.ir_version = n as i64,
+                (2, Value::Bytes(b)) => model.producer_name = decode_string(b)?,
+                (7, Value::Bytes(b)) => model.graph = decode_graph(b)?,
+                (8, Value::Bytes(b)) => {
+                    for (field, value) in fields(b)? {
+                        if let (2, Value::Varint(n)) = (field, value) {
+                            model.opset_version = n as i64;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(model)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&self.encode())?;
+        writer.flush()
+    }
+}
+
+fn encode_graph(graph: &Graph) -> Vec<u8> {
+    let mut buf = vec![];
+    for node in graph.nodes.iter() {
+        write_bytes_field(&mut buf, 1, &encode_node(node));
+    }
+    write_bytes_field(&mut buf, 2, graph.name.as_bytes());
+    for tensor in graph.initializers.iter() {
+        write_bytes_field(&mut buf, 5, &encode_tensor(tensor));
+    }
+    for input in graph.inputs.iter() {
+        write_bytes_field(&mut buf, 11, &encode_value_info(input));
+    }
+    for output in graph.outputs.iter() {
+        write_bytes_field(&mut buf, 12, &encode_value_info(output));
+    }
+    buf
+}
+
+fn encode_node(node: &Node) -> Vec<u8> {
+    let mut buf = vec![];
+    for input in node.inputs.iter() {
+        write_bytes_field(&mut buf, 1, input.as_bytes());
+    }
+    for output in node.outputs.iter() {
+        write_bytes_field(&mut buf, 2, output.as_bytes());
+    }
+    write_bytes_field(&mut buf, 3, node.name.as_bytes());
+    write_bytes_field(&mut buf, 4, node.op_type.as_bytes());
+    for attribute in node.attributes.iter() {
+        // AttributeProto.AttributeType is FLOAT = 1 and INT = 2.
+        let mut attr = vec![];
+        match attribute {
+            Attribute::Float(name, f) => {
+                write_bytes_field(&mut attr, 1, name.as_bytes());
+                write_key(&mut attr, 2, 5);
+                attr.extend_from_slice(&f.to_le_bytes());
+                write_varint_field(&mut attr, 20, 1);
+            }
+            Attribute::Int(name, i) => {
+                write_bytes_field(&mut attr, 1, name.as_bytes());
+                write_varint_field(&mut attr, 3, *i as u64);
+                write_varint_field(&mut attr, 20, 2);
+            }
+        }
+        write_bytes_field(&mut buf, 5, &attr);
+    }
+    buf
+}
+
+fn encode_tensor(tensor: &Tensor) -> Vec<u8> {
+    let mut buf = vec![];
+    for dim in tensor.dims.iter() {
+        write_varint_field(&mut buf, 1, *dim as u64);
+    }
+    write_varint_field(&mut buf, 2, FLOAT as u64);
+    write_bytes_field(&mut buf, 8, tensor.name.as_bytes());
+    let raw_data = tensor
+        .data
+        .iter()
+        .flat_map(|x| x.to_le_bytes())
+        .collect::<Vec<u8>>();
+    write_bytes_field(&mut buf, 9, &raw_data);
+    buf
+}
+
+fn encode_value_info(value_info: &ValueInfo) -> Vec<u8> {
+    let mut shape = vec![];
+    for dim in value_info.dims.iter() {
+        let mut dimension = vec![];
+        match dim {
+            Some(n) => write_varint_field(&mut dimension, 1, *n as u64),
+            None => write_bytes_field(&mut dimension, 2, b"batch"),
+        }
+        write_bytes_field(&mut shape, 1, &dimension);
+    }
+    let mut tensor_type = vec![];
+    write_varint_field(&mut tensor_type, 1, FLOAT as u64);
+    write_bytes_field(&mut tensor_type, 2, &shape);
+    let mut type_proto = vec![];
+    write_bytes_field(&mut type_proto, 1, &tensor_type);
+    let mut buf = vec![];
+    write_bytes_field(&mut buf, 1, value_info.name.as_bytes());
+    write_bytes_field(&mut buf, 2, &type_proto);
+    buf
+}
+
+fn decode_graph(buf: &[u8]) -> io::Result<Graph> {
+    let mut graph = Graph::default();
+    for (field, value) in fields(buf)? {
+        match (field, value) {
+            (1, Value::Bytes(b)) => graph.nodes.push(decode_node(b)?),
+            (2, Value::Bytes(b)) => graph.name = decode_string(b)?,
+            (5, Value::Bytes(b)) => graph.initializers.push(decode_tensor(b)?),
+            (11, Value::Bytes(b)) => graph.inputs.push(decode_value_info(b)?),
+            (12, Value::Bytes(b)) => graph.outputs.push(decode_value_info(b)?),
+            _ => {}
+        }
+    }
+    Ok(graph)
+}
+
+fn decode_node(buf: &[u8]) -> io::Result<Node> {
+    let mut node = Node::default();
+    for (field, value) in fields(buf)? {
+        match (field, value) {
+            (1, Value::Bytes(b)) => node.inputs.push(decode_string(b)?),
+            (2, Value::Bytes(b)) => node.outputs.push(decode_string(b)?),
+            (3, Value::Bytes(b)) => node.name = decode_string(b)?,
+            (4, Value::Bytes(b)) => node.op_type = decode_string(b)?,
+            (5, Value::Bytes(b)) => {
+                let mut name = String::new();
+                let mut attribute = None;
+                for (field, value) in fields(b)? {
+                    match (field, value) {
+                        (1, Value::Bytes(b)) => name = decode_string(b)?,
+                        (2, Value::Fixed32(f)) => attribute = Some(Value::Fixed32(f)),
+                        (3, Value::Varint(i)) => attribute = Some(Value::Varint(i)),
+                        _ => {}
+                    }
+                }
+                node.attributes.push(match attribute {
+                    Some(Value::Fixed32(f)) => Attribute::Float(name, f32::from_le_bytes(f)),
+                    Some(Value::Varint(i)) => Attribute::Int(name, i as i64),
+                    _ => {
+                        return Err(invalid_data(format!(
+                            "attribute {} has an unsupported type.",
+                            name
+                        )))
+                    }
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(node)
+}
+
+fn decode_tensor(buf: &[u8]) -> io::Result<Tensor> {
+    let mut tensor = Tensor::default();
+    for (field, value) in fields(buf)? {
+        match (field, value) {
+            (1, Value::Varint(n)) => tensor.dims.push(n as usize),
+            (2, Value::Varint(n)) if n as i64 != FLOAT => {
+                return Err(invalid_data(format!(
+                    "tensor data type {} is not supported.",
+                    n
+                )))
+            }
+            (8, Value::Bytes(b)) => tensor.name = decode_string(b)?,
+            (9, Value::Bytes(b)) => {
+                tensor.data = b
+                    .chunks_exact(4)
+                    .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                    .collect()
+            }
+            _ => {}
+        }
+    }
+    if tensor.data.len() != tensor.dims.iter().product::<usize>() {
+        return Err(invalid_data(format!(
+            "tensor {} does not match its dimensions {:?}.",
+            tensor.name, tensor.dims
+        )));
+    }
+    Ok(tensor)
+}
+
+fn decode_value_info(buf: &[u8]) -> io::Result<ValueInfo> {
+    let mut value_info = ValueInfo::default();
+    for (field, value) in fields(buf)? {
+        match (field, value) {
+            (1, Value::Bytes(b)) => value_info.name = decode_string(b)?,
+            // TypeProto.tensor_type.shape.dim
+            (2, Value::Bytes(type_proto)) => {
+                for tensor_type in bytes_fields(type_proto, 1)? {
+                    for shape in bytes_fields(tensor_type, 2)? {
+                        for dimension in bytes_fields(shape, 1)? {
+                            let mut dim = None;
+                            for (field, value) in fields(dimension)? {
+                                if let (1, Value::Varint(n)) = (field, value) {
+                                    dim = Some(n as usize);
+                                }
+                            }
+                            value_info.dims.push(dim);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(value_info)
+}
+
+fn decode_string(buf: &[u8]) -> io::Result<String> {
+    String::from_utf8(buf.to_vec()).map_err(|e| invalid_data(e.to_string()))
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_key(buf: &mut Vec<u8>, field: u64, wire_type: u64) {
+    write_varint(buf, (field << 3) | wire_type);
+}
+
+fn write_varint_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+    write_key(buf, field, 0);
+    write_varint(buf, value);
+}
+
+fn write_bytes_field(buf: &mut Vec<u8>, field: u64, value: &[u8]) {
+    write_key(buf, field, 2);
+    write_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32([u8; 4]),
+}
+
+// Splits a protobuf message into its (field number, value) pairs.
+fn fields(buf: &[u8]) -> io::Result<Vec<(u64, Value<'_>)>> {
+    let truncated = || invalid_data("the protobuf message is truncated.".to_string());
+    let read_varint = |pos: &mut usize| -> io::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *buf.get(*pos).ok_or_else(truncated)?;
+            *pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid_data("a protobuf varint is too long.".to_string()))
+    };
+    let mut pos = 0;
+    let mut fields = vec![];
+    while pos < buf.len() {
+        let key = read_varint(&mut pos)?;
+        let value = match key & 7 {
+            0 => Value::Varint(read_varint(&mut pos)?),
+            1 => {
+                buf.get(pos..pos + 8).ok_or_else(truncated)?;
+                pos += 8;
+                Value::Fixed64
+            }
+            2 => {
+                let len = read_varint(&mut pos)? as usize;
+                let bytes = buf.get(pos..pos + len).ok_or_else(truncated)?;
+                pos += len;
+                Value::Bytes(bytes)
+            }
+            5 => {
+                let bytes = buf.get(pos..pos + 4).ok_or_else(truncated)?;
+                pos += 4;
+                Value::Fixed32(bytes.try_into().unwrap())
+            }
+            wire_type => {
+                return Err(invalid_data(format!(
+                    "protobuf wire type {} is not supported.",
+                    wire_type
+                )))
+            }
+        };
+        fields.push((key >> 3, value));
+    }
+    Ok(fields)
+}
+
+// Returns the length-delimited values of `field`.
+fn bytes_fields(buf: &[u8], field: u64) -> io::Result<Vec<&[u8]>> {
+    Ok(fields(buf)?
+        .into_iter()
+        .filter_map(|(f, value)| match value {
+            Value::Bytes(b) if f == field => Some(b),
+            _ => None,
+        })
+        .collect())
+}
+
+// Evaluates `graph` on `input` in f64 so that tests can compare an exported
+// model with the network it came from.
+#[cfg(test)]
+pub(crate) fn run(graph: &Graph, input: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    use std::collections::HashMap;
+
+    let mut values = HashMap::new();
+    for tensor in graph.initializers.iter() {
+        let (nrows, ncols) = match tensor.dims[..] {
+            [n] => (1, n),
+            [nrows, ncols] => (nrows, ncols),
+            _ => panic!("unsupported initializer {}", tensor.name),
+        };
+        let data = tensor.data.iter().map(|&x| x as f64).collect::<Vec<f64>>();
+        values.insert(
+            tensor.name.clone(),
+            na::DMatrix::<f64>::from_row_slice(nrows, ncols, &data),
+        );
+    }
+    values.insert(graph.inputs[0].name.clone(), input.clone());
+    // Row vectors broadcast over the batch.
+    let broadcast = |x: &na::DMatrix<f64>, row: &na::DMatrix<f64>, f: fn(f64, f64) -> f64| {
+        na::DMatrix::<f64>::from_fn(x.nrows(), x.ncols(), |i, j| f(x[(i, j)], row[(0, j)]))
+    };
+    for node in graph.nodes.iter() {
+        let x = |i: usize| &values[&node.inputs[i]];
+        let y = match node.op_type.as_str() {
+            "Gemm" => broadcast(&(x(0) * x(1)), x(2), |a, b| a + b),
+            "MatMul" => x(0) * x(1),
+            "Add" => broadcast(x(0), x(1), |a, b| a + b),
+            "Mul" => broadcast(x(0), x(1), |a, b| a * b),
+            "Relu" => x(0).map(|a| a.max(0.0)),
+            "Sigmoid" => x(0).map(|a| 1.0 / (1.0 + (-a).exp())),
+            "BatchNormalization" => {
+                let epsilon = node
+                    .attributes
+                    .iter()
+                    .find_map(|a| match a {
+                        Attribute::Float(name, f) if name == "epsilon" => Some(*f as f64),
+                        _ => None,
+                    })
+                    .unwrap_or(1e-5);
+                let std = x(4).map(|v| (v + epsilon).sqrt());
+                let xn = broadcast(&broadcast(x(0), x(3), |a, b| a - b), &std, |a, b| a / b);
+                broadcast(&broadcast(&xn, x(1), |a, b| a * b), x(2), |a, b| a + b)
+            }
+            "Softmax" => {
+                let mut y = x(0).clone();
+                y.row_iter_mut().for_each(|mut row| {
+                    let c = row.max();
+                    row.apply(|a| *a = (*a - c).exp());
+                    let sum = row.sum();
+                    row /= sum;
+                });
+                y
+            }
+            op_type => panic!("unsupported operator {}", op_type),
+        };
+        values.insert(node.outputs[0].clone(), y);
+    }
+    values[&graph.outputs[0].name].clone()
+}
+
+#[test]
+fn test_model_round_trip() {
+    let graph = Graph {
+        name: "test".to_string(),
+        nodes: vec![
+            Node::new("Gemm", "Gemm1", &["input", "W1", "b1"], &["affine1"]),
+            Node::new("Softmax", "Softmax", &["affine1"], &["output"])
+                .with_attribute(Attribute::Int("axis".to_string(), -1)),
+            Node::new("BatchNormalization", "BN", &["x"], &["y"])
+                .with_attribute(Attribute::Float("epsilon".to_string(), 1e-6)),
+        ],
+        initializers: vec![
+            Tensor::from_matrix("W1", &na::dmatrix![1.0, 2.0, 3.0; 4.0, 5.0, 6.0]),
+            Tensor::from_vector("b1", &na::dvector![0.5, -0.5, 0.25]),
+        ],
+        inputs: vec![ValueInfo {
+            name: "input".to_string(),
+            dims: vec![None, Some(2)],
+        }],
+        outputs: vec![ValueInfo {
+            name: "output".to_string(),
+            dims: vec![None, Some(3)],
+        }],
+    };
+    let model = Model::new("multi_layer_net", graph);
+    let encoded = model.encode();
+    let decoded = Model::decode(&encoded).unwrap();
+    assert_eq!(decoded, model);
+    assert_eq!(
+        decoded.graph.initializers[0].data,
+        vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]
+    );
+    assert!(Model::decode(&encoded[..encoded.len() - 1]).is_err());
+}
+
+#[test]
+fn test_varint_encoding() {
+    let mut buf = vec![];
+    write_varint(&mut buf, 300);
+    assert_eq!(buf, vec![0xac, 0x02]);
+    // Negative int64 values take ten bytes.
+    let mut buf = vec![];
+    write_varint_field(&mut buf, 3, -1i64 as u64);
+    assert_eq!(buf.len(), 11);
+    match &fields(&buf).unwrap()[0] {
+        (3, Value::Varint(n)) => assert_eq!(*n as i64, -1),
+        _ => panic!("expected a varint field"),
+    }
+}
@@ -0,0 +1,130 @@
+use std::{collections::HashMap, io};
+
+use crate::{
+    checkpoint::Checkpoint,
+    float::{cast_matrix, Float},
+    parameters::{NamedParameters, Parameter},
+};
+
+pub mod ada_grad;
+pub mod adam;
+pub mod momentum;
+pub mod nesterov;
+pub mod rms_prop;
+pub mod sgd;
+
+// The part of an optimiser which does not depend on the precision of the
+// parameters, so that wrappers like `Scheduler` and callers resuming from a
+// checkpoint need not know it. State is stored as f64 whatever the precision.
+pub trait OptimizerBase {
+    fn learning_rate(&self) -> f64;
+
+    fn set_learning_rate(&mut self, lr: f64);
+
+    // Stores whatever the optimiser has accumulated so far under keys starting
+    // with `prefix`, so that training can be resumed exactly.
+    fn save_state(&self, _checkpoint: &mut Checkpoint, _prefix: &str) {}
+
+    fn load_state(&mut self, _checkpoint: &Checkpoint, _prefix: &str) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub trait Optimizer<T: Float = f64>: OptimizerBase {
+    // Updates a single parameter in place. `key` identifies the parameter so
+    // that stateful optimisers can keep one slot of state per parameter.
+    fn step(&mut self, key: &str, param: na::DMatrixViewMut<T>, grad: na::DMatrixView<T>);
+
+    // For models which keep their parameters as plain matrices and vectors
+    // rather than as `NamedParameters`.
+    fn step_matrix(&mut self, key: &str, param: &mut na::DMatrix<T>, grad: &na::DMatrix<T>) {
+        self.step(key, param.as_view_mut(), grad.as_view());
+    }
+
+    fn step_vector(&mut self, key: &str, param: &mut na::DVector<T>, grad: &na::DVector<T>) {
+        let len = param.len();
+        self.step(
+            key,
+            na::DMatrixViewMut::from_slice(param.as_mut_slice(), len, 1),
+            na::DMatrixView::from_slice(grad.as_slice(), grad.len(), 1),
+        );
+    }
+
+    fn update(&mut self, params: &dyn NamedParameters<T>, grads: &dyn NamedParameters<T>) {
+        let grads: HashMap<String, Parameter<T>> = grads.named_parameters().into_iter().collect();
+        for (key, param) in params.named_parameters() {
+            let Some(grad) = grads.get(&key) else {
+                continue;
+            };
+            if param.is_empty() || grad.is_empty() {
+                continue;
+            }
+            assert_eq!(
+                param.shape(),
+                grad.shape(),
+                "shape of the gradient for {} does not match its parameter.",
+                key
+            );
+            grad.with_view(|grad| param.with_view_mut(|param| self.step(&key, param, grad)));
+        }
+    }
+}
+
+// Per-parameter state which is shaped after the gradient the first time a
+// parameter is seen, in the precision of the parameters.
+#[derive(Clone, Debug)]
+pub struct State<T: Float = f64> {
+    slots: HashMap<String, na::DMatrix<T>>,
+}
+
+impl<T: Float> Default for State<T> {
+    fn default() -> Self {
+        Self {
+            slots: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Float> State<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_or_zeros(&mut self, key: &str, shape: (usize, usize)) -> &mut na::DMatrix<T> {
+        self.slots
+            .entry(key.to_string())
+            .or_insert_with(|| na::DMatrix::<T>::zeros(shape.0, shape.1))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&na::DMatrix<T>> {
+        self.slots.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn save(&self, checkpoint: &mut Checkpoint, prefix: &str) {
+        for (key, slot) in self.slots.iter() {
+            checkpoint
+                .tensors
+                .insert(format!("{}{}", prefix, key), cast_matrix(slot));
+        }
+    }
+
+    // Replaces the current state with every tensor stored under `prefix`.
+    pub fn load(&mut self, checkpoint: &Checkpoint, prefix: &str) {
+        self.slots = checkpoint
+            .tensors
+            .iter()
+            .filter_map(|(key, tensor)| {
+                key.strip_prefix(prefix)
+                    .map(|key| (key.to_string(), cast_matrix(tensor)))
+            })
+            .collect();
+    }
+}
@@ -0,0 +1,60 @@
+use std::io;
+
+use super::{Optimizer, OptimizerBase, State};
+use crate::{checkpoint::Checkpoint, float::Float};
+
+pub struct AdaGrad<T: Float = f64> {
+    lr: f64,
+    h: State<T>,
+}
+
+impl<T: Float> AdaGrad<T> {
+    pub fn new(lr: f64) -> Self {
+        Self {
+            lr,
+            h: State::new(),
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for AdaGrad<T> {
+    fn step(&mut self, key: &str, mut param: na::DMatrixViewMut<T>, grad: na::DMatrixView<T>) {
+        let h = self.h.get_or_zeros(key, grad.shape());
+        *h += grad.component_mul(&grad);
+        let (lr, eps) = (T::of_f64(self.lr), T::of_f64(1e-7));
+        param.zip_zip_apply(&grad, &*h, |p, g, h| *p -= lr * g / (h.sqrt() + eps));
+    }
+}
+
+impl<T: Float> OptimizerBase for AdaGrad<T> {
+    fn learning_rate(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn save_state(&self, checkpoint: &mut Checkpoint, prefix: &str) {
+        self.h.save(checkpoint, &format!("{}h.", prefix));
+    }
+
+    fn load_state(&mut self, checkpoint: &Checkpoint, prefix: &str) -> io::Result<()> {
+        self.h.load(checkpoint, &format!("{}h.", prefix));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_ada_grad() {
+    let mut optimiser: AdaGrad = AdaGrad::new(0.1);
+    let mut param = na::dmatrix![1.0, 2.0];
+    let grad = na::dmatrix![2.0, -4.0];
+    optimiser.step("W1", param.as_view_mut(), grad.as_view());
+    assert!((param[0] - 0.9).abs() < 1e-6);
+    assert!((param[1] - 2.1).abs() < 1e-6);
+    optimiser.step("W1", param.as_view_mut(), grad.as_view());
+    // h = 2 * g^2, so the second step is lr / sqrt(2)
+    assert!((param[0] - (0.9 - 0.1 / 2f64.sqrt())).abs() < 1e-6);
+    assert_eq!(optimiser.h.len(), 1);
+}
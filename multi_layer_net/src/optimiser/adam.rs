@@ -0,0 +1,189 @@
+use std::{collections::HashMap, io};
+
+use super::{Optimizer, OptimizerBase, State};
+use crate::{checkpoint::Checkpoint, float::Float};
+
+pub struct Adam<T: Float = f64> {
+    lr: f64,
+    beta1: f64,
+    beta2: f64,
+    iter: HashMap<String, i32>,
+    m: State<T>,
+    v: State<T>,
+}
+
+impl<T: Float> Adam<T> {
+    pub fn new(lr: f64, beta1: f64, beta2: f64) -> Self {
+        Self {
+            lr,
+            beta1,
+            beta2,
+            iter: HashMap::new(),
+            m: State::new(),
+            v: State::new(),
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for Adam<T> {
+    fn step(&mut self, key: &str, mut param: na::DMatrixViewMut<T>, grad: na::DMatrixView<T>) {
+        let iter = self.iter.entry(key.to_string()).or_insert(0);
+        *iter += 1;
+        let m = self.m.get_or_zeros(key, grad.shape());
+        let (beta1, beta2) = (T::of_f64(self.beta1), T::of_f64(self.beta2));
+        *m = &*m * beta1 + grad * (T::one() - beta1);
+        let v = self.v.get_or_zeros(key, grad.shape());
+        *v = &*v * beta2 + grad.component_mul(&grad) * (T::one() - beta2);
+        let m_correction = T::of_f64(1.0 - self.beta1.powi(*iter));
+        let v_correction = T::of_f64(1.0 - self.beta2.powi(*iter));
+        let (lr, eps) = (T::of_f64(self.lr), T::of_f64(1e-7));
+        param.zip_zip_apply(&*m, &*v, |p, m, v| {
+            *p -= lr * (m / m_correction) / ((v / v_correction).sqrt() + eps)
+        });
+    }
+}
+
+impl<T: Float> OptimizerBase for Adam<T> {
+    fn learning_rate(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn save_state(&self, checkpoint: &mut Checkpoint, prefix: &str) {
+        for (key, iter) in self.iter.iter() {
+            checkpoint.insert_metadata(&format!("{}iter.{}", prefix, key), iter);
+        }
+        self.m.save(checkpoint, &format!("{}m.", prefix));
+        self.v.save(checkpoint, &format!("{}v.", prefix));
+    }
+
+    fn load_state(&mut self, checkpoint: &Checkpoint, prefix: &str) -> io::Result<()> {
+        let iter_prefix = format!("{}iter.", prefix);
+        self.iter = HashMap::new();
+        for key in checkpoint.metadata.keys() {
+            if let Some(param_key) = key.strip_prefix(&iter_prefix) {
+                self.iter
+                    .insert(param_key.to_string(), checkpoint.metadata(key)?);
+            }
+        }
+        self.m.load(checkpoint, &format!("{}m.", prefix));
+        self.v.load(checkpoint, &format!("{}v.", prefix));
+        Ok(())
+    }
+}
+
+// Adam with weight decay applied directly to the parameters rather than
+// added to the gradient, so that it is not rescaled by the adaptive step.
+pub struct AdamW<T: Float = f64> {
+    lr: f64,
+    weight_decay: f64,
+    adam: Adam<T>,
+}
+
+impl<T: Float> AdamW<T> {
+    pub fn new(lr: f64, beta1: f64, beta2: f64, weight_decay: f64) -> Self {
+        Self {
+            lr,
+            weight_decay,
+            adam: Adam::new(lr, beta1, beta2),
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for AdamW<T> {
+    fn step(&mut self, key: &str, mut param: na::DMatrixViewMut<T>, grad: na::DMatrixView<T>) {
+        param *= T::of_f64(1.0 - self.lr * self.weight_decay);
+        self.adam.step(key, param, grad);
+    }
+}
+
+impl<T: Float> OptimizerBase for AdamW<T> {
+    fn learning_rate(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f64) {
+        self.lr = lr;
+        self.adam.set_learning_rate(lr);
+    }
+
+    fn save_state(&self, checkpoint: &mut Checkpoint, prefix: &str) {
+        self.adam.save_state(checkpoint, prefix);
+    }
+
+    fn load_state(&mut self, checkpoint: &Checkpoint, prefix: &str) -> io::Result<()> {
+        self.adam.load_state(checkpoint, prefix)
+    }
+}
+
+#[test]
+fn test_adam() {
+    let mut optimiser: Adam = Adam::new(0.1, 0.9, 0.999);
+    let mut param = na::dmatrix![1.0];
+    optimiser.step("W1", param.as_view_mut(), na::dmatrix![2.0].as_view());
+    assert!((param[0] - 0.9).abs() < 1e-6);
+    optimiser.step("W1", param.as_view_mut(), na::dmatrix![1.0].as_view());
+    assert!((param[0] - 0.806782047).abs() < 1e-6);
+}
+
+#[test]
+fn test_adam_f32() {
+    let mut single: Adam<f32> = Adam::new(0.1, 0.9, 0.999);
+    let mut double: Adam = Adam::new(0.1, 0.9, 0.999);
+    let mut param = na::dmatrix![1.0f32, -0.5];
+    let mut expected = na::dmatrix![1.0, -0.5];
+    for grad in [[2.0, 0.5], [1.0, -1.0], [0.1, 3.0]] {
+        single.step_matrix(
+            "W1",
+            &mut param,
+            &na::dmatrix![grad[0] as f32, grad[1] as f32],
+        );
+        double.step_matrix("W1", &mut expected, &na::dmatrix![grad[0], grad[1]]);
+    }
+    assert!(
+        (param.map(f64::from) - &expected).amax() < 1e-5,
+        "{} != {}",
+        param,
+        expected
+    );
+}
+
+#[test]
+fn test_adam_counts_steps_per_parameter() {
+    let mut optimiser: Adam = Adam::new(0.1, 0.9, 0.999);
+    let mut w = na::dmatrix![1.0];
+    let mut b = na::dmatrix![1.0];
+    optimiser.step("W1", w.as_view_mut(), na::dmatrix![2.0].as_view());
+    optimiser.step("W1", w.as_view_mut(), na::dmatrix![1.0].as_view());
+    optimiser.step("b1", b.as_view_mut(), na::dmatrix![2.0].as_view());
+    assert!((b[0] - 0.9).abs() < 1e-6);
+}
+
+#[test]
+fn test_adam_w() {
+    let mut optimiser: AdamW = AdamW::new(0.1, 0.9, 0.999, 0.5);
+    let mut param = na::dmatrix![1.0];
+    optimiser.step("W1", param.as_view_mut(), na::dmatrix![2.0].as_view());
+    assert!((param[0] - 0.85).abs() < 1e-6);
+    optimiser.step("W1", param.as_view_mut(), na::dmatrix![1.0].as_view());
+    assert!((param[0] - 0.714282047).abs() < 1e-6);
+}
+
+#[test]
+fn test_adam_state_round_trip() {
+    let mut optimiser: Adam = Adam::new(0.1, 0.9, 0.999);
+    let mut param = na::dmatrix![1.0, -1.0];
+    optimiser.step("W1", param.as_view_mut(), na::dmatrix![2.0, 0.5].as_view());
+    let mut checkpoint = Checkpoint::new();
+    optimiser.save_state(&mut checkpoint, "optimiser.");
+    let mut resumed = Adam::new(0.1, 0.9, 0.999);
+    resumed.load_state(&checkpoint, "optimiser.").unwrap();
+    let mut resumed_param = param.clone();
+    let grad = na::dmatrix![1.0, -3.0];
+    optimiser.step("W1", param.as_view_mut(), grad.as_view());
+    resumed.step("W1", resumed_param.as_view_mut(), grad.as_view());
+    assert_eq!(param, resumed_param);
+}
@@ -0,0 +1,80 @@
+use std::io;
+
+use super::{Optimizer, OptimizerBase, State};
+use crate::{checkpoint::Checkpoint, float::Float};
+
+pub struct Momentum<T: Float = f64> {
+    lr: f64,
+    momentum: f64,
+    v: State<T>,
+}
+
+impl<T: Float> Momentum<T> {
+    pub fn new(lr: f64, momentum: f64) -> Self {
+        Self {
+            lr,
+            momentum,
+            v: State::new(),
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for Momentum<T> {
+    fn step(&mut self, key: &str, mut param: na::DMatrixViewMut<T>, grad: na::DMatrixView<T>) {
+        let v = self.v.get_or_zeros(key, grad.shape());
+        *v = &*v * T::of_f64(self.momentum) - grad * T::of_f64(self.lr);
+        param += &*v;
+    }
+}
+
+impl<T: Float> OptimizerBase for Momentum<T> {
+    fn learning_rate(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn save_state(&self, checkpoint: &mut Checkpoint, prefix: &str) {
+        self.v.save(checkpoint, &format!("{}v.", prefix));
+    }
+
+    fn load_state(&mut self, checkpoint: &Checkpoint, prefix: &str) -> io::Result<()> {
+        self.v.load(checkpoint, &format!("{}v.", prefix));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_momentum() {
+    let mut optimiser: Momentum = Momentum::new(0.1, 0.9);
+    let mut param = na::dmatrix![1.0; 2.0];
+    let grad = na::dmatrix![1.0; -2.0];
+    optimiser.step("W1", param.as_view_mut(), grad.as_view());
+    assert_eq!(param, na::dmatrix![0.9; 2.2]);
+    optimiser.step("W1", param.as_view_mut(), grad.as_view());
+    // v = 0.9 * [-0.1, 0.2] - 0.1 * [1.0, -2.0] = [-0.19, 0.38]
+    assert!((param[0] - 0.71).abs() < 1e-12);
+    assert!((param[1] - 2.58).abs() < 1e-12);
+}
+
+#[test]
+fn test_momentum_state_round_trip() {
+    let mut optimiser: Momentum = Momentum::new(0.1, 0.9);
+    let mut param = na::dmatrix![1.0; 2.0];
+    optimiser.step("W1", param.as_view_mut(), na::dmatrix![1.0; -2.0].as_view());
+    let mut checkpoint = Checkpoint::new();
+    optimiser.save_state(&mut checkpoint, "optimiser.");
+    assert!(checkpoint.tensors.contains_key("optimiser.v.W1"));
+    let mut resumed = Momentum::new(0.1, 0.9);
+    resumed.load_state(&checkpoint, "optimiser.").unwrap();
+    let mut resumed_param = param.clone();
+    optimiser.step("W1", param.as_view_mut(), na::dmatrix![0.5; 0.5].as_view());
+    resumed.step(
+        "W1",
+        resumed_param.as_view_mut(),
+        na::dmatrix![0.5; 0.5].as_view(),
+    );
+    assert_eq!(param, resumed_param);
+}
@@ -0,0 +1,59 @@
+use std::io;
+
+use super::{Optimizer, OptimizerBase, State};
+use crate::{checkpoint::Checkpoint, float::Float};
+
+pub struct Nesterov<T: Float = f64> {
+    lr: f64,
+    momentum: f64,
+    v: State<T>,
+}
+
+impl<T: Float> Nesterov<T> {
+    pub fn new(lr: f64, momentum: f64) -> Self {
+        Self {
+            lr,
+            momentum,
+            v: State::new(),
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for Nesterov<T> {
+    fn step(&mut self, key: &str, mut param: na::DMatrixViewMut<T>, grad: na::DMatrixView<T>) {
+        let v = self.v.get_or_zeros(key, grad.shape());
+        let (momentum, lr) = (T::of_f64(self.momentum), T::of_f64(self.lr));
+        *v = &*v * momentum - grad * lr;
+        param += &*v * (momentum * momentum);
+        param -= grad * ((T::one() + momentum) * lr);
+    }
+}
+
+impl<T: Float> OptimizerBase for Nesterov<T> {
+    fn learning_rate(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn save_state(&self, checkpoint: &mut Checkpoint, prefix: &str) {
+        self.v.save(checkpoint, &format!("{}v.", prefix));
+    }
+
+    fn load_state(&mut self, checkpoint: &Checkpoint, prefix: &str) -> io::Result<()> {
+        self.v.load(checkpoint, &format!("{}v.", prefix));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_nesterov() {
+    let mut optimiser: Nesterov = Nesterov::new(0.1, 0.9);
+    let mut param = na::dmatrix![1.0];
+    optimiser.step("W1", param.as_view_mut(), na::dmatrix![2.0].as_view());
+    assert!((param[0] - 0.458).abs() < 1e-12);
+    optimiser.step("W1", param.as_view_mut(), na::dmatrix![1.0].as_view());
+    assert!((param[0] - 0.0412).abs() < 1e-12);
+}
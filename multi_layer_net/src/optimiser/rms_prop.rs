@@ -0,0 +1,59 @@
+use std::io;
+
+use super::{Optimizer, OptimizerBase, State};
+use crate::{checkpoint::Checkpoint, float::Float};
+
+pub struct RMSProp<T: Float = f64> {
+    lr: f64,
+    decay_rate: f64,
+    h: State<T>,
+}
+
+impl<T: Float> RMSProp<T> {
+    pub fn new(lr: f64, decay_rate: f64) -> Self {
+        Self {
+            lr,
+            decay_rate,
+            h: State::new(),
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for RMSProp<T> {
+    fn step(&mut self, key: &str, mut param: na::DMatrixViewMut<T>, grad: na::DMatrixView<T>) {
+        let h = self.h.get_or_zeros(key, grad.shape());
+        let decay_rate = T::of_f64(self.decay_rate);
+        *h = &*h * decay_rate + grad.component_mul(&grad) * (T::one() - decay_rate);
+        let (lr, eps) = (T::of_f64(self.lr), T::of_f64(1e-7));
+        param.zip_zip_apply(&grad, &*h, |p, g, h| *p -= lr * g / (h.sqrt() + eps));
+    }
+}
+
+impl<T: Float> OptimizerBase for RMSProp<T> {
+    fn learning_rate(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn save_state(&self, checkpoint: &mut Checkpoint, prefix: &str) {
+        self.h.save(checkpoint, &format!("{}h.", prefix));
+    }
+
+    fn load_state(&mut self, checkpoint: &Checkpoint, prefix: &str) -> io::Result<()> {
+        self.h.load(checkpoint, &format!("{}h.", prefix));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_rms_prop() {
+    let mut optimiser: RMSProp = RMSProp::new(0.1, 0.99);
+    let mut param = na::dmatrix![1.0];
+    optimiser.step("W1", param.as_view_mut(), na::dmatrix![2.0].as_view());
+    assert!(param[0].abs() < 1e-6);
+    optimiser.step("W1", param.as_view_mut(), na::dmatrix![1.0].as_view());
+    assert!((param[0] + 0.449012553).abs() < 1e-6);
+}
@@ -0,0 +1,54 @@
+use super::{Optimizer, OptimizerBase};
+use crate::float::Float;
+
+pub struct SGD {
+    lr: f64,
+}
+
+impl SGD {
+    pub fn new(lr: f64) -> Self {
+        Self { lr }
+    }
+}
+
+// Stateless, so the same optimiser can update parameters of any precision.
+impl<T: Float> Optimizer<T> for SGD {
+    fn step(&mut self, _key: &str, mut param: na::DMatrixViewMut<T>, grad: na::DMatrixView<T>) {
+        param -= grad * T::of_f64(self.lr);
+    }
+}
+
+impl OptimizerBase for SGD {
+    fn learning_rate(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+}
+
+#[test]
+fn test_sgd() {
+    use crate::shared::Shared;
+
+    use crate::{grads_exteded::GradsExt, params_extended::ParamsExt};
+
+    let mut params = ParamsExt::new(2);
+    let mut grads = GradsExt::new(2);
+    params.weight_list[0] = Shared::new(na::dmatrix![1.0, 2.0; 3.0, 4.0]);
+    grads.d_weight_list[0] = Shared::new(na::dmatrix![1.0, 1.0; -1.0, 0.0]);
+    params.gamma_list[0] = Shared::new(na::dvector![1.0, 1.0]);
+    grads.d_gamma_list[0] = Shared::new(na::dvector![0.5, -0.5]);
+    let params = Shared::new(params);
+    let mut optimiser = SGD::new(0.1);
+    optimiser.update(&params, &grads);
+    assert_eq!(
+        *params.borrow().weight_list[0].borrow(),
+        na::dmatrix![0.9, 1.9; 3.1, 4.0]
+    );
+    assert_eq!(
+        *params.borrow().gamma_list[0].borrow(),
+        na::dvector![0.95, 1.05]
+    );
+}
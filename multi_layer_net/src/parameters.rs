@@ -0,0 +1,157 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    float::Float, grads::Grads, grads_exteded::GradsExt, params::Params,
+    params_extended::ParamsExt, shared::Shared,
+};
+
+// A handle to one trainable tensor, shared with the layer that uses it.
+#[derive(Clone, Debug)]
+pub enum Parameter<T: Float = f64> {
+    Matrix(Shared<na::DMatrix<T>>),
+    Vector(Shared<na::DVector<T>>),
+}
+
+impl<T: Float> Parameter<T> {
+    pub fn shape(&self) -> (usize, usize) {
+        match self {
+            Parameter::Matrix(m) => m.borrow().shape(),
+            Parameter::Vector(v) => v.borrow().shape(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let (nrows, ncols) = self.shape();
+        nrows * ncols == 0
+    }
+
+    // Vectors are viewed as a single column so that every parameter can be
+    // handled as a matrix.
+    pub fn to_matrix(&self) -> na::DMatrix<T> {
+        self.with_view(|view| view.clone_owned())
+    }
+
+    pub fn with_view<R>(&self, f: impl FnOnce(na::DMatrixView<T>) -> R) -> R {
+        let (nrows, ncols) = self.shape();
+        match self {
+            Parameter::Matrix(m) => f(na::DMatrixView::from_slice(
+                m.borrow().as_slice(),
+                nrows,
+                ncols,
+            )),
+            Parameter::Vector(v) => f(na::DMatrixView::from_slice(
+                v.borrow().as_slice(),
+                nrows,
+                ncols,
+            )),
+        }
+    }
+
+    pub fn with_view_mut<R>(&self, f: impl FnOnce(na::DMatrixViewMut<T>) -> R) -> R {
+        let (nrows, ncols) = self.shape();
+        match self {
+            Parameter::Matrix(m) => f(na::DMatrixViewMut::from_slice(
+                m.borrow_mut().as_mut_slice(),
+                nrows,
+                ncols,
+            )),
+            Parameter::Vector(v) => f(na::DMatrixViewMut::from_slice(
+                v.borrow_mut().as_mut_slice(),
+                nrows,
+                ncols,
+            )),
+        }
+    }
+}
+
+// Parameters and gradients are exposed under the same keys as the book
+// ("W1", "b1", "gamma1", "beta1", ...) so that optimisers can pair them up.
+pub trait NamedParameters<T: Float = f64> {
+    fn named_parameters(&self) -> Vec<(String, Parameter<T>)>;
+}
+
+impl<T: Float, P: NamedParameters<T>> NamedParameters<T> for Rc<RefCell<P>> {
+    fn named_parameters(&self) -> Vec<(String, Parameter<T>)> {
+        self.borrow().named_parameters()
+    }
+}
+
+impl<T: Float, P: NamedParameters<T>> NamedParameters<T> for Shared<P> {
+    fn named_parameters(&self) -> Vec<(String, Parameter<T>)> {
+        self.borrow().named_parameters()
+    }
+}
+
+fn matrix_list<T: Float>(
+    prefix: &str,
+    list: &[Shared<na::DMatrix<T>>],
+) -> Vec<(String, Parameter<T>)> {
+    list.iter()
+        .enumerate()
+        .map(|(i, m)| (format!("{}{}", prefix, i + 1), Parameter::Matrix(m.clone())))
+        .collect()
+}
+
+fn vector_list<T: Float>(
+    prefix: &str,
+    list: &[Shared<na::DVector<T>>],
+) -> Vec<(String, Parameter<T>)> {
+    list.iter()
+        .enumerate()
+        .map(|(i, v)| (format!("{}{}", prefix, i + 1), Parameter::Vector(v.clone())))
+        .collect()
+}
+
+impl<T: Float> NamedParameters<T> for Params<T> {
+    fn named_parameters(&self) -> Vec<(String, Parameter<T>)> {
+        let mut list = matrix_list("W", &self.weight_list);
+        list.extend(vector_list("b", &self.bias_list));
+        list
+    }
+}
+
+impl<T: Float> NamedParameters<T> for ParamsExt<T> {
+    fn named_parameters(&self) -> Vec<(String, Parameter<T>)> {
+        let mut list = matrix_list("W", &self.weight_list);
+        list.extend(vector_list("b", &self.bias_list));
+        list.extend(vector_list("gamma", &self.gamma_list));
+        list.extend(vector_list("beta", &self.beta_list));
+        list
+    }
+}
+
+impl<T: Float> NamedParameters<T> for Grads<T> {
+    fn named_parameters(&self) -> Vec<(String, Parameter<T>)> {
+        let mut list = matrix_list("W", &self.d_weight_list);
+        list.extend(vector_list("b", &self.d_bias_list));
+        list
+    }
+}
+
+impl<T: Float> NamedParameters<T> for GradsExt<T> {
+    fn named_parameters(&self) -> Vec<(String, Parameter<T>)> {
+        let mut list = matrix_list("W", &self.d_weight_list);
+        list.extend(vector_list("b", &self.d_bias_list));
+        list.extend(vector_list("gamma", &self.d_gamma_list));
+        list.extend(vector_list("beta", &self.d_beta_list));
+        list
+    }
+}
+
+#[test]
+fn test_named_parameters() {
+    let mut params = ParamsExt::new(2);
+    params.weight_list[0] = Shared::new(na::DMatrix::<f64>::zeros(3, 2));
+    params.gamma_list[0] = Shared::new(na::DVector::<f64>::from_element(2, 1.0));
+    let named = params.named_parameters();
+    let keys: Vec<&str> = named.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(
+        keys,
+        vec!["W1", "W2", "b1", "b2", "gamma1", "gamma2", "beta1", "beta2"]
+    );
+    assert_eq!(named[0].1.shape(), (3, 2));
+    assert_eq!(named[4].1.shape(), (2, 1));
+    assert!(named[1].1.is_empty());
+    named[4].1.with_view_mut(|mut view| view[(1, 0)] = 5.0);
+    assert_eq!(params.gamma_list[0].borrow()[1], 5.0);
+}
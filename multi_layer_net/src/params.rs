@@ -1,15 +1,36 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{io, path::Path};
 
-pub struct Params {
-    pub weight_list: Vec<Rc<RefCell<na::DMatrix<f64>>>>,
-    pub bias_list: Vec<Rc<RefCell<na::DVector<f64>>>>,
+use crate::{float::Float, params_extended::ParamsExt, shared::Shared};
+
+pub struct Params<T: Float = f64> {
+    pub weight_list: Vec<Shared<na::DMatrix<T>>>,
+    pub bias_list: Vec<Shared<na::DVector<T>>>,
 }
 
-impl Params {
+impl<T: Float> Params<T> {
     pub fn new(size: usize) -> Self {
         Self {
-            weight_list: vec![Rc::new(RefCell::new(na::DMatrix::<f64>::zeros(0, 0))); size],
-            bias_list: vec![Rc::new(RefCell::new(na::DVector::<f64>::zeros(0))); size],
+            weight_list: vec![Shared::new(na::DMatrix::<T>::zeros(0, 0)); size],
+            bias_list: vec![Shared::new(na::DVector::<T>::zeros(0)); size],
+        }
+    }
+
+    // Reads "W1", "b1", "W2", "b2", ... like `ParamsExt`, ignoring any batch
+    // normalisation parameters.
+    pub fn from_npz(path: &Path) -> io::Result<Self> {
+        ParamsExt::from_npz(path).map(Self::from)
+    }
+
+    pub fn from_safetensors(path: &Path) -> io::Result<Self> {
+        ParamsExt::from_safetensors(path).map(Self::from)
+    }
+}
+
+impl<T: Float> From<ParamsExt<T>> for Params<T> {
+    fn from(params: ParamsExt<T>) -> Self {
+        Self {
+            weight_list: params.weight_list,
+            bias_list: params.bias_list,
         }
     }
 }
@@ -1,19 +1,88 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{collections::BTreeMap, io, path::Path};
 
-pub struct ParamsExt {
-    pub weight_list: Vec<Rc<RefCell<na::DMatrix<f64>>>>,
-    pub bias_list: Vec<Rc<RefCell<na::DVector<f64>>>>,
-    pub gamma_list: Vec<Rc<RefCell<na::DVector<f64>>>>,
-    pub beta_list: Vec<Rc<RefCell<na::DVector<f64>>>>,
+use crate::{
+    checkpoint::invalid_data,
+    float::Float,
+    numpy::{load_npz, NpyArray},
+    safetensors::load_safetensors,
+    shared::Shared,
+};
+
+pub struct ParamsExt<T: Float = f64> {
+    pub weight_list: Vec<Shared<na::DMatrix<T>>>,
+    pub bias_list: Vec<Shared<na::DVector<T>>>,
+    pub gamma_list: Vec<Shared<na::DVector<T>>>,
+    pub beta_list: Vec<Shared<na::DVector<T>>>,
 }
 
-impl ParamsExt {
+impl<T: Float> ParamsExt<T> {
     pub fn new(size: usize) -> Self {
         Self {
-            weight_list: vec![Rc::new(RefCell::new(na::DMatrix::<f64>::zeros(0, 0))); size],
-            bias_list: vec![Rc::new(RefCell::new(na::DVector::<f64>::zeros(0))); size],
-            gamma_list: vec![Rc::new(RefCell::new(na::DVector::<f64>::zeros(0))); size],
-            beta_list: vec![Rc::new(RefCell::new(na::DVector::<f64>::zeros(0))); size],
+            weight_list: vec![Shared::new(na::DMatrix::<T>::zeros(0, 0)); size],
+            bias_list: vec![Shared::new(na::DVector::<T>::zeros(0)); size],
+            gamma_list: vec![Shared::new(na::DVector::<T>::zeros(0)); size],
+            beta_list: vec![Shared::new(na::DVector::<T>::zeros(0)); size],
         }
     }
+
+    // Builds the parameters from a .npz archive or a safetensors file whose
+    // arrays are keyed like the book's ("W1", "b1", "W2", "b2", ...), one
+    // entry per layer found, additionally reading "gamma1", "beta1", ... for
+    // the layers which have batch normalisation.
+    pub fn from_npz(path: &Path) -> io::Result<Self> {
+        Self::from_arrays(&load_npz(path)?)
+    }
+
+    pub fn from_safetensors(path: &Path) -> io::Result<Self> {
+        Self::from_arrays(&load_safetensors(path)?)
+    }
+
+    fn from_arrays(arrays: &BTreeMap<String, NpyArray>) -> io::Result<Self> {
+        let size = (1..)
+            .take_while(|i| arrays.contains_key(&format!("W{}", i)))
+            .count();
+        let mut params = Self::new(size);
+        for idx in 0..size {
+            let bias_key = format!("b{}", idx + 1);
+            let bias = arrays
+                .get(&bias_key)
+                .ok_or_else(|| invalid_data(format!("array {} is missing.", bias_key)))?;
+            params.weight_list[idx] = Shared::new(arrays[&format!("W{}", idx + 1)].to_matrix()?);
+            params.bias_list[idx] = Shared::new(bias.to_vector()?);
+            if let (Some(gamma), Some(beta)) = (
+                arrays.get(&format!("gamma{}", idx + 1)),
+                arrays.get(&format!("beta{}", idx + 1)),
+            ) {
+                params.gamma_list[idx] = Shared::new(gamma.to_vector()?);
+                params.beta_list[idx] = Shared::new(beta.to_vector()?);
+            }
+        }
+        Ok(params)
+    }
+}
+
+#[test]
+fn test_params_npz_round_trip() {
+    use crate::numpy::{save_parameters_npz, DType};
+
+    let mut params = ParamsExt::new(2);
+    params.weight_list[0] = Shared::new(na::dmatrix![1.0, 2.0; 3.0, 4.0; 5.0, 6.0]);
+    params.bias_list[0] = Shared::new(na::dvector![0.1, 0.2]);
+    params.gamma_list[0] = Shared::new(na::dvector![1.0, 1.5]);
+    params.beta_list[0] = Shared::new(na::dvector![0.0, -1.0]);
+    params.weight_list[1] = Shared::new(na::dmatrix![1.0; -1.0]);
+    params.bias_list[1] = Shared::new(na::dvector![0.3]);
+    let path = std::env::temp_dir().join("params_extended.npz");
+    save_parameters_npz(&path, &params, DType::F64).unwrap();
+    let loaded = ParamsExt::from_npz(&path).unwrap();
+    let plain = crate::params::Params::from_npz(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.weight_list.len(), 2);
+    assert_eq!(
+        *loaded.weight_list[0].borrow(),
+        *params.weight_list[0].borrow()
+    );
+    assert_eq!(*loaded.beta_list[0].borrow(), *params.beta_list[0].borrow());
+    assert!(loaded.gamma_list[1].borrow().is_empty());
+    assert_eq!(*plain.bias_list[1].borrow(), na::dvector![0.3]);
 }
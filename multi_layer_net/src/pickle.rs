@@ -0,0 +1,530 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use crate::{
+    checkpoint::invalid_data,
+    numpy::{DType, NpyArray},
+};
+
+// The subset of Python objects needed to rebuild a dict of NumPy arrays.
+#[derive(Clone, Debug)]
+enum Value {
+    None,
+    Bool(bool),
+    Int(i64),
+    // Only ever shown in error messages.
+    #[allow(dead_code)]
+    Float(f64),
+    Bytes(Vec<u8>),
+    String(String),
+    Tuple(Vec<Value>),
+    List(Vec<Value>),
+    Dict(Vec<(Value, Value)>),
+    Global(String),
+    // An `ndarray` created by `_reconstruct` which is filled in by BUILD.
+    EmptyArray,
+    Array(NpyArray),
+    // A `numpy.dtype` such as "f4", with the byte order set by BUILD.
+    DType { kind: String, byte_order: char },
+}
+
+impl Value {
+    // Python 2 pickles store `str` as bytes, so both count as strings.
+    fn as_str(&self) -> io::Result<&str> {
+        match self {
+            Value::String(s) => Ok(s),
+            Value::Bytes(b) => std::str::from_utf8(b).map_err(|e| invalid_data(e.to_string())),
+            other => Err(invalid_data(format!(
+                "expected a string but got {:?}.",
+                other
+            ))),
+        }
+    }
+
+    fn as_usize(&self) -> io::Result<usize> {
+        match self {
+            Value::Int(n) if *n >= 0 => Ok(*n as usize),
+            other => Err(invalid_data(format!(
+                "expected a non-negative integer but got {:?}.",
+                other
+            ))),
+        }
+    }
+}
+
+// A stack machine for the opcodes of pickle protocols 2 to 4. Only the
+// globals NumPy uses to pickle arrays are understood, so arbitrary classes
+// are never constructed.
+struct Unpickler<R: Read> {
+    reader: R,
+    stack: Vec<Value>,
+    marks: Vec<usize>,
+    memo: HashMap<u32, Value>,
+}
+
+impl<R: Read> Unpickler<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            stack: vec![],
+            marks: vec![],
+            memo: HashMap::new(),
+        }
+    }
+
+    fn read_bytes(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_u8(&mut self) -> io::Result<usize> {
+        Ok(self.read_array::<1>()?[0] as usize)
+    }
+
+    fn read_u32(&mut self) -> io::Result<usize> {
+        Ok(u32::from_le_bytes(self.read_array()?) as usize)
+    }
+
+    fn read_u64(&mut self) -> io::Result<usize> {
+        Ok(u64::from_le_bytes(self.read_array()?) as usize)
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = vec![];
+        loop {
+            match self.read_array::<1>()?[0] {
+                b'\n' => break,
+                byte => line.push(byte),
+            }
+        }
+        String::from_utf8(line).map_err(|e| invalid_data(e.to_string()))
+    }
+
+    fn read_string(&mut self, len: usize) -> io::Result<Value> {
+        let bytes = self.read_bytes(len)?;
+        String::from_utf8(bytes)
+            .map(Value::String)
+            .map_err(|e| invalid_data(e.to_string()))
+    }
+
+    fn pop(&mut self) -> io::Result<Value> {
+        self.stack
+            .pop()
+            .ok_or_else(|| invalid_data("the pickle stack is empty.".to_string()))
+    }
+
+    fn top(&mut self) -> io::Result<&mut Value> {
+        self.stack
+            .last_mut()
+            .ok_or_else(|| invalid_data("the pickle stack is empty.".to_string()))
+    }
+
+    fn pop_mark(&mut self) -> io::Result<Vec<Value>> {
+        let mark = self
+            .marks
+            .pop()
+            .ok_or_else(|| invalid_data("a pickle mark is missing.".to_string()))?;
+        if mark > self.stack.len() {
+            return Err(invalid_data("the pickle stack is corrupted.".to_string()));
+        }
+        Ok(self.stack.split_off(mark))
+    }
+
+    fn pop_n(&mut self, n: usize) -> io::Result<Vec<Value>> {
+        if n > self.stack.len() {
+            return Err(invalid_data("the pickle stack is empty.".to_string()));
+        }
+        Ok(self.stack.split_off(self.stack.len() - n))
+    }
+
+    fn memo_get(&self, index: usize) -> io::Result<Value> {
+        self.memo
+            .get(&(index as u32))
+            .cloned()
+            .ok_or_else(|| invalid_data(format!("memo entry {} is missing.", index)))
+    }
+
+    fn memo_put(&mut self, index: usize) -> io::Result<()> {
+        let value = self.top()?.clone();
+        self.memo.insert(index as u32, value);
+        Ok(())
+    }
+
+    fn set_items(&mut self, items: Vec<Value>) -> io::Result<()> {
+        let Value::Dict(dict) = self.top()? else {
+            return Err(invalid_data("items can only be set on a dict.".to_string()));
+        };
+        let mut items = items.into_iter();
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            dict.push((key, value));
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, values: Vec<Value>) -> io::Result<()> {
+        let Value::List(list) = self.top()? else {
+            return Err(invalid_data(
+                "values can only be appended to a list.".to_string(),
+            ));
+        };
+        list.extend(values);
+        Ok(())
+    }
+
+    fn load(mut self) -> io::Result<Value> {
+        loop {
+            let opcode = self.read_array::<1>()?[0];
+            match opcode {
+                // PROTO
+                0x80 => {
+                    let protocol = self.read_u8()?;
+                    if !(2..=4).contains(&protocol) {
+                        return Err(invalid_data(format!(
+                            "pickle protocol {} is not supported.",
+                            protocol
+                        )));
+                    }
+                }
+                // FRAME only groups opcodes for buffering.
+                0x95 => {
+                    self.read_u64()?;
+                }
+                // STOP
+                b'.' => return self.pop(),
+                // MARK
+                b'(' => self.marks.push(self.stack.len()),
+                // POP, POP_MARK, DUP
+                b'0' => {
+                    self.pop()?;
+                }
+                b'1' => {
+                    self.pop_mark()?;
+                }
+                b'2' => {
+                    let value = self.top()?.clone();
+                    self.stack.push(value);
+                }
+                // NONE, NEWTRUE, NEWFALSE
+                b'N' => self.stack.push(Value::None),
+                0x88 => self.stack.push(Value::Bool(true)),
+                0x89 => self.stack.push(Value::Bool(false)),
+                // BININT, BININT1, BININT2
+                b'J' => {
+                    let n = i32::from_le_bytes(self.read_array()?);
+                    self.stack.push(Value::Int(n as i64));
+                }
+                b'K' => {
+                    let n = self.read_u8()?;
+                    self.stack.push(Value::Int(n as i64));
+                }
+                b'M' => {
+                    let n = u16::from_le_bytes(self.read_array()?);
+                    self.stack.push(Value::Int(n as i64));
+                }
+                // LONG1 is a little-endian two's complement integer.
+                0x8a => {
+                    let len = self.read_u8()?;
+                    if len > 8 {
+                        return Err(invalid_data(format!(
+                            "a {}-byte integer does not fit into i64.",
+                            len
+                        )));
+                    }
+                    let bytes = self.read_bytes(len)?;
+                    let fill = match bytes.last() {
+                        Some(byte) if byte & 0x80 != 0 => 0xff,
+                        _ => 0,
+                    };
+                    let mut buf = [fill; 8];
+                    buf[..len].copy_from_slice(&bytes);
+                    self.stack.push(Value::Int(i64::from_le_bytes(buf)));
+                }
+                // BINFLOAT is big-endian.
+                b'G' => {
+                    let x = f64::from_be_bytes(self.read_array()?);
+                    self.stack.push(Value::Float(x));
+                }
+                // BINUNICODE, SHORT_BINUNICODE, BINUNICODE8
+                b'X' => {
+                    let len = self.read_u32()?;
+                    let value = self.read_string(len)?;
+                    self.stack.push(value);
+                }
+                0x8c => {
+                    let len = self.read_u8()?;
+                    let value = self.read_string(len)?;
+                    self.stack.push(value);
+                }
+                0x8d => {
+                    let len = self.read_u64()?;
+                    let value = self.read_string(len)?;
+                    self.stack.push(value);
+                }
+                // BINSTRING, SHORT_BINSTRING (Python 2 `str`)
+                b'T' => {
+                    let len = self.read_u32()?;
+                    let bytes = self.read_bytes(len)?;
+                    self.stack.push(Value::Bytes(bytes));
+                }
+                b'U' => {
+                    let len = self.read_u8()?;
+                    let bytes = self.read_bytes(len)?;
+                    self.stack.push(Value::Bytes(bytes));
+                }
+                // BINBYTES, SHORT_BINBYTES, BINBYTES8
+                b'B' => {
+                    let len = self.read_u32()?;
+                    let bytes = self.read_bytes(len)?;
+                    self.stack.push(Value::Bytes(bytes));
+                }
+                b'C' => {
+                    let len = self.read_u8()?;
+                    let bytes = self.read_bytes(len)?;
+                    self.stack.push(Value::Bytes(bytes));
+                }
+                0x8e => {
+                    let len = self.read_u64()?;
+                    let bytes = self.read_bytes(len)?;
+                    self.stack.push(Value::Bytes(bytes));
+                }
+                // EMPTY_TUPLE, TUPLE, TUPLE1, TUPLE2, TUPLE3
+                b')' => self.stack.push(Value::Tuple(vec![])),
+                b't' => {
+                    let values = self.pop_mark()?;
+                    self.stack.push(Value::Tuple(values));
+                }
+                0x85..=0x87 => {
+                    let values = self.pop_n((opcode - 0x84) as usize)?;
+                    self.stack.push(Value::Tuple(values));
+                }
+                // EMPTY_LIST, APPEND, APPENDS, LIST
+                b']' => self.stack.push(Value::List(vec![])),
+                b'a' => {
+                    let value = self.pop()?;
+                    self.append(vec![value])?;
+                }
+                b'e' => {
+                    let values = self.pop_mark()?;
+                    self.append(values)?;
+                }
+                b'l' => {
+                    let values = self.pop_mark()?;
+                    self.stack.push(Value::List(values));
+                }
+                // EMPTY_DICT, SETITEM, SETITEMS, DICT
+                b'}' => self.stack.push(Value::Dict(vec![])),
+                b's' => {
+                    let items = self.pop_n(2)?;
+                    self.set_items(items)?;
+                }
+                b'u' => {
+                    let items = self.pop_mark()?;
+                    self.set_items(items)?;
+                }
+                b'd' => {
+                    let items = self.pop_mark()?;
+                    self.stack.push(Value::Dict(vec![]));
+                    self.set_items(items)?;
+                }
+                // BINPUT, LONG_BINPUT, MEMOIZE
+                b'q' => {
+                    let index = self.read_u8()?;
+                    self.memo_put(index)?;
+                }
+                b'r' => {
+                    let index = self.read_u32()?;
+                    self.memo_put(index)?;
+                }
+                0x94 => {
+                    let index = self.memo.len();
+                    self.memo_put(index)?;
+                }
+                // BINGET, LONG_BINGET
+                b'h' => {
+                    let index = self.read_u8()?;
+                    let value = self.memo_get(index)?;
+                    self.stack.push(value);
+                }
+                b'j' => {
+                    let index = self.read_u32()?;
+                    let value = self.memo_get(index)?;
+                    self.stack.push(value);
+                }
+                // GLOBAL, STACK_GLOBAL
+                b'c' => {
+                    let module = self.read_line()?;
+                    let name = self.read_line()?;
+                    self.stack
+                        .push(Value::Global(format!("{}.{}", module, name)));
+                }
+                0x93 => {
+                    let name = self.pop()?;
+                    let module = self.pop()?;
+                    let global = format!("{}.{}", module.as_str()?, name.as_str()?);
+                    self.stack.push(Value::Global(global));
+                }
+                // REDUCE
+                b'R' => {
+                    let args = self.pop()?;
+                    let callable = self.pop()?;
+                    let value = reduce(callable, args)?;
+                    self.stack.push(value);
+                }
+                // BUILD
+                b'b' => {
+                    let state = self.pop()?;
+                    let object = self.pop()?;
+                    let value = build(object, state)?;
+                    self.stack.push(value);
+                }
+                other => {
+                    return Err(invalid_data(format!(
+                        "pickle opcode {:#04x} is not supported.",
+                        other
+                    )))
+                }
+            }
+        }
+    }
+}
+
+fn reduce(callable: Value, args: Value) -> io::Result<Value> {
+    let Value::Global(global) = callable else {
+        return Err(invalid_data(format!("{:?} is not callable.", callable)));
+    };
+    let Value::Tuple(args) = args else {
+        return Err(invalid_data(format!(
+            "{} needs a tuple of arguments.",
+            global
+        )));
+    };
+    match (global.as_str(), &args[..]) {
+        ("numpy.core.multiarray._reconstruct" | "numpy._core.multiarray._reconstruct", _) => {
+            Ok(Value::EmptyArray)
+        }
+        ("numpy.dtype", [kind, ..]) => Ok(Value::DType {
+            kind: kind.as_str()?.to_string(),
+            byte_order: '=',
+        }),
+        // Python 3 writes `bytes` as `_codecs.encode(str, "latin1")` with
+        // protocol 2.
+        ("_codecs.encode", [Value::String(s), ..]) => {
+            Ok(Value::Bytes(s.chars().map(|c| c as u8).collect()))
+        }
+        _ => Err(invalid_data(format!("{} cannot be unpickled.", global))),
+    }
+}
+
+fn build(object: Value, state: Value) -> io::Result<Value> {
+    let Value::Tuple(state) = state else {
+        return Err(invalid_data(format!("unexpected state {:?}.", state)));
+    };
+    match (object, &state[..]) {
+        // (version, byte order, ...) as written by `numpy.dtype.__reduce__`.
+        (Value::DType { kind, .. }, [_, byte_order, ..]) => {
+            let byte_order = byte_order.as_str()?.chars().next().unwrap_or('=');
+            Ok(Value::DType { kind, byte_order })
+        }
+        // (version, shape, dtype, is_fortran, raw data) as written by
+        // `numpy.ndarray.__reduce__`.
+        (
+            Value::EmptyArray,
+            [_, Value::Tuple(shape), Value::DType { kind, byte_order }, Value::Bool(fortran_order), data],
+        ) => {
+            let shape = shape
+                .iter()
+                .map(Value::as_usize)
+                .collect::<io::Result<Vec<usize>>>()?;
+            // "=" and "|" mean native order, which every supported target
+            // shares with NumPy's little-endian files.
+            let byte_order = match byte_order {
+                '=' | '|' => '<',
+                order => *order,
+            };
+            let dtype = DType::from_descr(&format!("{}{}", byte_order, kind))?;
+            let bytes = match data {
+                Value::Bytes(bytes) => bytes,
+                other => {
+                    return Err(invalid_data(format!(
+                        "array data must be bytes but got {:?}.",
+                        other
+                    )))
+                }
+            };
+            Ok(Value::Array(NpyArray::from_bytes(
+                shape,
+                dtype,
+                *fortran_order,
+                bytes,
+            )?))
+        }
+        (object, _) => Err(invalid_data(format!("{:?} cannot be built.", object))),
+    }
+}
+
+// Reads a pickled dict of NumPy arrays such as the book's `sample_weight.pkl`.
+pub fn read_pickle<R: Read>(reader: R) -> io::Result<BTreeMap<String, NpyArray>> {
+    let Value::Dict(items) = Unpickler::new(reader).load()? else {
+        return Err(invalid_data("the pickle does not hold a dict.".to_string()));
+    };
+    items
+        .into_iter()
+        .map(|(key, value)| match value {
+            Value::Array(array) => Ok((key.as_str()?.to_string(), array)),
+            other => Err(invalid_data(format!(
+                "{} is not an array but {:?}.",
+                key.as_str()?,
+                other
+            ))),
+        })
+        .collect()
+}
+
+pub fn load_pickle(path: &Path) -> io::Result<BTreeMap<String, NpyArray>> {
+    read_pickle(BufReader::new(File::open(path)?))
+}
+
+#[test]
+fn test_read_pickle() {
+    // {"W1": float32 (2, 3), "b1": float64 (3,), "F": Fortran-ordered float64
+    // (2, 2)} as pickled by NumPy with protocols 2 and 4.
+    for bytes in [
+        &include_bytes!("../testdata/arrays_protocol2.pkl")[..],
+        &include_bytes!("../testdata/arrays_protocol4.pkl")[..],
+    ] {
+        let arrays = read_pickle(bytes).unwrap();
+        assert_eq!(arrays.len(), 3);
+        assert_eq!(
+            arrays["W1"].to_matrix().unwrap(),
+            na::dmatrix![1.0, 2.0, 3.0; 4.0, 5.0, 6.0]
+        );
+        assert_eq!(
+            arrays["b1"].to_vector().unwrap(),
+            na::dvector![0.5, -0.5, 0.25]
+        );
+        assert_eq!(
+            arrays["F"].to_matrix().unwrap(),
+            na::dmatrix![1.0, 2.0; 3.0, 4.0]
+        );
+    }
+}
+
+#[test]
+fn test_read_pickle_rejects_unknown_globals() {
+    // pickle.dumps(os.system, protocol=2)
+    let bytes = b"\x80\x02cposix\nsystem\nq\x00.";
+    assert!(read_pickle(&bytes[..]).is_err());
+    let bytes = b"\x80\x02cos\nsystem\nX\x02\x00\x00\x00lsq\x00\x85R.";
+    assert!(read_pickle(&bytes[..]).is_err());
+}
@@ -0,0 +1,439 @@
+use std::{io, ops::Range, path::Path};
+
+use plotters::{
+    coord::{
+        ranged1d::{AsRangedCoord, ValueFormatter},
+        Shift,
+    },
+    prelude::*,
+};
+
+use crate::trainer::History;
+
+const COLOURS: [RGBColor; 6] = [RED, BLUE, GREEN, MAGENTA, CYAN, BLACK];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scale {
+    Linear,
+    Log,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Series {
+    pub label: String,
+    pub points: Vec<(f64, f64)>,
+}
+
+impl Series {
+    // Plots `ys` against their indices.
+    pub fn new(label: &str, ys: &[f64]) -> Self {
+        Self::from_points(
+            label,
+            ys.iter().enumerate().map(|(x, &y)| (x as f64, y)).collect(),
+        )
+    }
+
+    pub fn from_points(label: &str, points: Vec<(f64, f64)>) -> Self {
+        Self {
+            label: label.to_string(),
+            points,
+        }
+    }
+
+    // An exponential moving average as in TensorBoard: `weight` 0 leaves the
+    // series as it is and values close to 1 smooth it heavily.
+    pub fn smoothed(mut self, weight: f64) -> Self {
+        let mut last = None;
+        for (_, y) in self.points.iter_mut() {
+            if !y.is_finite() {
+                continue;
+            }
+            let value = last.map_or(*y, |last: f64| last * weight + (1.0 - weight) * *y);
+            *y = value;
+            last = Some(value);
+        }
+        self
+    }
+
+    pub fn scaled(mut self, factor: f64) -> Self {
+        self.points.iter_mut().for_each(|(_, y)| *y *= factor);
+        self
+    }
+}
+
+// A line chart of one or more series. Axes which are not given a range are
+// fitted to the data; non-finite points, and non-positive ones on a log axis,
+// are left out.
+#[derive(Clone, Debug)]
+pub struct LinePlot {
+    title: String,
+    x_desc: String,
+    y_desc: String,
+    series: Vec<Series>,
+    x_range: Option<Range<f64>>,
+    y_range: Option<Range<f64>>,
+    y_scale: Scale,
+    size: (u32, u32),
+}
+
+impl LinePlot {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            x_desc: String::new(),
+            y_desc: String::new(),
+            series: vec![],
+            x_range: None,
+            y_range: None,
+            y_scale: Scale::Linear,
+            size: (640, 480),
+        }
+    }
+
+    pub fn x_desc(mut self, desc: &str) -> Self {
+        self.x_desc = desc.to_string();
+        self
+    }
+
+    pub fn y_desc(mut self, desc: &str) -> Self {
+        self.y_desc = desc.to_string();
+        self
+    }
+
+    pub fn series(mut self, series: Series) -> Self {
+        self.series.push(series);
+        self
+    }
+
+    pub fn x_range(mut self, range: Range<f64>) -> Self {
+        self.x_range = Some(range);
+        self
+    }
+
+    pub fn y_range(mut self, range: Range<f64>) -> Self {
+        self.y_range = Some(range);
+        self
+    }
+
+    pub fn y_scale(mut self, scale: Scale) -> Self {
+        self.y_scale = scale;
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = (width, height);
+        self
+    }
+
+    // Writes an SVG for a .svg path and a bitmap (e.g. PNG) otherwise.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if path.extension().is_some_and(|ext| ext == "svg") {
+            self.draw(SVGBackend::new(path, self.size).into_drawing_area())
+        } else {
+            self.draw(BitMapBackend::new(path, self.size).into_drawing_area())
+        }
+    }
+
+    pub fn to_svg_string(&self) -> io::Result<String> {
+        let mut svg = String::new();
+        self.draw(SVGBackend::with_string(&mut svg, self.size).into_drawing_area())?;
+        Ok(svg)
+    }
+
+    fn visible_points(&self, series: &Series) -> Vec<(f64, f64)> {
+        series
+            .points
+            .iter()
+            .copied()
+            .filter(|(x, y)| x.is_finite() && y.is_finite())
+            .filter(|(_, y)| self.y_scale == Scale::Linear || *y > 0.0)
+            .collect()
+    }
+
+    fn draw<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> io::Result<()> {
+        let points = self
+            .series
+            .iter()
+            .map(|series| self.visible_points(series))
+            .collect::<Vec<_>>();
+        let x_range = self
+            .x_range
+            .clone()
+            .unwrap_or_else(|| auto_range(points.iter().flatten().map(|p| p.0), Scale::Linear));
+        let y_range = self
+            .y_range
+            .clone()
+            .unwrap_or_else(|| auto_range(points.iter().flatten().map(|p| p.1), self.y_scale));
+        match self.y_scale {
+            Scale::Linear => self.draw_chart(&root, x_range, y_range, &points),
+            Scale::Log => self.draw_chart(&root, x_range, y_range.log_scale(), &points),
+        }
+        .map_err(|e| io::Error::other(e.to_string()))?;
+        root.present()
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    fn draw_chart<DB: DrawingBackend, Y>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        x_range: Range<f64>,
+        y_range: Y,
+        points: &[Vec<(f64, f64)>],
+    ) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>>
+    where
+        Y: AsRangedCoord<Value = f64>,
+        Y::CoordDescType: ValueFormatter<f64>,
+    {
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(root)
+            .caption(&self.title, ("sans-serif", 30).into_font())
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(x_range, y_range)?;
+        chart
+            .configure_mesh()
+            .x_desc(&self.x_desc)
+            .y_desc(&self.y_desc)
+            .draw()?;
+        for (idx, (series, points)) in self.series.iter().zip(points.iter()).enumerate() {
+            let colour = COLOURS[idx % COLOURS.len()];
+            let drawn = chart.draw_series(LineSeries::new(points.iter().copied(), &colour))?;
+            if !series.label.is_empty() {
+                drawn
+                    .label(series.label.as_str())
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], colour));
+            }
+        }
+        if self.series.iter().any(|series| !series.label.is_empty()) {
+            chart
+                .configure_series_labels()
+                .background_style(WHITE.mix(0.8))
+                .border_style(BLACK)
+                .draw()?;
+        }
+        Ok(())
+    }
+}
+
+// Histograms laid out on a grid, filled row by row, all binned over the same
+// range so that the panels can be compared, e.g. the activations of every
+// layer for several weight initialisations.
+#[derive(Clone, Debug)]
+pub struct HistogramGrid {
+    title: String,
+    grid: (usize, usize),
+    panels: Vec<(String, Vec<f64>)>,
+    range: Range<f64>,
+    bins: usize,
+    size: (u32, u32),
+}
+
+impl HistogramGrid {
+    pub fn new(title: &str, rows: usize, columns: usize, range: Range<f64>, bins: usize) -> Self {
+        Self {
+            title: title.to_string(),
+            grid: (rows, columns),
+            panels: vec![],
+            range,
+            bins,
+            size: (300 * columns as u32, 240 * rows as u32 + 40),
+        }
+    }
+
+    pub fn panel(mut self, title: &str, values: Vec<f64>) -> Self {
+        assert!(
+            self.panels.len() < self.grid.0 * self.grid.1,
+            "the grid has no room for another panel."
+        );
+        self.panels.push((title.to_string(), values));
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = (width, height);
+        self
+    }
+
+    // Writes an SVG for a .svg path and a bitmap (e.g. PNG) otherwise.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if path.extension().is_some_and(|ext| ext == "svg") {
+            self.draw(SVGBackend::new(path, self.size).into_drawing_area())
+        } else {
+            self.draw(BitMapBackend::new(path, self.size).into_drawing_area())
+        }
+    }
+
+    pub fn to_svg_string(&self) -> io::Result<String> {
+        let mut svg = String::new();
+        self.draw(SVGBackend::with_string(&mut svg, self.size).into_drawing_area())?;
+        Ok(svg)
+    }
+
+    fn draw<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> io::Result<()> {
+        self.draw_panels(&root)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        root.present()
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    fn draw_panels<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+    ) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+        root.fill(&WHITE)?;
+        let root = root.titled(&self.title, ("sans-serif", 24).into_font())?;
+        let width = (self.range.end - self.range.start) / self.bins as f64;
+        for ((title, values), area) in self.panels.iter().zip(root.split_evenly(self.grid)) {
+            let counts = histogram(values, self.range.clone(), self.bins);
+            let max = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+            let mut chart = ChartBuilder::on(&area)
+                .caption(title, ("sans-serif", 16).into_font())
+                .margin(5)
+                .x_label_area_size(20)
+                .y_label_area_size(40)
+                .build_cartesian_2d(self.range.clone(), 0.0..max * 1.05)?;
+            chart.configure_mesh().disable_mesh().draw()?;
+            chart.draw_series(counts.iter().enumerate().map(|(bin, &count)| {
+                let start = self.range.start + bin as f64 * width;
+                Rectangle::new(
+                    [(start, 0.0), (start + width, count as f64)],
+                    BLUE.mix(0.6).filled(),
+                )
+            }))?;
+        }
+        Ok(())
+    }
+}
+
+// Counts the values falling into each of `bins` equal bins of `range`. The
+// end of the range is included in the last bin and values outside the
+// range, as well as non-finite ones, are dropped. No bins give no counts.
+pub fn histogram(values: &[f64], range: Range<f64>, bins: usize) -> Vec<usize> {
+    let mut counts = vec![0; bins];
+    if bins == 0 {
+        return counts;
+    }
+    let width = (range.end - range.start) / bins as f64;
+    for &value in values.iter() {
+        if !(range.start..=range.end).contains(&value) {
+            continue;
+        }
+        let bin = (((value - range.start) / width) as usize).min(bins - 1);
+        counts[bin] += 1;
+    }
+    counts
+}
+
+// The range of the values with 5% of margin on either side, measured in
+// decades on a log axis.
+fn auto_range(values: impl Iterator<Item = f64>, scale: Scale) -> Range<f64> {
+    let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+        (min.min(v), max.max(v))
+    });
+    if min > max {
+        return match scale {
+            Scale::Linear => 0.0..1.0,
+            Scale::Log => 0.1..1.0,
+        };
+    }
+    match scale {
+        Scale::Linear => {
+            let margin = if max > min {
+                (max - min) * 0.05
+            } else {
+                min.abs().max(1.0) * 0.05
+            };
+            (min - margin)..(max + margin)
+        }
+        Scale::Log => {
+            let (min, max) = (min.log10(), max.log10());
+            let margin = ((max - min) * 0.05).max(0.05);
+            10f64.powf(min - margin)..10f64.powf(max + margin)
+        }
+    }
+}
+
+// The loss of every iteration with its smoothed trend.
+pub fn loss_plot(history: &History, title: &str) -> LinePlot {
+    LinePlot::new(title)
+        .x_desc("Iterations")
+        .y_desc("Loss")
+        .series(Series::new("Loss", &history.loss))
+        .series(Series::new("Smoothed", &history.loss).smoothed(0.9))
+}
+
+// The training and validation accuracy of every epoch in percent.
+pub fn accuracy_plot(history: &History, title: &str) -> LinePlot {
+    let mut plot = LinePlot::new(title)
+        .x_desc("Epochs")
+        .y_desc("% Accuracy")
+        .y_range(0.0..100.0)
+        .series(Series::new("Train Dataset Accuracy", &history.train_accuracy).scaled(100.0));
+    if !history.val_accuracy.is_empty() {
+        plot =
+            plot.series(Series::new("Test Dataset Accuracy", &history.val_accuracy).scaled(100.0));
+    }
+    plot
+}
+
+#[test]
+fn test_smoothed() {
+    let series = Series::new("", &[1.0, 3.0, f64::NAN, 3.0]).smoothed(0.5);
+    assert_eq!(series.points[0], (0.0, 1.0));
+    assert_eq!(series.points[1], (1.0, 2.0));
+    assert!(series.points[2].1.is_nan());
+    assert_eq!(series.points[3], (3.0, 2.5));
+    assert_eq!(Series::new("", &[1.0, 3.0]).smoothed(0.0).points[1].1, 3.0);
+}
+
+#[test]
+fn test_auto_range() {
+    let range = auto_range([1.0, 3.0].into_iter(), Scale::Linear);
+    assert!((range.start - 0.9).abs() < 1e-12 && (range.end - 3.1).abs() < 1e-12);
+    let range = auto_range([0.01, 100.0].into_iter(), Scale::Log);
+    assert!(range.start < 0.01 && range.start > 0.001);
+    assert!(range.end > 100.0 && range.end < 1000.0);
+    assert_eq!(auto_range([2.0].into_iter(), Scale::Linear), 1.9..2.1);
+    assert_eq!(auto_range(std::iter::empty(), Scale::Linear), 0.0..1.0);
+}
+
+#[test]
+fn test_save_svg_and_png() {
+    let history = History {
+        loss: vec![2.3, 1.5, f64::NAN, 0.8, 0.4],
+        train_accuracy: vec![0.5, 0.9],
+        val_accuracy: vec![0.45, 0.85],
+        ..History::default()
+    };
+    let svg = loss_plot(&history, "Loss")
+        .y_scale(Scale::Log)
+        .to_svg_string()
+        .unwrap();
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.contains("Smoothed"));
+
+    let dir = std::env::temp_dir();
+    for name in ["accuracy_plot.svg", "accuracy_plot.png"] {
+        let path = dir.join(name);
+        accuracy_plot(&history, "Accuracy").save(&path).unwrap();
+        let size = std::fs::metadata(&path).unwrap().len();
+        std::fs::remove_file(&path).unwrap();
+        assert!(size > 0);
+    }
+}
+
+#[test]
+fn test_histogram() {
+    let values = [0.0, 0.1, 0.5, 0.99, 1.0, -0.5, f64::NAN];
+    assert_eq!(histogram(&values, 0.0..1.0, 2), vec![2, 3]);
+    assert_eq!(histogram(&values, 0.0..1.0, 4), vec![2, 0, 1, 2]);
+    assert!(histogram(&values, 0.0..1.0, 0).is_empty());
+
+    let svg = HistogramGrid::new("Activations", 1, 2, 0.0..1.0, 10)
+        .panel("1-layer", vec![0.1, 0.2, 0.2])
+        .panel("2-layer", vec![0.9])
+        .to_svg_string()
+        .unwrap();
+    assert!(svg.contains("2-layer"));
+}
@@ -0,0 +1,294 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    checkpoint::{invalid_data, read_bytes},
+    float::Float,
+    numpy::{DType, NpyArray},
+    parameters::{NamedParameters, Parameter},
+};
+
+// The safetensors layout:
+//
+//   header size u64 | JSON header | data
+//
+// where the header maps every tensor name to its dtype, shape and the
+// [begin, end) byte range of its little-endian, row-major data, e.g.
+// {"W1": {"dtype": "F32", "shape": [784, 50], "data_offsets": [0, 156800]}}.
+// An optional "__metadata__" entry maps strings to strings.
+const METADATA_KEY: &str = "__metadata__";
+
+// The limit the reference implementation puts on the header, so that a
+// corrupt size is reported rather than allocated.
+const MAX_HEADER_LEN: u64 = 100_000_000;
+
+impl DType {
+    fn safetensors_name(&self) -> &str {
+        match self {
+            DType::F32 => "F32",
+            DType::F64 => "F64",
+        }
+    }
+
+    fn from_safetensors_name(name: &str) -> io::Result<Self> {
+        match name {
+            "F32" => Ok(DType::F32),
+            "F64" => Ok(DType::F64),
+            other => Err(invalid_data(format!(
+                "dtype {} is not supported; only F32 and F64 are.",
+                other
+            ))),
+        }
+    }
+}
+
+pub fn write_safetensors<W: Write>(
+    writer: &mut W,
+    arrays: &BTreeMap<String, NpyArray>,
+    dtype: DType,
+    metadata: &BTreeMap<String, String>,
+) -> io::Result<()> {
+    let mut header = serde_json::Map::new();
+    if !metadata.is_empty() {
+        header.insert(METADATA_KEY.to_string(), serde_json::json!(metadata));
+    }
+    let mut data = vec![];
+    for (name, array) in arrays.iter() {
+        let begin = data.len();
+        for value in array.data.iter() {
+            match dtype {
+                DType::F32 => data.extend_from_slice(&(*value as f32).to_le_bytes()),
+                DType::F64 => data.extend_from_slice(&value.to_le_bytes()),
+            }
+        }
+        header.insert(
+            name.clone(),
+            serde_json::json!({
+                "dtype": dtype.safetensors_name(),
+                "shape": array.shape,
+                "data_offsets": [begin, data.len()],
+            }),
+        );
+    }
+    let mut header = serde_json::Value::Object(header).to_string();
+    // Padding the header with spaces keeps the data 8-byte aligned.
+    header.push_str(&" ".repeat((8 - header.len() % 8) % 8));
+    writer.write_all(&(header.len() as u64).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(&data)
+}
+
+// Returns every tensor with the metadata of the file.
+pub fn read_safetensors<R: Read>(
+    reader: &mut R,
+) -> io::Result<(BTreeMap<String, NpyArray>, BTreeMap<String, String>)> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    let header_len = u64::from_le_bytes(buf);
+    if header_len > MAX_HEADER_LEN {
+        return Err(invalid_data(format!(
+            "the safetensors header is {} bytes long, more than the {} allowed.",
+            header_len, MAX_HEADER_LEN
+        )));
+    }
+    let header = read_bytes(reader, header_len)?;
+    let header: serde_json::Value =
+        serde_json::from_slice(&header).map_err(|e| invalid_data(e.to_string()))?;
+    let header = header
+        .as_object()
+        .ok_or_else(|| invalid_data("the safetensors header is not an object.".to_string()))?;
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+
+    let mut arrays = BTreeMap::new();
+    let mut metadata = BTreeMap::new();
+    for (name, entry) in header.iter() {
+        if name == METADATA_KEY {
+            metadata = serde_json::from_value(entry.clone())
+                .map_err(|e| invalid_data(format!("{} is malformed: {}", METADATA_KEY, e)))?;
+            continue;
+        }
+        let malformed = || invalid_data(format!("the header of tensor {} is malformed.", name));
+        let dtype = entry["dtype"].as_str().ok_or_else(malformed)?;
+        let dtype = DType::from_safetensors_name(dtype)?;
+        let shape = entry["shape"]
+            .as_array()
+            .ok_or_else(malformed)?
+            .iter()
+            .map(|n| n.as_u64().map(|n| n as usize))
+            .collect::<Option<Vec<usize>>>()
+            .ok_or_else(malformed)?;
+        let offsets = entry["data_offsets"]
+            .as_array()
+            .ok_or_else(malformed)?
+            .iter()
+            .map(|n| n.as_u64().map(|n| n as usize))
+            .collect::<Option<Vec<usize>>>()
+            .ok_or_else(malformed)?;
+        let [begin, end] = offsets[..] else {
+            return Err(malformed());
+        };
+        if begin > end || end > data.len() {
+            return Err(invalid_data(format!(
+                "the data of tensor {} lies outside the file.",
+                name
+            )));
+        }
+        let array = NpyArray::from_bytes(shape, dtype, false, &data[begin..end])?;
+        arrays.insert(name.clone(), array);
+    }
+    Ok((arrays, metadata))
+}
+
+pub fn load_safetensors(path: &Path) -> io::Result<BTreeMap<String, NpyArray>> {
+    let (arrays, _) = read_safetensors(&mut BufReader::new(File::open(path)?))?;
+    Ok(arrays)
+}
+
+// Every non-empty parameter is saved under its own name ("W1", "b1",
+// "gamma1", "beta1", ...), with vectors as 1-D tensors.
+pub fn save_parameters_safetensors<T: Float>(
+    path: &Path,
+    params: &dyn NamedParameters<T>,
+    dtype: DType,
+    metadata: &BTreeMap<String, String>,
+) -> io::Result<()> {
+    let arrays = params
+        .named_parameters()
+        .into_iter()
+        .filter(|(_, param)| !param.is_empty())
+        .map(|(key, param)| {
+            let array = match param {
+                Parameter::Matrix(m) => NpyArray::from_matrix(&m.borrow()),
+                Parameter::Vector(v) => NpyArray::from_vector(&v.borrow()),
+            };
+            (key, array)
+        })
+        .collect::<BTreeMap<String, NpyArray>>();
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_safetensors(&mut writer, &arrays, dtype, metadata)?;
+    writer.flush()
+}
+
+// Copies the tensors into the already shaped `params` in place. Unlike the
+// .npz loader the shapes have to match exactly, so a vector must be stored as
+// a 1-D tensor.
+pub fn load_parameters_safetensors<T: Float>(
+    path: &Path,
+    params: &dyn NamedParameters<T>,
+) -> io::Result<()> {
+    let arrays = load_safetensors(path)?;
+    for (key, param) in params.named_parameters() {
+        if param.is_empty() {
+            continue;
+        }
+        let array = arrays
+            .get(&key)
+            .ok_or_else(|| invalid_data(format!("tensor {} is missing.", key)))?;
+        let (nrows, ncols) = param.shape();
+        let expected = match param {
+            Parameter::Matrix(_) => vec![nrows, ncols],
+            Parameter::Vector(_) => vec![nrows],
+        };
+        if array.shape != expected {
+            return Err(invalid_data(format!(
+                "tensor {} has shape {:?} but {:?} was expected.",
+                key, array.shape, expected
+            )));
+        }
+        let matrix = array.to_matrix::<T>()?;
+        param.with_view_mut(|mut view| view.copy_from(&matrix));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_safetensors_round_trip() {
+    let mut arrays = BTreeMap::new();
+    arrays.insert(
+        "W1".to_string(),
+        NpyArray::from_matrix(&na::dmatrix![1.0, 2.0, 3.0; 4.0, 5.0, 6.0]),
+    );
+    arrays.insert(
+        "b1".to_string(),
+        NpyArray::from_vector(&na::dvector![0.5, -0.5, 0.25]),
+    );
+    let mut metadata = BTreeMap::new();
+    metadata.insert("model".to_string(), "Test".to_string());
+    for dtype in [DType::F32, DType::F64] {
+        let mut buf = vec![];
+        write_safetensors(&mut buf, &arrays, dtype, &metadata).unwrap();
+        let header_len = u64::from_le_bytes(buf[..8].try_into().unwrap()) as usize;
+        assert_eq!(header_len % 8, 0);
+        let (loaded, loaded_metadata) = read_safetensors(&mut buf.as_slice()).unwrap();
+        assert_eq!(loaded, arrays);
+        assert_eq!(loaded_metadata, metadata);
+    }
+}
+
+#[test]
+fn test_safetensors_reads_reference_layout() {
+    // safetensors.numpy.save({"x": np.array([[1, 2]], dtype=np.float32)})
+    let header = br#"{"x":{"dtype":"F32","shape":[1,2],"data_offsets":[0,8]}}"#;
+    let mut buf = (header.len() as u64).to_le_bytes().to_vec();
+    buf.extend_from_slice(header);
+    buf.extend_from_slice(&1.0f32.to_le_bytes());
+    buf.extend_from_slice(&2.0f32.to_le_bytes());
+    let (arrays, metadata) = read_safetensors(&mut buf.as_slice()).unwrap();
+    assert!(metadata.is_empty());
+    assert_eq!(arrays["x"].to_matrix().unwrap(), na::dmatrix![1.0, 2.0]);
+
+    let header = br#"{"x":{"dtype":"F32","shape":[1,2],"data_offsets":[0,16]}}"#;
+    let mut buf = (header.len() as u64).to_le_bytes().to_vec();
+    buf.extend_from_slice(header);
+    buf.extend_from_slice(&[0u8; 8]);
+    assert!(read_safetensors(&mut buf.as_slice()).is_err());
+
+    // Header sizes beyond the limit or the end of the file.
+    for header_len in [u64::MAX, MAX_HEADER_LEN + 1, 1000] {
+        let mut buf = header_len.to_le_bytes().to_vec();
+        buf.extend_from_slice(b"{}");
+        let error = read_safetensors(&mut buf.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
+
+#[test]
+fn test_load_parameters_safetensors_validates_shapes() {
+    use crate::params_extended::ParamsExt;
+    use crate::shared::Shared;
+
+    let mut params = ParamsExt::new(1);
+    params.weight_list[0] = Shared::new(na::dmatrix![1.0, 2.0; 3.0, 4.0]);
+    params.bias_list[0] = Shared::new(na::dvector![0.1, 0.2]);
+    params.gamma_list[0] = Shared::new(na::dvector![1.0, 1.0]);
+    params.beta_list[0] = Shared::new(na::dvector![0.0, 0.0]);
+    let path = std::env::temp_dir().join("load_parameters.safetensors");
+    save_parameters_safetensors(&path, &params, DType::F64, &BTreeMap::new()).unwrap();
+
+    let mut loaded = ParamsExt::new(1);
+    loaded.weight_list[0] = Shared::new(na::DMatrix::<f64>::zeros(2, 2));
+    loaded.bias_list[0] = Shared::new(na::DVector::<f64>::zeros(2));
+    loaded.gamma_list[0] = Shared::new(na::DVector::<f64>::zeros(2));
+    loaded.beta_list[0] = Shared::new(na::DVector::<f64>::zeros(2));
+    load_parameters_safetensors(&path, &loaded).unwrap();
+    assert_eq!(
+        *loaded.weight_list[0].borrow(),
+        *params.weight_list[0].borrow()
+    );
+    assert_eq!(*loaded.bias_list[0].borrow(), *params.bias_list[0].borrow());
+    assert_eq!(
+        *loaded.gamma_list[0].borrow(),
+        *params.gamma_list[0].borrow()
+    );
+
+    let mismatched = ParamsExt::new(1);
+    mismatched.weight_list[0].replace(na::DMatrix::<f64>::zeros(2, 3));
+    let result = load_parameters_safetensors(&path, &mismatched);
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.is_err());
+}
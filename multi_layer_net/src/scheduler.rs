@@ -0,0 +1,222 @@
+use std::io;
+
+use crate::{
+    checkpoint::Checkpoint,
+    float::Float,
+    optimiser::{Optimizer, OptimizerBase},
+    parameters::NamedParameters,
+};
+
+pub mod cosine_annealing;
+pub mod exponential;
+pub mod linear_warmup;
+pub mod one_cycle;
+pub mod reduce_on_plateau;
+pub mod step_decay;
+
+pub trait Schedule {
+    // Returns the learning rate for the `step`-th call to `Scheduler::advance`.
+    // `metric` is only given when the scheduler is advanced with a validation
+    // metric and may be ignored by schedules which do not depend on it.
+    fn learning_rate(&mut self, base_lr: f64, step: usize, metric: Option<f64>) -> f64;
+
+    // Schedules which depend on the metric must be advanced with one.
+    fn needs_metric(&self) -> bool {
+        false
+    }
+
+    // Only schedules which depend on more than the step count need to store
+    // anything.
+    fn save_state(&self, _checkpoint: &mut Checkpoint, _prefix: &str) {}
+
+    fn load_state(&mut self, _checkpoint: &Checkpoint, _prefix: &str) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Wraps an optimiser and rewrites its learning rate every time it is advanced.
+// Whether a step means one iteration or one epoch is up to the caller.
+pub struct Scheduler<O> {
+    optimiser: O,
+    schedule: Box<dyn Schedule>,
+    base_lr: f64,
+    step_count: usize,
+}
+
+impl<O: OptimizerBase> Scheduler<O> {
+    pub fn new(mut optimiser: O, mut schedule: impl Schedule + 'static) -> Self {
+        let base_lr = optimiser.learning_rate();
+        optimiser.set_learning_rate(schedule.learning_rate(base_lr, 0, None));
+        Self {
+            optimiser,
+            schedule: Box::new(schedule),
+            base_lr,
+            step_count: 0,
+        }
+    }
+
+    pub fn advance(&mut self) {
+        assert!(
+            !self.schedule.needs_metric(),
+            "this schedule needs a metric, use advance_with_metric."
+        );
+        self.step_count += 1;
+        let lr = self
+            .schedule
+            .learning_rate(self.base_lr, self.step_count, None);
+        self.optimiser.set_learning_rate(lr);
+    }
+
+    pub fn advance_with_metric(&mut self, metric: f64) {
+        self.step_count += 1;
+        let lr = self
+            .schedule
+            .learning_rate(self.base_lr, self.step_count, Some(metric));
+        self.optimiser.set_learning_rate(lr);
+    }
+
+    pub fn step_count(&self) -> usize {
+        self.step_count
+    }
+
+    pub fn optimiser(&self) -> &O {
+        &self.optimiser
+    }
+
+    pub fn into_inner(self) -> O {
+        self.optimiser
+    }
+}
+
+impl<T: Float, O: Optimizer<T>> Optimizer<T> for Scheduler<O> {
+    fn step(&mut self, key: &str, param: na::DMatrixViewMut<T>, grad: na::DMatrixView<T>) {
+        self.optimiser.step(key, param, grad);
+    }
+
+    fn update(&mut self, params: &dyn NamedParameters<T>, grads: &dyn NamedParameters<T>) {
+        self.optimiser.update(params, grads);
+    }
+}
+
+impl<O: OptimizerBase> OptimizerBase for Scheduler<O> {
+    fn learning_rate(&self) -> f64 {
+        self.optimiser.learning_rate()
+    }
+
+    // Changes the rate the schedule is computed from.
+    fn set_learning_rate(&mut self, lr: f64) {
+        self.base_lr = lr;
+        let lr = self.schedule.learning_rate(lr, self.step_count, None);
+        self.optimiser.set_learning_rate(lr);
+    }
+
+    fn save_state(&self, checkpoint: &mut Checkpoint, prefix: &str) {
+        checkpoint.insert_metadata(&format!("{}base_lr", prefix), self.base_lr);
+        checkpoint.insert_metadata(&format!("{}step_count", prefix), self.step_count);
+        self.schedule
+            .save_state(checkpoint, &format!("{}schedule.", prefix));
+        self.optimiser.save_state(checkpoint, prefix);
+    }
+
+    fn load_state(&mut self, checkpoint: &Checkpoint, prefix: &str) -> io::Result<()> {
+        self.base_lr = checkpoint.metadata(&format!("{}base_lr", prefix))?;
+        self.step_count = checkpoint.metadata(&format!("{}step_count", prefix))?;
+        self.schedule
+            .load_state(checkpoint, &format!("{}schedule.", prefix))?;
+        self.optimiser.load_state(checkpoint, prefix)?;
+        let lr = self
+            .schedule
+            .learning_rate(self.base_lr, self.step_count, None);
+        self.optimiser.set_learning_rate(lr);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_scheduler() {
+    use crate::optimiser::sgd::SGD;
+
+    let mut scheduler = Scheduler::new(SGD::new(0.1), step_decay::StepDecay::new(2, 0.5));
+    assert_eq!(scheduler.learning_rate(), 0.1);
+    scheduler.advance();
+    assert_eq!(scheduler.learning_rate(), 0.1);
+    scheduler.advance();
+    assert_eq!(scheduler.learning_rate(), 0.05);
+    let mut param = na::dmatrix![1.0];
+    scheduler.step("W1", param.as_view_mut(), na::dmatrix![2.0].as_view());
+    assert_eq!(param[0], 0.9);
+    assert_eq!(scheduler.step_count(), 2);
+}
+
+#[test]
+fn test_scheduler_state_round_trip() {
+    use crate::optimiser::momentum::Momentum;
+
+    let mut scheduler = Scheduler::new(
+        Momentum::new(1.0, 0.9),
+        reduce_on_plateau::ReduceOnPlateau::new(0.5, 0, 0.0, 0.0),
+    );
+    let mut param = na::dmatrix![1.0];
+    scheduler.step("W1", param.as_view_mut(), na::dmatrix![1.0].as_view());
+    scheduler.advance_with_metric(1.0);
+    scheduler.advance_with_metric(2.0);
+    assert_eq!(scheduler.learning_rate(), 0.5);
+    let mut checkpoint = Checkpoint::new();
+    scheduler.save_state(&mut checkpoint, "optimiser.");
+    let mut resumed = Scheduler::new(
+        Momentum::new(1.0, 0.9),
+        reduce_on_plateau::ReduceOnPlateau::new(0.5, 0, 0.0, 0.0),
+    );
+    resumed.load_state(&checkpoint, "optimiser.").unwrap();
+    assert_eq!(resumed.step_count(), 2);
+    assert_eq!(resumed.learning_rate(), 0.5);
+    let mut resumed_param = param.clone();
+    for (scheduler, param) in [
+        (&mut scheduler, &mut param),
+        (&mut resumed, &mut resumed_param),
+    ] {
+        scheduler.advance_with_metric(3.0);
+        scheduler.step("W1", param.as_view_mut(), na::dmatrix![1.0].as_view());
+    }
+    assert_eq!(resumed.learning_rate(), 0.25);
+    assert_eq!(param, resumed_param);
+
+    // The base rate comes from the checkpoint rather than the optimiser.
+    scheduler.set_learning_rate(0.1);
+    scheduler.save_state(&mut checkpoint, "optimiser.");
+    let mut restored: Scheduler<Momentum> = Scheduler::new(
+        Momentum::new(1.0, 0.9),
+        reduce_on_plateau::ReduceOnPlateau::new(0.5, 0, 0.0, 0.0),
+    );
+    restored.load_state(&checkpoint, "optimiser.").unwrap();
+    assert_eq!(restored.learning_rate(), 0.1);
+    restored.advance_with_metric(3.0);
+    assert_eq!(restored.learning_rate(), 0.05);
+}
+
+#[test]
+#[should_panic(expected = "this schedule needs a metric")]
+fn test_scheduler_advance_without_metric() {
+    use crate::optimiser::sgd::SGD;
+
+    let mut scheduler = Scheduler::new(
+        SGD::new(0.1),
+        reduce_on_plateau::ReduceOnPlateau::new(0.5, 0, 0.0, 0.0),
+    );
+    scheduler.advance();
+}
+
+#[test]
+#[should_panic(expected = "this schedule needs a metric")]
+fn test_scheduler_warmup_advance_without_metric() {
+    use crate::optimiser::sgd::SGD;
+
+    let mut scheduler = Scheduler::new(
+        SGD::new(0.1),
+        linear_warmup::LinearWarmup::new(
+            2,
+            reduce_on_plateau::ReduceOnPlateau::new(0.5, 0, 0.0, 0.0),
+        ),
+    );
+    scheduler.advance();
+}
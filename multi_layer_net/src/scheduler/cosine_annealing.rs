@@ -0,0 +1,47 @@
+use std::f64::consts::PI;
+
+use super::Schedule;
+
+// Cosine annealing with warm restarts (SGDR). The first cycle lasts `t_0`
+// steps and every following cycle is `t_mult` times longer than the last.
+pub struct CosineAnnealing {
+    t_0: usize,
+    t_mult: usize,
+    eta_min: f64,
+}
+
+impl CosineAnnealing {
+    pub fn new(t_0: usize, t_mult: usize, eta_min: f64) -> Self {
+        assert!(t_0 > 0, "t_0 must be positive.");
+        assert!(t_mult > 0, "t_mult must be positive.");
+        Self {
+            t_0,
+            t_mult,
+            eta_min,
+        }
+    }
+}
+
+impl Schedule for CosineAnnealing {
+    fn learning_rate(&mut self, base_lr: f64, step: usize, _metric: Option<f64>) -> f64 {
+        let mut t_cur = step;
+        let mut t_i = self.t_0;
+        while t_cur >= t_i {
+            t_cur -= t_i;
+            t_i *= self.t_mult;
+        }
+        self.eta_min
+            + (base_lr - self.eta_min) * (1.0 + (PI * t_cur as f64 / t_i as f64).cos()) / 2.0
+    }
+}
+
+#[test]
+fn test_cosine_annealing() {
+    let mut schedule = CosineAnnealing::new(4, 2, 0.0);
+    assert_eq!(schedule.learning_rate(1.0, 0, None), 1.0);
+    assert!((schedule.learning_rate(1.0, 2, None) - 0.5).abs() < 1e-12);
+    // The first restart happens after 4 steps and the second cycle lasts 8.
+    assert_eq!(schedule.learning_rate(1.0, 4, None), 1.0);
+    assert!((schedule.learning_rate(1.0, 8, None) - 0.5).abs() < 1e-12);
+    assert_eq!(schedule.learning_rate(1.0, 12, None), 1.0);
+}
@@ -0,0 +1,24 @@
+use super::Schedule;
+
+pub struct Exponential {
+    gamma: f64,
+}
+
+impl Exponential {
+    pub fn new(gamma: f64) -> Self {
+        Self { gamma }
+    }
+}
+
+impl Schedule for Exponential {
+    fn learning_rate(&mut self, base_lr: f64, step: usize, _metric: Option<f64>) -> f64 {
+        base_lr * self.gamma.powi(step as i32)
+    }
+}
+
+#[test]
+fn test_exponential() {
+    let mut schedule = Exponential::new(0.5);
+    assert_eq!(schedule.learning_rate(0.8, 0, None), 0.8);
+    assert_eq!(schedule.learning_rate(0.8, 3, None), 0.1);
+}
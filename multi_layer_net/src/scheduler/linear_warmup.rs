@@ -0,0 +1,54 @@
+use std::io;
+
+use super::Schedule;
+use crate::checkpoint::Checkpoint;
+
+// Ramps the learning rate up linearly over `warmup_steps` and then hands over
+// to `after`, which sees the step count from the end of the warmup.
+pub struct LinearWarmup {
+    warmup_steps: usize,
+    after: Box<dyn Schedule>,
+}
+
+impl LinearWarmup {
+    pub fn new(warmup_steps: usize, after: impl Schedule + 'static) -> Self {
+        Self {
+            warmup_steps,
+            after: Box::new(after),
+        }
+    }
+}
+
+impl Schedule for LinearWarmup {
+    fn learning_rate(&mut self, base_lr: f64, step: usize, metric: Option<f64>) -> f64 {
+        if step < self.warmup_steps {
+            base_lr * (step + 1) as f64 / self.warmup_steps as f64
+        } else {
+            self.after
+                .learning_rate(base_lr, step - self.warmup_steps, metric)
+        }
+    }
+
+    fn needs_metric(&self) -> bool {
+        self.after.needs_metric()
+    }
+
+    fn save_state(&self, checkpoint: &mut Checkpoint, prefix: &str) {
+        self.after.save_state(checkpoint, prefix);
+    }
+
+    fn load_state(&mut self, checkpoint: &Checkpoint, prefix: &str) -> io::Result<()> {
+        self.after.load_state(checkpoint, prefix)
+    }
+}
+
+#[test]
+fn test_linear_warmup() {
+    use super::step_decay::StepDecay;
+
+    let mut schedule = LinearWarmup::new(4, StepDecay::new(2, 0.5));
+    assert_eq!(schedule.learning_rate(1.0, 0, None), 0.25);
+    assert_eq!(schedule.learning_rate(1.0, 3, None), 1.0);
+    assert_eq!(schedule.learning_rate(1.0, 5, None), 1.0);
+    assert_eq!(schedule.learning_rate(1.0, 6, None), 0.5);
+}
@@ -0,0 +1,71 @@
+use std::f64::consts::PI;
+
+use super::Schedule;
+
+// The one-cycle policy: the learning rate rises from `max_lr / div_factor` to
+// `max_lr` over the first `pct_start` of `total_steps`, then anneals down to
+// `max_lr / (div_factor * final_div_factor)`. The optimiser's own learning
+// rate is ignored.
+pub struct OneCycle {
+    max_lr: f64,
+    total_steps: usize,
+    pct_start: f64,
+    div_factor: f64,
+    final_div_factor: f64,
+}
+
+impl OneCycle {
+    pub fn new(
+        max_lr: f64,
+        total_steps: usize,
+        pct_start: f64,
+        div_factor: f64,
+        final_div_factor: f64,
+    ) -> Self {
+        assert!(total_steps > 1, "total_steps must be greater than 1.");
+        Self {
+            max_lr,
+            total_steps,
+            pct_start,
+            div_factor,
+            final_div_factor,
+        }
+    }
+}
+
+fn cosine_anneal(start: f64, end: f64, pct: f64) -> f64 {
+    end + (start - end) / 2.0 * ((PI * pct).cos() + 1.0)
+}
+
+impl Schedule for OneCycle {
+    fn learning_rate(&mut self, _base_lr: f64, step: usize, _metric: Option<f64>) -> f64 {
+        let initial_lr = self.max_lr / self.div_factor;
+        let min_lr = initial_lr / self.final_div_factor;
+        let warmup_end = (self.pct_start * self.total_steps as f64 - 1.0).max(1.0);
+        let last_step = (self.total_steps - 1) as f64;
+        let step = (step as f64).min(last_step);
+        if step <= warmup_end {
+            cosine_anneal(initial_lr, self.max_lr, step / warmup_end)
+        } else {
+            cosine_anneal(
+                self.max_lr,
+                min_lr,
+                (step - warmup_end) / (last_step - warmup_end),
+            )
+        }
+    }
+}
+
+#[test]
+fn test_one_cycle() {
+    let mut schedule = OneCycle::new(1.0, 11, 0.2, 10.0, 100.0);
+    // The warmup ends at step 0.2 * 11 - 1 = 1.2.
+    assert!((schedule.learning_rate(0.0, 0, None) - 0.1).abs() < 1e-12);
+    assert!(schedule.learning_rate(0.0, 1, None) > 0.9);
+    assert!((schedule.learning_rate(0.0, 10, None) - 0.001).abs() < 1e-12);
+    assert!((schedule.learning_rate(0.0, 20, None) - 0.001).abs() < 1e-12);
+    let lrs: Vec<f64> = (2..11)
+        .map(|step| schedule.learning_rate(0.0, step, None))
+        .collect();
+    assert!(lrs.windows(2).all(|w| w[0] > w[1]));
+}
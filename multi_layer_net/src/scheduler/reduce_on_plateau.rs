@@ -0,0 +1,104 @@
+use std::io;
+
+use super::Schedule;
+use crate::checkpoint::Checkpoint;
+
+// Multiplies the learning rate by `factor` once the metric (e.g. validation
+// loss) has not improved by more than `threshold` relative to the best value
+// for more than `patience` consecutive steps. A new base rate, e.g. from
+// `Scheduler::set_learning_rate`, replaces the reduced one.
+pub struct ReduceOnPlateau {
+    factor: f64,
+    patience: usize,
+    threshold: f64,
+    min_lr: f64,
+    best: f64,
+    num_bad_steps: usize,
+    // The current rate and the base rate it was reduced from.
+    lr: Option<(f64, f64)>,
+}
+
+impl ReduceOnPlateau {
+    pub fn new(factor: f64, patience: usize, threshold: f64, min_lr: f64) -> Self {
+        Self {
+            factor,
+            patience,
+            threshold,
+            min_lr,
+            best: f64::INFINITY,
+            num_bad_steps: 0,
+            lr: None,
+        }
+    }
+}
+
+impl Schedule for ReduceOnPlateau {
+    fn learning_rate(&mut self, base_lr: f64, _step: usize, metric: Option<f64>) -> f64 {
+        let lr = match self.lr {
+            Some((lr, base)) if base == base_lr => lr,
+            _ => base_lr,
+        };
+        self.lr = Some((lr, base_lr));
+        let Some(metric) = metric else {
+            return lr;
+        };
+        if metric < self.best * (1.0 - self.threshold) {
+            self.best = metric;
+            self.num_bad_steps = 0;
+        } else {
+            self.num_bad_steps += 1;
+        }
+        if self.num_bad_steps > self.patience {
+            self.lr = Some(((lr * self.factor).max(self.min_lr), base_lr));
+            self.num_bad_steps = 0;
+        }
+        self.lr.unwrap().0
+    }
+
+    fn needs_metric(&self) -> bool {
+        true
+    }
+
+    fn save_state(&self, checkpoint: &mut Checkpoint, prefix: &str) {
+        checkpoint.insert_metadata(&format!("{}best", prefix), self.best);
+        checkpoint.insert_metadata(&format!("{}num_bad_steps", prefix), self.num_bad_steps);
+        if let Some((lr, base_lr)) = self.lr {
+            checkpoint.insert_metadata(&format!("{}lr", prefix), lr);
+            checkpoint.insert_metadata(&format!("{}base_lr", prefix), base_lr);
+        }
+    }
+
+    fn load_state(&mut self, checkpoint: &Checkpoint, prefix: &str) -> io::Result<()> {
+        self.best = checkpoint.metadata(&format!("{}best", prefix))?;
+        self.num_bad_steps = checkpoint.metadata(&format!("{}num_bad_steps", prefix))?;
+        self.lr = match checkpoint.metadata(&format!("{}lr", prefix)) {
+            Ok(lr) => Some((lr, checkpoint.metadata(&format!("{}base_lr", prefix))?)),
+            Err(_) => None,
+        };
+        Ok(())
+    }
+}
+
+#[test]
+fn test_reduce_on_plateau() {
+    let mut schedule = ReduceOnPlateau::new(0.5, 1, 0.0, 0.2);
+    assert_eq!(schedule.learning_rate(1.0, 0, None), 1.0);
+    assert_eq!(schedule.learning_rate(1.0, 1, Some(2.0)), 1.0);
+    assert_eq!(schedule.learning_rate(1.0, 2, Some(1.0)), 1.0);
+    assert_eq!(schedule.learning_rate(1.0, 3, Some(1.5)), 1.0);
+    assert_eq!(schedule.learning_rate(1.0, 4, Some(1.2)), 0.5);
+    assert_eq!(schedule.learning_rate(1.0, 5, Some(1.0)), 0.5);
+    assert_eq!(schedule.learning_rate(1.0, 6, Some(1.0)), 0.25);
+    assert_eq!(schedule.learning_rate(1.0, 7, Some(1.0)), 0.25);
+    assert_eq!(schedule.learning_rate(1.0, 8, Some(1.0)), 0.2);
+}
+
+#[test]
+fn test_reduce_on_plateau_new_base_lr() {
+    let mut schedule = ReduceOnPlateau::new(0.5, 0, 0.0, 0.0);
+    assert_eq!(schedule.learning_rate(1.0, 1, Some(1.0)), 1.0);
+    assert_eq!(schedule.learning_rate(1.0, 2, Some(1.0)), 0.5);
+    // The reductions start again from a new base rate.
+    assert_eq!(schedule.learning_rate(0.1, 2, None), 0.1);
+    assert_eq!(schedule.learning_rate(0.1, 3, Some(1.0)), 0.05);
+}
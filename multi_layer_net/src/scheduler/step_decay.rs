@@ -0,0 +1,31 @@
+use super::Schedule;
+
+// Multiplies the learning rate by `gamma` every `step_size` steps.
+pub struct StepDecay {
+    step_size: usize,
+    gamma: f64,
+}
+
+impl StepDecay {
+    pub fn new(step_size: usize, gamma: f64) -> Self {
+        assert!(step_size > 0, "step_size must be positive.");
+        Self { step_size, gamma }
+    }
+}
+
+impl Schedule for StepDecay {
+    fn learning_rate(&mut self, base_lr: f64, step: usize, _metric: Option<f64>) -> f64 {
+        base_lr * self.gamma.powi((step / self.step_size) as i32)
+    }
+}
+
+#[test]
+fn test_step_decay() {
+    let mut schedule = StepDecay::new(3, 0.1);
+    let lrs: Vec<f64> = (0..7)
+        .map(|step| schedule.learning_rate(1.0, step, None))
+        .collect();
+    assert_eq!(lrs[0..3], [1.0, 1.0, 1.0]);
+    assert!((lrs[3] - 0.1).abs() < 1e-12);
+    assert!((lrs[6] - 0.01).abs() < 1e-12);
+}
@@ -0,0 +1,205 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+#[cfg(debug_assertions)]
+use std::cell::RefCell;
+
+// A tensor shared between a network's parameter lists and the layer that uses
+// it, like `Rc<RefCell<_>>` but `Send + Sync` so that networks can be moved to
+// and trained on other threads. Borrows on different threads which conflict
+// block. On the same thread they would deadlock, so debug builds panic
+// instead, as `RefCell` does. A panic while a borrow is held does not poison
+// the value, as a half-updated tensor is no worse than `RefCell` would leave
+// it.
+pub struct Shared<M>(Arc<RwLock<M>>);
+
+impl<M> Shared<M> {
+    pub fn new(value: M) -> Self {
+        Self(Arc::new(RwLock::new(value)))
+    }
+
+    pub fn borrow(&self) -> Ref<'_, M> {
+        #[cfg(debug_assertions)]
+        let held = Held::new(self.address(), false);
+        Ref {
+            guard: self.0.read().unwrap_or_else(PoisonError::into_inner),
+            #[cfg(debug_assertions)]
+            _held: held,
+        }
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, M> {
+        #[cfg(debug_assertions)]
+        let held = Held::new(self.address(), true);
+        RefMut {
+            guard: self.0.write().unwrap_or_else(PoisonError::into_inner),
+            #[cfg(debug_assertions)]
+            _held: held,
+        }
+    }
+
+    pub fn replace(&self, value: M) -> M {
+        std::mem::replace(&mut *self.borrow_mut(), value)
+    }
+
+    // Whether both handles refer to the same tensor.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    #[cfg(debug_assertions)]
+    fn address(&self) -> usize {
+        Arc::as_ptr(&self.0) as *const () as usize
+    }
+}
+
+pub struct Ref<'a, M> {
+    guard: RwLockReadGuard<'a, M>,
+    #[cfg(debug_assertions)]
+    _held: Held,
+}
+
+impl<M> Deref for Ref<'_, M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.guard
+    }
+}
+
+pub struct RefMut<'a, M> {
+    guard: RwLockWriteGuard<'a, M>,
+    #[cfg(debug_assertions)]
+    _held: Held,
+}
+
+impl<M> Deref for RefMut<'_, M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.guard
+    }
+}
+
+impl<M> DerefMut for RefMut<'_, M> {
+    fn deref_mut(&mut self) -> &mut M {
+        &mut self.guard
+    }
+}
+
+// The values the current thread has borrowed, by address, and whether
+// mutably.
+#[cfg(debug_assertions)]
+thread_local! {
+    static HELD: RefCell<Vec<(usize, bool)>> = const { RefCell::new(Vec::new()) };
+}
+
+// Records a borrow for as long as it is held, after checking that it does
+// not conflict with one the thread already holds.
+#[cfg(debug_assertions)]
+struct Held(usize, bool);
+
+#[cfg(debug_assertions)]
+impl Held {
+    fn new(address: usize, mutable: bool) -> Self {
+        HELD.with(|held| {
+            let mut held = held.borrow_mut();
+            // The same messages as `RefCell`'s.
+            if held
+                .iter()
+                .any(|&(other, other_mutable)| other == address && (mutable || other_mutable))
+            {
+                if mutable {
+                    panic!("already borrowed");
+                } else {
+                    panic!("already mutably borrowed");
+                }
+            }
+            held.push((address, mutable));
+        });
+        Self(address, mutable)
+    }
+}
+
+#[cfg(debug_assertions)]
+impl Drop for Held {
+    fn drop(&mut self) {
+        // Ignored while the thread is being torn down.
+        let _ = HELD.try_with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(idx) = held.iter().rposition(|&entry| entry == (self.0, self.1)) {
+                held.swap_remove(idx);
+            }
+        });
+    }
+}
+
+// Cloning shares the value, as with `Rc`. Copy it with
+// `Shared::new(x.borrow().clone())` to get an independent one.
+impl<M> Clone for Shared<M> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<M: fmt::Debug> fmt::Debug for Shared<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.borrow().fmt(f)
+    }
+}
+
+#[test]
+fn test_shared() {
+    let a = Shared::new(na::dvector![1.0, 2.0]);
+    let b = a.clone();
+    b.borrow_mut()[0] = 3.0;
+    assert_eq!(a.borrow()[0], 3.0);
+    assert!(a.ptr_eq(&b));
+    assert!(!a.ptr_eq(&Shared::new(a.borrow().clone())));
+
+    // Updated from another thread.
+    std::thread::spawn(move || b.borrow_mut()[1] = 4.0)
+        .join()
+        .unwrap();
+    assert_eq!(*a.borrow(), na::dvector![3.0, 4.0]);
+}
+
+#[test]
+fn test_shared_poisoning() {
+    let a = Shared::new(vec![1.0]);
+    let b = a.clone();
+    // A panic while the value is borrowed leaves it usable.
+    let result = std::thread::spawn(move || {
+        let mut v = b.borrow_mut();
+        v[0] = 2.0;
+        panic!("while borrowed");
+    })
+    .join();
+    assert!(result.is_err());
+    assert_eq!(*a.borrow(), vec![2.0]);
+    a.borrow_mut()[0] = 3.0;
+    // Nested shared borrows are fine, as with `RefCell`.
+    let (x, y) = (a.borrow(), a.borrow());
+    assert_eq!(x[0], y[0]);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "already borrowed")]
+fn test_shared_nested_borrow_mut() {
+    let a = Shared::new(1.0);
+    let _x = a.borrow();
+    a.clone().borrow_mut();
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "already mutably borrowed")]
+fn test_shared_borrow_while_mutably_borrowed() {
+    let a = Shared::new(1.0);
+    let _x = a.borrow_mut();
+    a.borrow();
+}
@@ -0,0 +1,551 @@
+use std::{io, path::Path};
+
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    callbacks::{Callback, Control, EpochLogs},
+    checkpoint::Checkpoint,
+    float::{cast_matrix, Float},
+    metrics,
+    multi_layer_net_extended::MultiLayerNetExtended,
+    optimiser::Optimizer,
+};
+
+// What `Trainer` needs from a network. Inputs hold one sample per row and
+// targets are one-hot rows, and `T` is the precision of the inputs and
+// outputs. Losses are `f64` whatever the precision.
+pub trait Model<T: Float = f64> {
+    fn predict(&mut self, x: &na::DMatrix<T>, train_flg: bool) -> na::DMatrix<T>;
+
+    fn loss(&mut self, x: &na::DMatrix<T>, t: &na::DMatrix<u8>, train_flg: bool) -> f64;
+
+    // Computes the gradients of the loss for the batch and keeps them until
+    // the next call to `update`.
+    fn gradient(&mut self, x: &na::DMatrix<T>, t: &na::DMatrix<u8>);
+
+    fn update(&mut self, optimiser: &mut dyn Optimizer<T>);
+
+    // Used by `ModelCheckpoint`; models which cannot be saved need not
+    // implement it.
+    fn save(&self, _path: &Path) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this model cannot be saved.",
+        ))
+    }
+}
+
+impl<T: Float> Model<T> for MultiLayerNetExtended<T> {
+    fn predict(&mut self, x: &na::DMatrix<T>, train_flg: bool) -> na::DMatrix<T> {
+        MultiLayerNetExtended::predict(self, x, train_flg)
+    }
+
+    fn loss(&mut self, x: &na::DMatrix<T>, t: &na::DMatrix<u8>, train_flg: bool) -> f64 {
+        MultiLayerNetExtended::loss(self, x, t, train_flg)
+    }
+
+    fn gradient(&mut self, x: &na::DMatrix<T>, t: &na::DMatrix<u8>) {
+        MultiLayerNetExtended::gradient(self, x, t)
+    }
+
+    fn update(&mut self, optimiser: &mut dyn Optimizer<T>) {
+        optimiser.update(&self.params, &self.grads);
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        MultiLayerNetExtended::save(self, path)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dataset<T: Float = f64> {
+    pub x: na::DMatrix<T>,
+    pub t: na::DMatrix<u8>,
+}
+
+impl<T: Float> Dataset<T> {
+    pub fn new(x: na::DMatrix<T>, t: na::DMatrix<u8>) -> Self {
+        assert_eq!(
+            x.nrows(),
+            t.nrows(),
+            "inputs and targets must have the same number of rows."
+        );
+        Self { x, t }
+    }
+
+    // MNIST images are loaded one per column, so they are transposed into
+    // rows while the labels already are.
+    pub fn from_image_columns<R1, C1, S1, R2, C2, S2>(
+        images: &na::Matrix<T, R1, C1, S1>,
+        labels: &na::Matrix<u8, R2, C2, S2>,
+    ) -> Self
+    where
+        R1: na::Dim,
+        C1: na::Dim,
+        S1: na::RawStorage<T, R1, C1>,
+        R2: na::Dim,
+        C2: na::Dim,
+        S2: na::RawStorage<u8, R2, C2>,
+    {
+        Self::new(
+            na::DMatrix::<T>::from_fn(images.ncols(), images.nrows(), |i, j| images[(j, i)]),
+            na::DMatrix::<u8>::from_fn(labels.nrows(), labels.ncols(), |i, j| labels[(i, j)]),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.x.nrows()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Converts the inputs to another precision, e.g. to train in `f32` on
+    // data loaded as `f64`.
+    pub fn cast<U: Float>(&self) -> Dataset<U> {
+        Dataset::new(cast_matrix(&self.x), self.t.clone())
+    }
+
+    pub fn batch(&self, indices: &[usize]) -> (na::DMatrix<T>, na::DMatrix<u8>) {
+        (self.x.select_rows(indices), self.t.select_rows(indices))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct History {
+    // One entry per iteration.
+    pub loss: Vec<f64>,
+    // One entry per epoch.
+    pub train_accuracy: Vec<f64>,
+    pub val_loss: Vec<f64>,
+    pub val_accuracy: Vec<f64>,
+}
+
+impl History {
+    pub fn epochs(&self) -> usize {
+        self.train_accuracy.len()
+    }
+
+    pub fn append(&mut self, other: History) {
+        self.loss.extend(other.loss);
+        self.train_accuracy.extend(other.train_accuracy);
+        self.val_loss.extend(other.val_loss);
+        self.val_accuracy.extend(other.val_accuracy);
+    }
+
+    pub fn save_state(&self, checkpoint: &mut Checkpoint, prefix: &str) {
+        checkpoint.insert_list(&format!("{}loss", prefix), &self.loss);
+        checkpoint.insert_list(&format!("{}train_accuracy", prefix), &self.train_accuracy);
+        checkpoint.insert_list(&format!("{}val_loss", prefix), &self.val_loss);
+        checkpoint.insert_list(&format!("{}val_accuracy", prefix), &self.val_accuracy);
+    }
+
+    pub fn load_state(checkpoint: &Checkpoint, prefix: &str) -> io::Result<Self> {
+        Ok(Self {
+            loss: checkpoint.list(&format!("{}loss", prefix))?,
+            train_accuracy: checkpoint.list(&format!("{}train_accuracy", prefix))?,
+            val_loss: checkpoint.list(&format!("{}val_loss", prefix))?,
+            val_accuracy: checkpoint.list(&format!("{}val_accuracy", prefix))?,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Evaluation {
+    pub loss: f64,
+    pub accuracy: f64,
+}
+
+// Runs the mini-batch training loop of the book: every epoch the training
+// data is shuffled and split into batches, each of which is used for one
+// gradient step, and the accuracy is evaluated at the end of the epoch.
+// Anything else, such as printing progress, is left to callbacks.
+pub struct Trainer {
+    rng: ChaCha8Rng,
+}
+
+impl Default for Trainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Trainer {
+    pub fn new() -> Self {
+        Self {
+            rng: ChaCha8Rng::from_entropy(),
+        }
+    }
+
+    // Shuffles the same way on every run. Together with a network built from
+    // a generator with a fixed seed this makes training reproducible.
+    pub fn seeded(seed: u64) -> Self {
+        Self {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn with_rng(mut self, rng: ChaCha8Rng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    // The generator used for shuffling, e.g. to store it in a checkpoint.
+    pub fn rng(&self) -> &ChaCha8Rng {
+        &self.rng
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn fit<T: Float>(
+        &mut self,
+        model: &mut dyn Model<T>,
+        optimiser: &mut dyn Optimizer<T>,
+        train: &Dataset<T>,
+        val: Option<&Dataset<T>>,
+        epochs: usize,
+        batch_size: usize,
+        callbacks: &mut [&mut dyn Callback<T>],
+    ) -> History {
+        assert!(batch_size > 0, "batch_size must be positive.");
+        let mut history = History::default();
+        let mut indices: Vec<usize> = (0..train.len()).collect();
+        for epoch in 0..epochs {
+            callbacks.iter_mut().for_each(|c| c.on_epoch_begin(epoch));
+            indices.shuffle(&mut self.rng);
+            let mut control = Control::Continue;
+            let mut epoch_loss = 0.0;
+            let mut batch_count = 0;
+            for (batch, batch_mask) in indices.chunks(batch_size).enumerate() {
+                callbacks
+                    .iter_mut()
+                    .for_each(|c| c.on_batch_begin(epoch, batch));
+                let (x, t) = train.batch(batch_mask);
+                model.gradient(&x, &t);
+                model.update(optimiser);
+                let loss = model.loss(&x, &t, false);
+                history.loss.push(loss);
+                epoch_loss += loss;
+                batch_count += 1;
+                control = notify(callbacks, |c| c.on_batch_end(epoch, batch, loss));
+                if control == Control::Stop {
+                    break;
+                }
+            }
+            if control == Control::Stop {
+                break;
+            }
+            let loss = epoch_loss / batch_count.max(1) as f64;
+            let logs = self.end_epoch(model, train, val, batch_size, loss, &mut history);
+            if notify(callbacks, |c| c.on_epoch_end(epoch, &logs, &*model)) == Control::Stop {
+                break;
+            }
+        }
+        history
+    }
+
+    fn end_epoch<T: Float>(
+        &self,
+        model: &mut dyn Model<T>,
+        train: &Dataset<T>,
+        val: Option<&Dataset<T>>,
+        batch_size: usize,
+        loss: f64,
+        history: &mut History,
+    ) -> EpochLogs {
+        let train_accuracy = self.evaluate(model, train, batch_size).accuracy;
+        history.train_accuracy.push(train_accuracy);
+        let val = val.map(|val| self.evaluate(model, val, batch_size));
+        if let Some(val) = val {
+            history.val_loss.push(val.loss);
+            history.val_accuracy.push(val.accuracy);
+        }
+        EpochLogs {
+            loss,
+            train_accuracy,
+            val_loss: val.map(|val| val.loss),
+            val_accuracy: val.map(|val| val.accuracy),
+        }
+    }
+
+    // Averages the loss and accuracy over `data` in batches, so that large
+    // datasets need not go through the network at once.
+    pub fn evaluate<T: Float>(
+        &self,
+        model: &mut dyn Model<T>,
+        data: &Dataset<T>,
+        batch_size: usize,
+    ) -> Evaluation {
+        assert!(batch_size > 0, "batch_size must be positive.");
+        let mut loss = 0.0;
+        let mut correct = 0;
+        for start in (0..data.len()).step_by(batch_size) {
+            let len = batch_size.min(data.len() - start);
+            let x = data.x.rows(start, len).into_owned();
+            let t = data.t.rows(start, len).into_owned();
+            loss += model.loss(&x, &t, false) * len as f64;
+            let y = model.predict(&x, false);
+            correct += (metrics::accuracy(&y, &t) * len as f64).round() as usize;
+        }
+        let len = data.len().max(1) as f64;
+        Evaluation {
+            loss: loss / len,
+            accuracy: correct as f64 / len,
+        }
+    }
+
+    pub fn predict<T: Float>(
+        &self,
+        model: &mut dyn Model<T>,
+        x: &na::DMatrix<T>,
+        batch_size: usize,
+    ) -> na::DMatrix<T> {
+        assert!(batch_size > 0, "batch_size must be positive.");
+        let outputs = (0..x.nrows())
+            .step_by(batch_size)
+            .map(|start| {
+                let len = batch_size.min(x.nrows() - start);
+                model.predict(&x.rows(start, len).into_owned(), false)
+            })
+            .collect::<Vec<na::DMatrix<T>>>();
+        let ncols = outputs.first().map_or(0, |y| y.ncols());
+        let mut y = na::DMatrix::<T>::zeros(x.nrows(), ncols);
+        let mut start = 0;
+        for output in outputs {
+            y.rows_mut(start, output.nrows()).copy_from(&output);
+            start += output.nrows();
+        }
+        y
+    }
+}
+
+// Every callback is notified even when an earlier one asks to stop.
+fn notify<T: Float>(
+    callbacks: &mut [&mut dyn Callback<T>],
+    mut f: impl FnMut(&mut dyn Callback<T>) -> Control,
+) -> Control {
+    callbacks
+        .iter_mut()
+        .map(|c| f(&mut **c))
+        .fold(Control::Continue, |control, c| {
+            if c == Control::Stop {
+                Control::Stop
+            } else {
+                control
+            }
+        })
+}
+
+// Two well separated Gaussian blobs in 2D, labelled by blob.
+#[cfg(test)]
+pub(crate) fn blobs(n: usize, seed: u64) -> Dataset {
+    use rand::Rng;
+
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut x = na::DMatrix::<f64>::zeros(n, 2);
+    let mut t = na::DMatrix::<u8>::zeros(n, 2);
+    for i in 0..n {
+        let class = i % 2;
+        let centre = if class == 0 { -2.0 } else { 2.0 };
+        x[(i, 0)] = centre + rng.sample::<f64, _>(rand_distr::StandardNormal) * 0.5;
+        x[(i, 1)] = centre + rng.sample::<f64, _>(rand_distr::StandardNormal) * 0.5;
+        t[(i, class)] = 1;
+    }
+    Dataset::new(x, t)
+}
+
+// One-hot targets for `n` rows whose labels cycle through `classes`.
+#[cfg(test)]
+pub(crate) fn one_hot_targets(n: usize, classes: usize) -> na::DMatrix<u8> {
+    na::DMatrix::<u8>::from_fn(n, classes, |i, j| u8::from(i % classes == j))
+}
+
+#[test]
+fn test_fit_evaluate_predict() {
+    use crate::optimiser::sgd::SGD;
+
+    let train = blobs(200, 0);
+    let val = blobs(100, 1);
+    let mut network = MultiLayerNetExtended::new_with_rng(
+        2,
+        vec![8],
+        2,
+        0.0,
+        "he",
+        "relu",
+        &mut ChaCha8Rng::seed_from_u64(42),
+    );
+    let mut optimiser = SGD::new(0.1);
+    let mut trainer = Trainer::seeded(42);
+    let history = trainer.fit(
+        &mut network,
+        &mut optimiser,
+        &train,
+        Some(&val),
+        5,
+        32,
+        &mut [],
+    );
+    assert_eq!(history.epochs(), 5);
+    // 200 samples in batches of 32 make 7 iterations per epoch.
+    assert_eq!(history.loss.len(), 35);
+    assert_eq!(history.val_loss.len(), 5);
+    let first_epoch = history.loss[..7].iter().sum::<f64>();
+    let last_epoch = history.loss[28..].iter().sum::<f64>();
+    assert!(last_epoch < first_epoch);
+    assert!(*history.val_accuracy.last().unwrap() > 0.9);
+
+    let evaluation = trainer.evaluate(&mut network, &val, 30);
+    assert_eq!(evaluation.accuracy, *history.val_accuracy.last().unwrap());
+    let y = trainer.predict(&mut network, &val.x, 30);
+    assert_eq!(y.shape(), (100, 2));
+    assert_eq!(y, network.predict(&val.x, false));
+}
+
+#[test]
+fn test_fit_f32() {
+    use crate::optimiser::momentum::Momentum;
+
+    // The same network and data in both precisions, with the weights drawn in
+    // f64 and rounded.
+    let train = blobs(200, 0);
+    let val = blobs(100, 1);
+    let network = |rng: &mut ChaCha8Rng| {
+        MultiLayerNetExtended::<f64>::new_with_rng(2, vec![8, 8], 2, 0.01, "he", "relu", rng)
+    };
+    let mut single = MultiLayerNetExtended::<f32>::new_with_rng(
+        2,
+        vec![8, 8],
+        2,
+        0.01,
+        "he",
+        "relu",
+        &mut ChaCha8Rng::seed_from_u64(7),
+    );
+    let mut double = network(&mut ChaCha8Rng::seed_from_u64(7));
+    let single_history = Trainer::seeded(7).fit(
+        &mut single,
+        &mut Momentum::new(0.05, 0.9),
+        &train.cast(),
+        Some(&val.cast()),
+        3,
+        20,
+        &mut [],
+    );
+    let double_history = Trainer::seeded(7).fit(
+        &mut double,
+        &mut Momentum::new(0.05, 0.9),
+        &train,
+        Some(&val),
+        3,
+        20,
+        &mut [],
+    );
+    assert_eq!(single_history.loss.len(), double_history.loss.len());
+    for (a, b) in single_history.loss.iter().zip(double_history.loss.iter()) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+    assert!(*single_history.val_accuracy.last().unwrap() > 0.9);
+    let y: na::DMatrix<f32> = single.predict(&val.cast().x, false);
+    assert!((y.map(f64::from) - double.predict(&val.x, false)).amax() < 1e-3);
+}
+
+#[test]
+fn test_seed_determines_training_run() {
+    use crate::optimiser::adam::Adam;
+
+    let train = blobs(100, 0);
+    let run = |seed| {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut network =
+            MultiLayerNetExtended::new_with_rng(2, vec![6, 6], 2, 0.01, "he", "relu", &mut rng);
+        let history = Trainer::seeded(seed).fit(
+            &mut network,
+            &mut Adam::new(0.01, 0.9, 0.999),
+            &train,
+            None,
+            3,
+            16,
+            &mut [],
+        );
+        (history, network.to_checkpoint())
+    };
+    assert_eq!(run(3), run(3));
+    assert_ne!(run(3).0.loss, run(4).0.loss);
+}
+
+#[test]
+fn test_history_state_round_trip() {
+    let history = History {
+        loss: vec![1.0, 0.5],
+        train_accuracy: vec![0.75],
+        val_loss: vec![0.6],
+        val_accuracy: vec![0.7],
+    };
+    let mut checkpoint = Checkpoint::new();
+    history.save_state(&mut checkpoint, "history.");
+    assert_eq!(
+        History::load_state(&checkpoint, "history.").unwrap(),
+        history
+    );
+    let mut appended = history.clone();
+    appended.append(history);
+    assert_eq!(appended.epochs(), 2);
+}
+
+#[test]
+fn test_fit_stops_on_callbacks() {
+    use crate::{
+        callbacks::{early_stopping::EarlyStopping, terminate_on_nan::TerminateOnNaN, Monitor},
+        optimiser::sgd::SGD,
+    };
+
+    let train = blobs(100, 0);
+    let mut network = MultiLayerNetExtended::new(2, vec![4], 2, 0.0, "he", "relu");
+    let mut trainer = Trainer::seeded(0);
+    // A huge learning rate makes the loss overflow within a few batches.
+    let mut terminate_on_nan = TerminateOnNaN::new();
+    let history = trainer.fit(
+        &mut network,
+        &mut SGD::new(1e300),
+        &train,
+        None,
+        10,
+        10,
+        &mut [&mut terminate_on_nan],
+    );
+    let (epoch, batch) = terminate_on_nan.terminated_at().unwrap();
+    assert_eq!(history.loss.len(), epoch * 10 + batch + 1);
+    assert!(!history.loss.last().unwrap().is_finite());
+
+    let mut network = MultiLayerNetExtended::new(2, vec![4], 2, 0.0, "he", "relu");
+    // Nothing can improve the training accuracy by more than 1.
+    let mut early_stopping = EarlyStopping::new(Monitor::TrainAccuracy, 2, 1.0);
+    let history = trainer.fit(
+        &mut network,
+        &mut SGD::new(0.1),
+        &train,
+        None,
+        10,
+        10,
+        &mut [&mut early_stopping],
+    );
+    assert_eq!(early_stopping.stopped_epoch(), Some(2));
+    assert_eq!(history.epochs(), 3);
+}
+
+#[test]
+#[should_panic(expected = "batch_size must be positive.")]
+fn test_evaluate_rejects_empty_batches() {
+    let mut network: MultiLayerNetExtended = MultiLayerNetExtended::new_with_rng(
+        2,
+        vec![4],
+        2,
+        0.0,
+        "he",
+        "relu",
+        &mut ChaCha8Rng::seed_from_u64(0),
+    );
+    Trainer::seeded(0).evaluate(&mut network, &blobs(10, 0), 0);
+}
@@ -1,6 +1,6 @@
 extern crate nalgebra as na;
 use flate2::bufread::GzDecoder;
-use nalgebra::{Const, Dyn, Scalar};
+use nalgebra::{Const, Dyn, RealField, Scalar};
 use reqwest::header::USER_AGENT;
 use std::{
     fs::{self, File},
@@ -9,6 +9,9 @@ use std::{
 };
 
 const URL_BASE: &str = "http://yann.lecun.com/exdb/mnist/";
+const SAMPLE_WEIGHT_URL_BASE: &str =
+    "https://github.com/oreilly-japan/deep-learning-from-scratch/raw/master/ch03/";
+const SAMPLE_WEIGHT_FILE_NAME: &str = "sample_weight.pkl";
 pub enum DatasetType {
     TrainImg,
     TrainLabel,
@@ -37,7 +40,7 @@ impl DatasetType {
 }
 
 pub type ImageVec = ImageBase<u8>;
-pub type NormalisedImageVec = ImageBase<f64>;
+pub type NormalisedImageVec<T = f64> = ImageBase<T>;
 
 pub struct ImageBase<T: Clone + Scalar> {
     image_vec: Vec<na::OMatrix<T, Const<28>, Const<28>>>,
@@ -109,12 +112,26 @@ pub fn load_image(_type: DatasetType, dataset_dir: &Path) -> ImageVec {
 }
 
 pub fn load_normalised_image(_type: DatasetType, dataset_dir: &Path) -> NormalisedImageVec {
-    let x = load_image(_type, dataset_dir);
-    let mut vec: Vec<na::OMatrix<f64, Const<28>, Const<28>>> = vec![];
-    x.as_ref().iter().for_each(|t| -> () {
-        let mut matrix = t.cast::<f64>();
-        matrix.apply(|t| *t /= 255.0);
-        vec.push(matrix);
+    load_normalised_image_as::<f64>(_type, dataset_dir)
+}
+
+// Loads the images in any floating-point precision, e.g. `f32` to halve the
+// memory taken by the training set.
+pub fn load_normalised_image_as<T>(_type: DatasetType, dataset_dir: &Path) -> NormalisedImageVec<T>
+where
+    T: RealField + Copy,
+{
+    normalise(&load_image(_type, dataset_dir))
+}
+
+// Scales every pixel into [0, 1].
+pub fn normalise<T>(images: &ImageVec) -> NormalisedImageVec<T>
+where
+    T: RealField + Copy,
+{
+    let mut vec: Vec<na::OMatrix<T, Const<28>, Const<28>>> = vec![];
+    images.as_ref().iter().for_each(|t| -> () {
+        vec.push(t.map(|t| na::convert::<f64, T>(t as f64 / 255.0)));
     });
     vec.into()
 }
@@ -123,12 +140,19 @@ pub fn init_mnist() -> PathBuf {
     let dataset_dir = std::env::current_dir().unwrap().join("dataset");
     DatasetType::values()
         .iter()
-        .for_each(|v| download(&format!("{}.gz", v.file_name()), &dataset_dir));
+        .for_each(|v| download(URL_BASE, &format!("{}.gz", v.file_name()), &dataset_dir));
     decode_gzip_files(&dataset_dir);
     dataset_dir
 }
 
-fn download(file_name: &str, dataset_dir: &Path) {
+// Downloads the book's pretrained weights for chapter 3 and returns their path.
+pub fn init_sample_weight() -> PathBuf {
+    let dataset_dir = std::env::current_dir().unwrap().join("dataset");
+    download(SAMPLE_WEIGHT_URL_BASE, SAMPLE_WEIGHT_FILE_NAME, &dataset_dir);
+    dataset_dir.join(SAMPLE_WEIGHT_FILE_NAME)
+}
+
+fn download(url_base: &str, file_name: &str, dataset_dir: &Path) {
     let _ = fs::create_dir(dataset_dir);
     let file_path = dataset_dir.join(file_name);
     if file_path.exists() {
@@ -137,7 +161,7 @@ fn download(file_name: &str, dataset_dir: &Path) {
     println!("downloading {} now in progress...", file_name);
     let client = reqwest::blocking::Client::new();
     let bytes = client
-        .get(URL_BASE.to_string() + file_name)
+        .get(url_base.to_string() + file_name)
         .header(
             USER_AGENT,
             "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:47.0) Gecko/20100101 Firefox/47.0",
@@ -174,7 +198,7 @@ fn decode_gzip_files(dataset_dir: &Path) {
     let dir = fs::read_dir(dataset_dir).unwrap();
     for item in dir.into_iter() {
         let path = &item.as_ref().unwrap().path();
-        if path.file_name().unwrap() == ".DS_Store" || path.extension().is_none() {
+        if path.file_name().unwrap() == ".DS_Store" || path.extension() != Some("gz".as_ref()) {
             continue;
         }
         let file = File::open(path).unwrap();
@@ -249,3 +273,17 @@ fn test_as_one_hot() {
     let label = Label::from(vec![2, 8, 2]);
     let one_hot = label.as_one_hot();
 }
+
+#[test]
+fn test_normalise() {
+    let mut image = na::OMatrix::<u8, Const<28>, Const<28>>::zeros();
+    image[(0, 0)] = 255;
+    image[(3, 5)] = 51;
+    let images: ImageVec = vec![image].into();
+    let single = normalise::<f32>(&images);
+    let double = normalise::<f64>(&images);
+    assert_eq!(single.as_ref()[0][(0, 0)], 1.0f32);
+    assert_eq!(single.as_ref()[0][(3, 5)], 0.2f32);
+    assert_eq!(double.as_ref()[0][(3, 5)], 0.2f64);
+    assert_eq!(double.flatten().column(0).sum(), 1.2);
+}
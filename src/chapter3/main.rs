@@ -1,14 +1,11 @@
+mod neural_net_mnist;
 mod show_mnist;
 
 extern crate nalgebra as na;
 use nalgebra::RowDVector;
-use plotters::{
-    backend::BitMapBackend,
-    chart::ChartBuilder,
-    drawing::IntoDrawingArea,
-    series::LineSeries,
-    style::{IntoFont, RED, WHITE},
-};
+use std::path::Path;
+
+use multi_layer_net::plot::{LinePlot, Series};
 
 struct Network {
     w1: na::Matrix2x3<f64>,
@@ -117,21 +114,13 @@ fn test_step_function() {
         .collect();
     let y = step_function(x.clone().into());
 
-    let root = BitMapBackend::new("step_function.png", (640, 480)).into_drawing_area();
-    root.fill(&WHITE).unwrap();
-    let mut plot = ChartBuilder::on(&root)
-        .caption("Step Function", ("sans-serif", 30).into_font())
-        .margin(10)
-        .x_label_area_size(30)
-        .y_label_area_size(30)
-        .build_cartesian_2d(-5.0f64..5.5f64, 0.0..1.3)
+    let points = x.iter().zip(y.iter()).map(|(x, y)| (*x, (*y).into()));
+    LinePlot::new("Step Function")
+        .series(Series::from_points("", points.collect()))
+        .x_range(-5.0..5.5)
+        .y_range(0.0..1.3)
+        .save(Path::new("step_function.png"))
         .unwrap();
-    plot.configure_mesh().draw().unwrap();
-    plot.draw_series(LineSeries::new(
-        x.iter().zip(y.iter()).map(|(x, y)| (*x, (*y).into())),
-        &RED,
-    ))
-    .unwrap();
 }
 
 #[test]
@@ -142,21 +131,13 @@ fn test_sigmoid() {
         .collect();
     let y = sigmoid(x.clone().into());
 
-    let root = BitMapBackend::new("sigmoid.png", (640, 480)).into_drawing_area();
-    root.fill(&WHITE).unwrap();
-    let mut plot = ChartBuilder::on(&root)
-        .caption("Sigmoid", ("sans-serif", 30).into_font())
-        .margin(10)
-        .x_label_area_size(30)
-        .y_label_area_size(30)
-        .build_cartesian_2d(-5.0f64..5.5f64, 0.0..1.3)
+    let points = x.iter().zip(y.iter()).map(|(x, y)| (*x, *y));
+    LinePlot::new("Sigmoid")
+        .series(Series::from_points("", points.collect()))
+        .x_range(-5.0..5.5)
+        .y_range(0.0..1.3)
+        .save(Path::new("sigmoid.png"))
         .unwrap();
-    plot.configure_mesh().draw().unwrap();
-    plot.draw_series(LineSeries::new(
-        x.iter().zip(y.iter()).map(|(x, y)| (*x, (*y).into())),
-        &RED,
-    ))
-    .unwrap();
 }
 
 #[test]
@@ -167,21 +148,13 @@ fn test_relu() {
         .collect();
     let y = relu(x.clone().into());
 
-    let root = BitMapBackend::new("relu.png", (640, 480)).into_drawing_area();
-    root.fill(&WHITE).unwrap();
-    let mut plot = ChartBuilder::on(&root)
-        .caption("ReLU", ("sans-serif", 30).into_font())
-        .margin(10)
-        .x_label_area_size(30)
-        .y_label_area_size(30)
-        .build_cartesian_2d(-5.0f64..5.5f64, 0.0..6.0)
+    let points = x.iter().zip(y.iter()).map(|(x, y)| (*x, *y));
+    LinePlot::new("ReLU")
+        .series(Series::from_points("", points.collect()))
+        .x_range(-5.0..5.5)
+        .y_range(0.0..6.0)
+        .save(Path::new("relu.png"))
         .unwrap();
-    plot.configure_mesh().draw().unwrap();
-    plot.draw_series(LineSeries::new(
-        x.iter().zip(y.iter()).map(|(x, y)| (*x, (*y).into())),
-        &RED,
-    ))
-    .unwrap();
 }
 
 #[test]
@@ -189,4 +162,6 @@ fn test_forward() {
     forward();
 }
 
-fn main() {}
+fn main() {
+    neural_net_mnist::neural_net_mnist();
+}
@@ -0,0 +1,125 @@
+use std::{io, path::Path};
+
+use multi_layer_net::pickle::load_pickle;
+use mylib::mnist::{self, load_label, load_normalised_image, DatasetType};
+
+// The 784-50-100-10 network of the book, whose pretrained weights are read
+// from `sample_weight.pkl`.
+pub struct Network {
+    weight_list: Vec<na::DMatrix<f64>>,
+    bias_list: Vec<na::DVector<f64>>,
+}
+
+impl Network {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let arrays = load_pickle(path)?;
+        let sizes = [784, 50, 100, 10];
+        let mut weight_list = vec![];
+        let mut bias_list = vec![];
+        for idx in 1..sizes.len() {
+            let get = |key: String| {
+                arrays.get(&key).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("array {} is missing.", key),
+                    )
+                })
+            };
+            let weight = get(format!("W{}", idx))?.to_matrix()?;
+            let bias = get(format!("b{}", idx))?.to_vector()?;
+            if weight.shape() != (sizes[idx - 1], sizes[idx]) || bias.len() != sizes[idx] {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "layer {} has shapes {:?} and {} but {:?} and {} were expected.",
+                        idx,
+                        weight.shape(),
+                        bias.len(),
+                        (sizes[idx - 1], sizes[idx]),
+                        sizes[idx]
+                    ),
+                ));
+            }
+            weight_list.push(weight);
+            bias_list.push(bias);
+        }
+        Ok(Network {
+            weight_list,
+            bias_list,
+        })
+    }
+
+    // `x` holds one image per row.
+    pub fn predict(&self, x: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+        let mut z = x.clone();
+        let layer_count = self.weight_list.len();
+        for (idx, (weight, bias)) in self
+            .weight_list
+            .iter()
+            .zip(self.bias_list.iter())
+            .enumerate()
+        {
+            let mut a = &z * weight;
+            a.row_iter_mut().for_each(|mut row| row += bias.transpose());
+            z = if idx + 1 < layer_count {
+                a.map(|t| 1.0 / (1.0 + (-t).exp()))
+            } else {
+                softmax(a)
+            };
+        }
+        z
+    }
+}
+
+fn softmax(mut x: na::DMatrix<f64>) -> na::DMatrix<f64> {
+    x.row_iter_mut().for_each(|mut row| {
+        let c = row.max();
+        row.apply(|t| *t = (*t - c).exp());
+        let sum = row.sum();
+        row /= sum;
+    });
+    x
+}
+
+// Runs the pretrained network over the test images in batches of 100 and
+// returns the accuracy.
+pub fn neural_net_mnist() -> f64 {
+    let dataset_dir = mnist::init_mnist();
+    let network = Network::load(&mnist::init_sample_weight()).unwrap();
+    let test_img = load_normalised_image(DatasetType::TestImg, &dataset_dir).flatten();
+    let test_img =
+        na::DMatrix::<f64>::from_column_slice(784, test_img.ncols(), test_img.as_slice())
+            .transpose();
+    let test_label = load_label(DatasetType::TestLabel, &dataset_dir);
+    let batch_size = 100;
+    let mut accuracy_cnt = 0;
+    for start in (0..test_img.nrows()).step_by(batch_size) {
+        let len = batch_size.min(test_img.nrows() - start);
+        let y = network.predict(&test_img.rows(start, len).into_owned());
+        accuracy_cnt += y
+            .row_iter()
+            .zip(test_label.label[start..start + len].iter())
+            .filter(|(row, &label)| row.transpose().argmax().0 == label as usize)
+            .count();
+    }
+    let accuracy = accuracy_cnt as f64 / test_img.nrows() as f64;
+    println!("Accuracy:{}", accuracy);
+    accuracy
+}
+
+#[test]
+fn test_predict() {
+    let network = Network {
+        weight_list: vec![na::dmatrix![1.0, -1.0], na::dmatrix![2.0, 0.0; 0.0, 2.0]],
+        bias_list: vec![na::dvector![0.0, 0.0], na::dvector![0.0, 0.0]],
+    };
+    let y = network.predict(&na::dmatrix![0.0; 10.0]);
+    assert!((y.row(0).sum() - 1.0).abs() < 1e-12);
+    assert_eq!(y[(0, 0)], 0.5);
+    assert!(y[(1, 0)] > 0.8);
+}
+
+#[test]
+fn test_neural_net_mnist() {
+    assert!(neural_net_mnist() > 0.9);
+}
@@ -3,43 +3,35 @@ mod train_neural_net;
 mod two_layer_net;
 
 extern crate nalgebra as na;
+use multi_layer_net::numerical::NumericalDiff;
 use mylib::mnist::{self, load_normalised_image, Label, NormalisedImageVec};
-use na::{DVector, Dyn, OMatrix};
-use rand::seq::IteratorRandom;
-
-fn gradient_descent(f: fn(&na::DVector<f64>) -> f64, init_x: na::DVector<f64>) -> na::DVector<f64> {
-    let mut x = init_x.clone();
-    let lr = 0.1;
-    for _ in 0..100 {
-        let mut grad = numerical_gradient(f, &mut x);
-        grad.apply(|t| *t *= lr);
-        x = x - grad;
-    }
-    x
+use na::{DMatrix, Dyn, OMatrix};
+use rand::{seq::IteratorRandom, Rng};
+
+fn gradient_descent(
+    f: impl Fn(&na::DVector<f64>) -> f64 + Sync,
+    init_x: na::DVector<f64>,
+) -> na::DVector<f64> {
+    let init_x = DMatrix::from_column_slice(init_x.len(), 1, init_x.as_slice());
+    NumericalDiff::new()
+        .gradient_descent(|x| f(&x.column(0).into_owned()), &init_x, 0.1, 100)
+        .column(0)
+        .into_owned()
 }
 
-fn numerical_diff(f: fn(f64) -> f64, x: f64) -> f64 {
-    let h = 1e-4;
-    (f(x + h) - f(x - h)) / (2 as f64 * h)
+fn numerical_diff(f: impl Fn(f64) -> f64, x: f64) -> f64 {
+    NumericalDiff::new().derivative(f, x)
 }
 
 fn numerical_gradient(
-    f: fn(&na::DVector<f64>) -> f64,
-    x: &mut na::DVector<f64>,
+    f: impl Fn(&na::DVector<f64>) -> f64 + Sync,
+    x: &na::DVector<f64>,
 ) -> na::DVector<f64> {
-    let h = 1e-4;
-    let length = x.shape().0;
-    let mut grad = DVector::<f64>::zeros(length);
-    for idx in 0..length {
-        let tmp_val = x[idx];
-        x[idx] = tmp_val + h;
-        let fxh1 = f(&x);
-        x[idx] = tmp_val - h;
-        let fxh2 = f(&x);
-        grad[idx] = (fxh1 - fxh2) / (2.0 * h);
-        x[idx] = tmp_val;
-    }
-    grad
+    let x = DMatrix::from_column_slice(x.len(), 1, x.as_slice());
+    NumericalDiff::new()
+        .gradient(|x| f(&x.column(0).into_owned()), &x)
+        .column(0)
+        .into_owned()
 }
 
 fn sum_squared_error(y: na::DVector<f64>, t: na::DVector<u8>) -> f64 {
@@ -72,19 +64,30 @@ fn cross_entropy_error_from_label(y: na::OMatrix<f64, Dyn, na::Const<10>>, t: La
     cross_entropy_error(y, t.as_one_hot())
 }
 
-fn mini_batch() -> Vec<usize> {
+fn mini_batch<R: Rng + ?Sized>(rng: &mut R) -> Vec<usize> {
     let dataset_dir = mnist::init_mnist();
     let train_img: NormalisedImageVec =
         load_normalised_image(mnist::DatasetType::TrainImg, &dataset_dir);
     let batch_size = 10;
-    let batch_mask =
-        (0..train_img.as_ref().len()).choose_multiple(&mut rand::thread_rng(), batch_size);
+    let batch_mask = (0..train_img.as_ref().len()).choose_multiple(rng, batch_size);
     batch_mask
 }
 
 #[test]
 fn test_mini_batch() {
-    dbg!(mini_batch());
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    let batch_mask = mini_batch(&mut ChaCha8Rng::seed_from_u64(0));
+    assert_eq!(batch_mask, mini_batch(&mut ChaCha8Rng::seed_from_u64(0)));
+    assert_ne!(batch_mask, mini_batch(&mut ChaCha8Rng::seed_from_u64(1)));
+    // Ten distinct images out of the 60000 in the training set.
+    assert_eq!(batch_mask.len(), 10);
+    assert!(batch_mask.iter().all(|&i| i < 60000));
+    let mut distinct = batch_mask.clone();
+    distinct.sort_unstable();
+    distinct.dedup();
+    assert_eq!(distinct.len(), 10);
 }
 
 #[test]
@@ -129,7 +132,7 @@ fn test_numerical_gradient() {
     }
     dbg!(numerical_gradient(
         funtion_2,
-        &mut DVector::from_vec(vec![3.0, 4.0])
+        &na::DVector::from_vec(vec![3.0, 4.0])
     ));
 }
 
@@ -138,7 +141,7 @@ fn test_gradient_descent() {
     fn funtion_2(x: &na::DVector<f64>) -> f64 {
         x[0].powi(2) + x[1].powi(2)
     }
-    let init_x = DVector::from_vec(vec![-3.0, 4.0]);
+    let init_x = na::DVector::from_vec(vec![-3.0, 4.0]);
     dbg!(gradient_descent(funtion_2, init_x));
 }
 
@@ -1,5 +1,6 @@
+use multi_layer_net::{optimiser::Optimizer, trainer};
 use mylib::mnist::{self, load_label, load_normalised_image, DatasetType};
-use na::{DMatrix, Scalar};
+use na::DMatrix;
 use nalgebra as na;
 use rand::Rng;
 
@@ -43,17 +44,18 @@ pub struct Params {
 }
 
 impl Params {
-    pub fn new(
+    pub fn new<R: Rng + ?Sized>(
         weight_init_std: f64,
         input_size: usize,
         hidden_size: usize,
         output_size: usize,
+        rng: &mut R,
     ) -> Self {
         let w1: na::DMatrix<f64> =
-            init_matrix_with_standard_normal(input_size, hidden_size) * weight_init_std;
+            init_matrix_with_standard_normal(input_size, hidden_size, rng) * weight_init_std;
         let b1: na::DVector<f64> = na::DVector::<f64>::zeros(hidden_size);
         let w2: na::DMatrix<f64> =
-            init_matrix_with_standard_normal(hidden_size, output_size) * weight_init_std;
+            init_matrix_with_standard_normal(hidden_size, output_size, rng) * weight_init_std;
         let b2: na::DVector<f64> = na::DVector::<f64>::zeros(output_size);
         Self { w1, b1, w2, b2 }
     }
@@ -61,9 +63,9 @@ impl Params {
 
 impl Grads {
     pub fn new(input_size: usize, hidden_size: usize, output_size: usize) -> Self {
-        let d_w1: na::DMatrix<f64> = init_matrix_with_standard_normal(input_size, hidden_size);
+        let d_w1: na::DMatrix<f64> = na::DMatrix::<f64>::zeros(input_size, hidden_size);
         let d_b1: na::DVector<f64> = na::DVector::<f64>::zeros(hidden_size);
-        let d_w2: na::DMatrix<f64> = init_matrix_with_standard_normal(hidden_size, output_size);
+        let d_w2: na::DMatrix<f64> = na::DMatrix::<f64>::zeros(hidden_size, output_size);
         let d_b2: na::DVector<f64> = na::DVector::<f64>::zeros(output_size);
         Self {
             d_w1,
@@ -75,10 +77,17 @@ impl Grads {
 }
 
 impl TwoLayerNet {
-    pub fn new(input_size: usize, hidden_size: usize, output_size: usize) -> Self {
+    // The weights are drawn from `rng`, so a seeded generator gives the same
+    // network every time.
+    pub fn new<R: Rng + ?Sized>(
+        input_size: usize,
+        hidden_size: usize,
+        output_size: usize,
+        rng: &mut R,
+    ) -> Self {
         let weight_init_std = 0.01;
         Self {
-            params: Params::new(weight_init_std, input_size, hidden_size, output_size),
+            params: Params::new(weight_init_std, input_size, hidden_size, output_size, rng),
             grads: Grads::new(input_size, hidden_size, output_size),
         }
     }
@@ -100,39 +109,6 @@ impl TwoLayerNet {
         cross_entropy_error(&y, t)
     }
 
-    pub fn accuracy(&self, x: &na::DMatrix<f64>, t: &na::DMatrix<u8>) -> f64 {
-        let y = self.predict(x);
-        fn max_index_by_column<T>(matrix: &na::DMatrix<T>) -> Vec<(usize, usize)>
-        where
-            T: Scalar + PartialOrd,
-        {
-            matrix
-                .row_iter()
-                .enumerate()
-                .map(|(i, column)| -> (usize, usize) {
-                    let tmp = column.transpose().argmax().0;
-                    (i, tmp)
-                })
-                .collect()
-        }
-        let y_max: Vec<(usize, usize)> = max_index_by_column(&y);
-        let t_max: Vec<(usize, usize)> = max_index_by_column(t);
-        let result = y_max
-            .iter()
-            .zip(t_max.iter())
-            .map(|(_y, _t)| -> i32 {
-                if _y == _t {
-                    1
-                } else {
-                    0
-                }
-            })
-            .collect::<Vec<i32>>()
-            .iter()
-            .sum::<i32>();
-        (result as f64 / y.shape().0 as f64).into()
-    }
-
     // x.shape should be (n, 784) and as well t.shape (n, 10)
     pub fn numerical_gradient(&mut self, x: &na::DMatrix<f64>, t: &na::DMatrix<u8>) {
         self.grads.d_w1 = numerical_gradient_loss!(self, &x, &t, w1);
@@ -142,12 +118,35 @@ impl TwoLayerNet {
     }
 }
 
-fn init_matrix_with_standard_normal(column: usize, row: usize) -> DMatrix<f64> {
-    let mut matrix = na::DMatrix::<f64>::zeros(column, row);
-    matrix
-        .iter_mut()
-        .for_each(|t| *t = rand::thread_rng().sample(rand_distr::StandardNormal));
-    matrix
+// The trait is not imported, so that its `loss` does not shadow the inherent one
+// in `numerical_gradient_loss!`.
+impl trainer::Model for TwoLayerNet {
+    fn predict(&mut self, x: &na::DMatrix<f64>, _train_flg: bool) -> na::DMatrix<f64> {
+        TwoLayerNet::predict(self, x)
+    }
+
+    fn loss(&mut self, x: &na::DMatrix<f64>, t: &na::DMatrix<u8>, _train_flg: bool) -> f64 {
+        TwoLayerNet::loss(self, x, t)
+    }
+
+    fn gradient(&mut self, x: &na::DMatrix<f64>, t: &na::DMatrix<u8>) {
+        self.numerical_gradient(x, t);
+    }
+
+    fn update(&mut self, optimiser: &mut dyn Optimizer) {
+        optimiser.step_matrix("W1", &mut self.params.w1, &self.grads.d_w1);
+        optimiser.step_vector("b1", &mut self.params.b1, &self.grads.d_b1);
+        optimiser.step_matrix("W2", &mut self.params.w2, &self.grads.d_w2);
+        optimiser.step_vector("b2", &mut self.params.b2, &self.grads.d_b2);
+    }
+}
+
+fn init_matrix_with_standard_normal<R: Rng + ?Sized>(
+    column: usize,
+    row: usize,
+    rng: &mut R,
+) -> DMatrix<f64> {
+    DMatrix::<f64>::from_fn(column, row, |_, _| rng.sample(rand_distr::StandardNormal))
 }
 
 fn sigmoid(x: &na::DMatrix<f64>) -> na::DMatrix<f64> {
@@ -1,10 +1,10 @@
-use std::{cell::RefCell, ops::Deref, rc::Rc};
+use multi_layer_net::shared::Shared;
 
 use super::Layer;
 
 pub struct Affine {
-    w: Rc<RefCell<na::DMatrix<f64>>>,
-    pub b: Rc<RefCell<na::DVector<f64>>>,
+    w: Shared<na::DMatrix<f64>>,
+    pub b: Shared<na::DVector<f64>>,
     x: na::DMatrix<f64>,
     pub dw: na::DMatrix<f64>,
     pub db: na::DVector<f64>,
@@ -16,19 +16,14 @@ impl Layer for Affine {
         #[allow(non_snake_case)]
         let B = na::DMatrix::<f64>::from_row_slice(
             self.x.nrows(),
-            self.b.deref().borrow().nrows(),
-            self.b
-                .deref()
-                .borrow()
-                .as_slice()
-                .repeat(self.x.nrows())
-                .as_slice(),
+            self.b.borrow().nrows(),
+            self.b.borrow().as_slice().repeat(self.x.nrows()).as_slice(),
         );
-        &self.x * self.w.deref().borrow().deref() + B
+        &self.x * &*self.w.borrow() + B
     }
 
     fn backwards(&mut self, dout: &na::DMatrix<f64>) -> na::DMatrix<f64> {
-        let dx = dout * &self.w.deref().borrow().transpose();
+        let dx = dout * &self.w.borrow().transpose();
         self.dw = &self.x.transpose() * dout;
         self.db = na::DVector::<f64>::from_fn(dout.ncols(), |i, _| dout.column(i).sum());
         dx
@@ -36,7 +31,7 @@ impl Layer for Affine {
 }
 
 impl Affine {
-    pub fn new(w: Rc<RefCell<na::DMatrix<f64>>>, b: Rc<RefCell<na::DVector<f64>>>) -> Self {
+    pub fn new(w: Shared<na::DMatrix<f64>>, b: Shared<na::DVector<f64>>) -> Self {
         Self {
             w,
             b,
@@ -52,7 +47,7 @@ fn test_affine() {
     let x = na::DMatrix::<f64>::from_element(20, 50, 0.0);
     let w = na::DMatrix::<f64>::from_element(50, 10, 0.0);
     let b = na::DVector::<f64>::from_element(10, 0.0);
-    let mut affine = Affine::new(Rc::new(RefCell::new(w)), Rc::new(RefCell::new(b)));
+    let mut affine = Affine::new(Shared::new(w), Shared::new(b));
     dbg!(affine.forwards(&x).shape());
     let dy = na::DMatrix::<f64>::from_element(20, 10, 0.0);
     dbg!(affine.backwards(&dy).shape());
@@ -1,11 +1,22 @@
 use std::{
     cell::{Ref, RefCell},
+    io,
     ops::Deref,
+    path::Path,
     rc::Rc,
 };
 
+use multi_layer_net::{
+    checkpoint::Checkpoint,
+    gradient_check,
+    optimiser::Optimizer,
+    parameters::{NamedParameters, Parameter},
+    shared::Shared,
+    trainer,
+};
+
 use mylib::mnist::{self, load_label, load_normalised_image, DatasetType};
-use na::{dmatrix, DMatrix, Scalar};
+use na::{dmatrix, DMatrix};
 use nalgebra as na;
 use rand::Rng;
 
@@ -13,26 +24,6 @@ use crate::layers::{
     affine_layer::Affine, relu_layer::Relu, softmax_with_loss_layer::SoftmaxWithLoss, Layer,
 };
 
-macro_rules! numerical_gradient_loss {
-    ($instance:ident, $x:expr, $t:expr, $elm:ident) => {{
-        let h = 1e-4;
-        let mut grad = $instance.params.borrow_mut().$elm.borrow_mut().clone();
-        for i in 0..grad.shape().0 {
-            for j in 0..grad.shape().1 {
-                let index = (i, j);
-                let tmp_val = $instance.params.borrow_mut().$elm.borrow_mut()[index];
-                $instance.params.borrow_mut().$elm.borrow_mut()[index] = tmp_val + h;
-                let fxh1 = $instance.loss($x, $t);
-                $instance.params.borrow_mut().$elm.borrow_mut()[index] = tmp_val - h;
-                let fxh2 = $instance.loss($x, $t);
-                grad[index] = (fxh1 - fxh2) / (2.0 * h);
-                $instance.params.borrow_mut().$elm.borrow_mut()[index] = tmp_val;
-            }
-        }
-        grad
-    }};
-}
-
 pub struct TwoLayerNet {
     pub params: Rc<RefCell<Params>>,
     pub grads: Grads,
@@ -48,56 +39,80 @@ pub struct Layers {
 
 #[derive(Clone, Debug)]
 pub struct Grads {
-    pub d_w1: na::DMatrix<f64>,
-    pub d_b1: na::DVector<f64>,
-    pub d_w2: na::DMatrix<f64>,
-    pub d_b2: na::DVector<f64>,
+    pub d_w1: Shared<na::DMatrix<f64>>,
+    pub d_b1: Shared<na::DVector<f64>>,
+    pub d_w2: Shared<na::DMatrix<f64>>,
+    pub d_b2: Shared<na::DVector<f64>>,
 }
 
 pub struct Params {
-    pub w1: Rc<RefCell<na::DMatrix<f64>>>,
-    pub b1: Rc<RefCell<na::DVector<f64>>>,
-    pub w2: Rc<RefCell<na::DMatrix<f64>>>,
-    pub b2: Rc<RefCell<na::DVector<f64>>>,
+    pub w1: Shared<na::DMatrix<f64>>,
+    pub b1: Shared<na::DVector<f64>>,
+    pub w2: Shared<na::DMatrix<f64>>,
+    pub b2: Shared<na::DVector<f64>>,
 }
 
 impl Params {
-    pub fn new(
+    pub fn new<R: Rng + ?Sized>(
         weight_init_std: f64,
         input_size: usize,
         hidden_size: usize,
         output_size: usize,
+        rng: &mut R,
     ) -> Self {
         let w1: na::DMatrix<f64> =
-            init_matrix_with_standard_normal(input_size, hidden_size) * weight_init_std;
+            init_matrix_with_standard_normal(input_size, hidden_size, rng) * weight_init_std;
         let b1: na::DVector<f64> = na::DVector::<f64>::zeros(hidden_size);
         let w2: na::DMatrix<f64> =
-            init_matrix_with_standard_normal(hidden_size, output_size) * weight_init_std;
+            init_matrix_with_standard_normal(hidden_size, output_size, rng) * weight_init_std;
         let b2: na::DVector<f64> = na::DVector::<f64>::zeros(output_size);
         Self {
-            w1: Rc::new(RefCell::new(w1)),
-            b1: Rc::new(RefCell::new(b1)),
-            w2: Rc::new(RefCell::new(w2)),
-            b2: Rc::new(RefCell::new(b2)),
+            w1: Shared::new(w1),
+            b1: Shared::new(b1),
+            w2: Shared::new(w2),
+            b2: Shared::new(b2),
         }
     }
 }
 
+impl NamedParameters for Params {
+    fn named_parameters(&self) -> Vec<(String, Parameter)> {
+        vec![
+            ("W1".to_string(), Parameter::Matrix(self.w1.clone())),
+            ("b1".to_string(), Parameter::Vector(self.b1.clone())),
+            ("W2".to_string(), Parameter::Matrix(self.w2.clone())),
+            ("b2".to_string(), Parameter::Vector(self.b2.clone())),
+        ]
+    }
+}
+
 impl Grads {
     pub fn new(input_size: usize, hidden_size: usize, output_size: usize) -> Self {
-        let d_w1: na::DMatrix<f64> = init_matrix_with_standard_normal(input_size, hidden_size);
+        let d_w1: na::DMatrix<f64> = na::DMatrix::<f64>::zeros(input_size, hidden_size);
         let d_b1: na::DVector<f64> = na::DVector::<f64>::zeros(hidden_size);
-        let d_w2: na::DMatrix<f64> = init_matrix_with_standard_normal(hidden_size, output_size);
+        let d_w2: na::DMatrix<f64> = na::DMatrix::<f64>::zeros(hidden_size, output_size);
         let d_b2: na::DVector<f64> = na::DVector::<f64>::zeros(output_size);
         Self {
-            d_w1,
-            d_b1,
-            d_w2,
-            d_b2,
+            d_w1: Shared::new(d_w1),
+            d_b1: Shared::new(d_b1),
+            d_w2: Shared::new(d_w2),
+            d_b2: Shared::new(d_b2),
         }
     }
 }
 
+// Under the keys of the parameters they belong to.
+impl NamedParameters for Grads {
+    fn named_parameters(&self) -> Vec<(String, Parameter)> {
+        vec![
+            ("W1".to_string(), Parameter::Matrix(self.d_w1.clone())),
+            ("b1".to_string(), Parameter::Vector(self.d_b1.clone())),
+            ("W2".to_string(), Parameter::Matrix(self.d_w2.clone())),
+            ("b2".to_string(), Parameter::Vector(self.d_b2.clone())),
+        ]
+    }
+}
+
 impl Layers {
     pub fn new(params: Rc<RefCell<Params>>) -> Self {
         let clone = Rc::clone(&params);
@@ -114,13 +129,21 @@ impl Layers {
 }
 
 impl TwoLayerNet {
-    pub fn new(input_size: usize, hidden_size: usize, output_size: usize) -> Self {
+    // The weights are drawn from `rng`, so a seeded generator gives the same
+    // network every time.
+    pub fn new<R: Rng + ?Sized>(
+        input_size: usize,
+        hidden_size: usize,
+        output_size: usize,
+        rng: &mut R,
+    ) -> Self {
         let weight_init_std = 0.01;
         let params = Rc::new(RefCell::new(Params::new(
             weight_init_std,
             input_size,
             hidden_size,
             output_size,
+            rng,
         )));
         Self {
             grads: Grads::new(input_size, hidden_size, output_size),
@@ -130,6 +153,44 @@ impl TwoLayerNet {
         }
     }
 
+    pub fn to_checkpoint(&self) -> Checkpoint {
+        let mut checkpoint = Checkpoint::new();
+        let (input_size, hidden_size) = self.params.borrow().w1.borrow().shape();
+        let output_size = self.params.borrow().w2.borrow().ncols();
+        checkpoint.insert_metadata("model", "TwoLayerNet");
+        checkpoint.insert_metadata("input_size", input_size);
+        checkpoint.insert_metadata("hidden_size", hidden_size);
+        checkpoint.insert_metadata("output_size", output_size);
+        checkpoint.insert_parameters("", &self.params);
+        checkpoint
+    }
+
+    pub fn from_checkpoint(checkpoint: &Checkpoint) -> io::Result<Self> {
+        let model: String = checkpoint.metadata("model")?;
+        if model != "TwoLayerNet" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("checkpoint holds a {} rather than a TwoLayerNet.", model),
+            ));
+        }
+        let network = Self::new(
+            checkpoint.metadata("input_size")?,
+            checkpoint.metadata("hidden_size")?,
+            checkpoint.metadata("output_size")?,
+            &mut rand::thread_rng(),
+        );
+        checkpoint.load_parameters("", &network.params)?;
+        Ok(network)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        self.to_checkpoint().save(path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_checkpoint(&Checkpoint::load(path)?)
+    }
+
     pub fn predict(&self, x: &na::DMatrix<f64>) -> na::DMatrix<f64> {
         let mut x = x.clone();
         let layers: Vec<Rc<RefCell<dyn Layer>>> = vec![
@@ -148,45 +209,21 @@ impl TwoLayerNet {
         self.last_layer.forwards(&y, t)
     }
 
-    pub fn accuracy(&self, x: &na::DMatrix<f64>, t: &na::DMatrix<u8>) -> f64 {
-        let y = self.predict(x);
-        fn max_index_by_column<T>(matrix: &na::DMatrix<T>) -> Vec<(usize, usize)>
-        where
-            T: Scalar + PartialOrd,
-        {
-            matrix
-                .row_iter()
-                .enumerate()
-                .map(|(i, column)| -> (usize, usize) {
-                    let tmp = column.transpose().argmax().0;
-                    (i, tmp)
-                })
-                .collect()
-        }
-        let y_max: Vec<(usize, usize)> = max_index_by_column(&y);
-        let t_max: Vec<(usize, usize)> = max_index_by_column(t);
-        let result = y_max
-            .iter()
-            .zip(t_max.iter())
-            .map(|(_y, _t)| -> i32 {
-                if _y == _t {
-                    1
-                } else {
-                    0
-                }
-            })
-            .collect::<Vec<i32>>()
-            .iter()
-            .sum::<i32>();
-        (result as f64 / y.shape().0 as f64).into()
-    }
-
     // x.shape should be (n, 784) and as well t.shape (n, 10)
     pub fn numerical_gradient(&mut self, x: &na::DMatrix<f64>, t: &na::DMatrix<u8>) {
-        self.grads.d_w1 = numerical_gradient_loss!(self, x, t, w1);
-        self.grads.d_b1 = numerical_gradient_loss!(self, x, t, b1);
-        self.grads.d_w2 = numerical_gradient_loss!(self, x, t, w2);
-        self.grads.d_b2 = numerical_gradient_loss!(self, x, t, b2);
+        let params = self.params.borrow().named_parameters();
+        for (key, param) in params.iter() {
+            let numerical = gradient_check::numerical_gradient(param, || self.loss(x, t));
+            // Fresh tensors, as in `gradient`, leave clones of the previous
+            // gradients alone.
+            match key.as_str() {
+                "W1" => self.grads.d_w1 = Shared::new(numerical),
+                "b1" => self.grads.d_b1 = Shared::new(numerical.column(0).into_owned()),
+                "W2" => self.grads.d_w2 = Shared::new(numerical),
+                "b2" => self.grads.d_b2 = Shared::new(numerical.column(0).into_owned()),
+                _ => panic!("there is no gradient for {}.", key),
+            }
+        }
     }
 
     pub fn gradient(&mut self, x: &na::DMatrix<f64>, t: &na::DMatrix<u8>) {
@@ -200,106 +237,94 @@ impl TwoLayerNet {
         for layer in layers {
             dout = layer.borrow_mut().backwards(&dout);
         }
-        self.grads.d_w1 = self.layers.affine1.deref().borrow().dw.clone();
-        self.grads.d_b1 = self.layers.affine1.deref().borrow().db.clone();
-        self.grads.d_w2 = self.layers.affine2.deref().borrow().dw.clone();
-        self.grads.d_b2 = self.layers.affine2.deref().borrow().db.clone();
+        self.grads.d_w1 = Shared::new(self.layers.affine1.deref().borrow().dw.clone());
+        self.grads.d_b1 = Shared::new(self.layers.affine1.deref().borrow().db.clone());
+        self.grads.d_w2 = Shared::new(self.layers.affine2.deref().borrow().dw.clone());
+        self.grads.d_b2 = Shared::new(self.layers.affine2.deref().borrow().db.clone());
+    }
+}
+
+// The trait is not imported, so that its methods do not shadow the inherent
+// ones taking fewer arguments.
+impl trainer::Model for TwoLayerNet {
+    fn predict(&mut self, x: &na::DMatrix<f64>, _train_flg: bool) -> na::DMatrix<f64> {
+        TwoLayerNet::predict(self, x)
+    }
+
+    fn loss(&mut self, x: &na::DMatrix<f64>, t: &na::DMatrix<u8>, _train_flg: bool) -> f64 {
+        TwoLayerNet::loss(self, x, t)
+    }
+
+    fn gradient(&mut self, x: &na::DMatrix<f64>, t: &na::DMatrix<u8>) {
+        TwoLayerNet::gradient(self, x, t)
+    }
+
+    fn update(&mut self, optimiser: &mut dyn Optimizer) {
+        optimiser.update(&self.params, &self.grads);
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        TwoLayerNet::save(self, path)
     }
 }
 
-fn init_matrix_with_standard_normal(column: usize, row: usize) -> DMatrix<f64> {
-    let mut matrix = na::DMatrix::<f64>::zeros(column, row);
-    matrix
-        .iter_mut()
-        .for_each(|t| *t = rand::thread_rng().sample(rand_distr::StandardNormal));
-    matrix
+fn init_matrix_with_standard_normal<R: Rng + ?Sized>(
+    column: usize,
+    row: usize,
+    rng: &mut R,
+) -> DMatrix<f64> {
+    DMatrix::<f64>::from_fn(column, row, |_, _| rng.sample(rand_distr::StandardNormal))
 }
 
 #[test]
 fn gradient_check() {
-    let mut network = TwoLayerNet::new(784, 50, 10);
-    let dataset_dir = std::env::current_dir().unwrap().join("dataset");
-    mnist::init_mnist();
-    let train_img = load_normalised_image(DatasetType::TrainImg, &dataset_dir).flatten();
-    let train_label = load_label(DatasetType::TrainLabel, &dataset_dir).as_one_hot();
-    let test_img = load_normalised_image(DatasetType::TestImg, &dataset_dir).flatten();
-    let test_label = load_label(DatasetType::TestLabel, &dataset_dir).as_one_hot();
-    let mut img_batch = na::DMatrix::<f64>::zeros(3, 784);
-    let mut label_batch = na::DMatrix::<u8>::zeros(3, 10);
-    img_batch
-        .row_iter_mut()
-        .zip((0..3).into_iter())
-        .for_each(|(mut column, n)| column.copy_from_slice(&train_img.column(n).as_slice()));
-    label_batch
-        .row_iter_mut()
-        .zip((0..3).into_iter())
-        .for_each(|(mut column, n)| column.copy_from(&train_label.row(n)));
-    network.numerical_gradient(&img_batch, &label_batch);
-    let grad_numerical = network.grads.clone();
-    network.gradient(&img_batch, &label_batch);
-    let backprop = network.grads.clone();
-    println!(
-        "dw1 {}",
-        (&grad_numerical.d_w1 - &backprop.d_w1).sum() / grad_numerical.d_w1.nrows() as f64
-    );
-    println!(
-        "db1 {}",
-        (&grad_numerical.d_b1 - &backprop.d_b1).sum() / grad_numerical.d_b1.nrows() as f64
-    );
-    println!(
-        "dw2 {}",
-        (&grad_numerical.d_w2 - &backprop.d_w2).sum() / grad_numerical.d_w2.nrows() as f64
-    );
-    println!(
-        "db2 {}",
-        (&grad_numerical.d_b2 - &backprop.d_b2).sum() / grad_numerical.d_b2.nrows() as f64
-    );
-    panic!("panic");
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut network = TwoLayerNet::new(8, 6, 4, &mut rng);
+    let x = init_matrix_with_standard_normal(3, 8, &mut rng);
+    let t: na::DMatrix<u8> = dmatrix![1,0,0,0;0,0,1,0;0,0,0,1];
+    network.gradient(&x, &t);
+    let (params, grads) = (network.params.clone(), network.grads.clone());
+    let result = gradient_check::check_parameters(&params, &grads, || network.loss(&x, &t));
+    print!("{}", result);
+    result.assert_within(1e-5);
+
+    // The numerical gradients end up under the same keys.
+    network.numerical_gradient(&x, &t);
+    for ((key, numerical), (_, analytic)) in network
+        .grads
+        .named_parameters()
+        .iter()
+        .zip(grads.named_parameters())
+    {
+        let error =
+            gradient_check::max_relative_error(&analytic.to_matrix(), &numerical.to_matrix());
+        assert!(error < 1e-5, "{}: {}", key, error);
+    }
 }
 
 #[test]
 fn test_accuracy() {
+    use multi_layer_net::metrics;
     let t: na::DMatrix<u8> = dmatrix![0,0,1,0,0,0,0,0,0,0;0,0,0,0,0,0,0,0,1,0];
     let x: na::DMatrix<f64> = dmatrix![-0.0031, -0.0076,  0.0073, -0.0048, -0.0027, -0.0019, -0.0059,  0.0017,  0.0058,  0.0034;
     0.0002, -0.0136,  0.0085, -0.0022, -0.0032, -0.0048, -0.0029,  0.0063,  0.0097,  0.0042];
-    fn max_index_by_column<T>(matrix: &na::DMatrix<T>) -> Vec<(usize, usize)>
-    where
-        T: Scalar + PartialOrd,
-    {
-        matrix
-            .row_iter()
-            .enumerate()
-            .map(|(i, column)| -> (usize, usize) {
-                let tmp = column.transpose().argmax().0;
-                dbg!((i, tmp));
-                (i, tmp)
-            })
-            .collect()
-    }
-    let x_max: Vec<(usize, usize)> = max_index_by_column(&x);
-    let t_max: Vec<(usize, usize)> = max_index_by_column(&t);
-    let result = x_max
-        .iter()
-        .zip(t_max.iter())
-        .map(|(_y, _t)| -> i32 {
-            if _y == _t {
-                1
-            } else {
-                0
-            }
-        })
-        .collect::<Vec<i32>>()
-        .iter()
-        .sum::<i32>();
-    let a = result as f64 / x.shape().0 as f64;
-    dbg!(a);
+    assert_eq!(metrics::argmax_rows(&x), vec![2, 8]);
+    assert_eq!(metrics::argmax_rows(&t), vec![2, 8]);
+    assert_eq!(metrics::accuracy(&x, &t), 1.0);
 }
 
 #[test]
 fn test_predict() {
+    use multi_layer_net::metrics;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
     let dataset_dir = std::env::current_dir().unwrap().join("dataset");
     mnist::init_mnist();
-    let mut network = TwoLayerNet::new(784, 50, 10);
+    let mut network = TwoLayerNet::new(784, 50, 10, &mut ChaCha8Rng::seed_from_u64(0));
     let _train_img = mnist::load_normalised_image(DatasetType::TrainImg, &dataset_dir).flatten();
     let train_label = load_label(DatasetType::TrainLabel, &dataset_dir).as_one_hot();
     let mut img_batch = na::DMatrix::<f64>::zeros(4, 784);
@@ -316,6 +341,25 @@ fn test_predict() {
     result.apply(|a| *a = (*a * 10000.0).round() / 10000.0);
     println!("{}", result);
     println!("{}", label_batch);
-    println!("{}", network.accuracy(&img_batch, &label_batch));
+    println!(
+        "{}",
+        metrics::accuracy(&network.predict(&img_batch), &label_batch)
+    );
     panic!();
 }
+
+#[test]
+fn test_checkpoint_round_trip() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let network = TwoLayerNet::new(6, 5, 3, &mut rng);
+    let path = std::env::temp_dir().join("two_layer_net_checkpoint.bin");
+    network.save(&path).unwrap();
+    let loaded = TwoLayerNet::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let x = init_matrix_with_standard_normal(4, 6, &mut rng);
+    assert_eq!(loaded.predict(&x), network.predict(&x));
+    assert_eq!(loaded.to_checkpoint(), network.to_checkpoint());
+}
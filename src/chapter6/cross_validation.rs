@@ -0,0 +1,45 @@
+use multi_layer_net::{
+    cross_validation::KFold, multi_layer_net_extended::MultiLayerNetExtended, optimiser::sgd::SGD,
+    trainer::Dataset,
+};
+use mylib::mnist::{self, load_label, load_normalised_image, DatasetType};
+
+// Five-fold cross-validation of the network from `over_fit_decay_batch_norm`
+// on the same 300 training images, which are too few for a fixed hold-out
+// split to give a reliable accuracy.
+pub fn cross_validate_overfit_subset() {
+    let dataset_dir = std::env::current_dir().unwrap().join("dataset");
+    mnist::init_mnist();
+    let data = Dataset::from_image_columns(
+        &load_normalised_image(DatasetType::TrainImg, &dataset_dir)
+            .flatten()
+            .columns(0, 300),
+        &load_label(DatasetType::TrainLabel, &dataset_dir)
+            .as_one_hot()
+            .rows(0, 300),
+    );
+    let result = KFold::new(5)
+        .epochs(50)
+        .batch_size(100)
+        .run(&data, |_, rng| {
+            (
+                MultiLayerNetExtended::new_with_rng(
+                    784,
+                    [100; 6].to_vec(),
+                    10,
+                    0.1,
+                    "relu",
+                    "relu",
+                    rng,
+                ),
+                SGD::new(0.01),
+            )
+        });
+    print!("{}", result.report());
+    let accuracy = result.accuracy();
+    println!(
+        "Cross-validated accuracy is {:.1}% ± {:.1}%",
+        accuracy.mean * 100.0,
+        accuracy.std * 100.0
+    );
+}
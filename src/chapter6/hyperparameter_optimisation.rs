@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use multi_layer_net::{
+    hyperparameter_search::{HyperparameterSearch, SearchSpace},
+    trainer::Dataset,
+};
+use mylib::mnist::{self, load_label, load_normalised_image, DatasetType};
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+// Section 6.5 of the book: 100 random learning rates and weight decays are
+// tried on 500 training images, a fifth of which are held out to validate.
+pub fn hyperparameter_optimisation() {
+    let dataset_dir = std::env::current_dir().unwrap().join("dataset");
+    mnist::init_mnist();
+    let data = Dataset::from_image_columns(
+        &load_normalised_image(DatasetType::TrainImg, &dataset_dir)
+            .flatten()
+            .columns(0, 500),
+        &load_label(DatasetType::TrainLabel, &dataset_dir)
+            .as_one_hot()
+            .rows(0, 500),
+    );
+    let mut indices: Vec<usize> = (0..data.len()).collect();
+    indices.shuffle(&mut ChaCha8Rng::seed_from_u64(0));
+    let validation_num = data.len() / 5;
+    let (x, t) = data.batch(&indices[..validation_num]);
+    let val = Dataset::new(x, t);
+    let (x, t) = data.batch(&indices[validation_num..]);
+    let train = Dataset::new(x, t);
+
+    let result = HyperparameterSearch::new(&train, &val, vec![100; 6])
+        .epochs(50)
+        .batch_size(100)
+        .random(&SearchSpace::book(), 100);
+    println!("=========== Hyper-Parameter Optimization Result ===========");
+    print!("{}", result.table());
+    result
+        .plot(5, "Hyper-Parameter Optimization")
+        .save(Path::new("Hyperparameter Search.png"))
+        .unwrap();
+}
@@ -1,4 +1,3 @@
-pub mod ada_grad;
-pub mod momentum;
-pub mod sgd;
-pub mod sgd_ext;
+pub use multi_layer_net::optimiser::{
+    ada_grad, adam, momentum, nesterov, rms_prop, sgd, Optimizer, OptimizerBase,
+};
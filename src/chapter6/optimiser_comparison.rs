@@ -0,0 +1,234 @@
+use plotters::{
+    backend::BitMapBackend,
+    chart::ChartBuilder,
+    drawing::IntoDrawingArea,
+    element::{Circle, Rectangle},
+    series::LineSeries,
+    style::{Color, HSLColor, IntoFont, BLACK, RED, WHITE},
+};
+
+use crate::optimiser::{
+    ada_grad::AdaGrad,
+    adam::{Adam, AdamW},
+    momentum::Momentum,
+    nesterov::Nesterov,
+    rms_prop::RMSProp,
+    sgd::SGD,
+    Optimizer,
+};
+
+pub type NamedOptimisers = Vec<(&'static str, Box<dyn Optimizer>)>;
+
+pub struct TestFunction {
+    pub name: &'static str,
+    pub f: fn(f64, f64) -> f64,
+    pub gradient: fn(f64, f64) -> (f64, f64),
+    pub init: (f64, f64),
+    pub x_range: (f64, f64),
+    pub y_range: (f64, f64),
+    pub iterations: usize,
+    pub optimisers: fn() -> NamedOptimisers,
+}
+
+// f(x, y) = x^2 / 20 + y^2 with the learning rates used in the book.
+pub fn book_function() -> TestFunction {
+    TestFunction {
+        name: "Quadratic",
+        f: |x, y| x.powi(2) / 20.0 + y.powi(2),
+        gradient: |x, y| (x / 10.0, 2.0 * y),
+        init: (-7.0, 2.0),
+        x_range: (-10.0, 10.0),
+        y_range: (-5.0, 5.0),
+        iterations: 30,
+        optimisers: || {
+            vec![
+                ("SGD", Box::new(SGD::new(0.95))),
+                ("Momentum", Box::new(Momentum::new(0.1, 0.9))),
+                ("Nesterov", Box::new(Nesterov::new(0.1, 0.9))),
+                ("AdaGrad", Box::new(AdaGrad::new(1.5))),
+                ("RMSProp", Box::new(RMSProp::new(0.1, 0.99))),
+                ("Adam", Box::new(Adam::new(0.3, 0.9, 0.999))),
+                ("AdamW", Box::new(AdamW::new(0.3, 0.9, 0.999, 0.01))),
+            ]
+        },
+    }
+}
+
+pub fn rosenbrock() -> TestFunction {
+    TestFunction {
+        name: "Rosenbrock",
+        f: |x, y| (1.0 - x).powi(2) + 100.0 * (y - x.powi(2)).powi(2),
+        gradient: |x, y| {
+            (
+                -2.0 * (1.0 - x) - 400.0 * x * (y - x.powi(2)),
+                200.0 * (y - x.powi(2)),
+            )
+        },
+        init: (-1.5, 2.0),
+        x_range: (-2.0, 2.0),
+        y_range: (-1.0, 3.0),
+        iterations: 1000,
+        optimisers: || {
+            vec![
+                ("SGD", Box::new(SGD::new(0.0005))),
+                ("Momentum", Box::new(Momentum::new(0.0002, 0.9))),
+                ("Nesterov", Box::new(Nesterov::new(0.0002, 0.9))),
+                ("AdaGrad", Box::new(AdaGrad::new(0.5))),
+                ("RMSProp", Box::new(RMSProp::new(0.01, 0.99))),
+                ("Adam", Box::new(Adam::new(0.05, 0.9, 0.999))),
+                ("AdamW", Box::new(AdamW::new(0.05, 0.9, 0.999, 0.01))),
+            ]
+        },
+    }
+}
+
+pub fn beale() -> TestFunction {
+    TestFunction {
+        name: "Beale",
+        f: |x, y| {
+            (1.5 - x + x * y).powi(2)
+                + (2.25 - x + x * y.powi(2)).powi(2)
+                + (2.625 - x + x * y.powi(3)).powi(2)
+        },
+        gradient: |x, y| {
+            let a = 1.5 - x + x * y;
+            let b = 2.25 - x + x * y.powi(2);
+            let c = 2.625 - x + x * y.powi(3);
+            (
+                2.0 * a * (y - 1.0) + 2.0 * b * (y.powi(2) - 1.0) + 2.0 * c * (y.powi(3) - 1.0),
+                2.0 * a * x + 4.0 * b * x * y + 6.0 * c * x * y.powi(2),
+            )
+        },
+        init: (1.0, 1.5),
+        x_range: (-4.5, 4.5),
+        y_range: (-4.5, 4.5),
+        iterations: 1000,
+        optimisers: || {
+            vec![
+                ("SGD", Box::new(SGD::new(0.001))),
+                ("Momentum", Box::new(Momentum::new(0.0005, 0.9))),
+                ("Nesterov", Box::new(Nesterov::new(0.0005, 0.9))),
+                ("AdaGrad", Box::new(AdaGrad::new(0.5))),
+                ("RMSProp", Box::new(RMSProp::new(0.01, 0.99))),
+                ("Adam", Box::new(Adam::new(0.05, 0.9, 0.999))),
+                ("AdamW", Box::new(AdamW::new(0.05, 0.9, 0.999, 0.01))),
+            ]
+        },
+    }
+}
+
+// Runs `optimiser` from `function.init` and returns every visited point,
+// including the initial one.
+pub fn trajectory(function: &TestFunction, optimiser: &mut dyn Optimizer) -> Vec<(f64, f64)> {
+    let mut param = na::dmatrix![function.init.0; function.init.1];
+    let mut trajectory = vec![function.init];
+    for _ in 0..function.iterations {
+        let (dx, dy) = (function.gradient)(param[0], param[1]);
+        let grad = na::dmatrix![dx; dy];
+        optimiser.step("xy", param.as_view_mut(), grad.as_view());
+        trajectory.push((param[0], param[1]));
+    }
+    trajectory
+}
+
+pub fn compare_optimisers(functions: &[TestFunction]) {
+    for function in functions {
+        let optimisers = (function.optimisers)();
+        let name = format!("Optimisers {}.png", function.name);
+        let root = BitMapBackend::new(&name, (1280, 720)).into_drawing_area();
+        root.fill(&WHITE).unwrap();
+        let root = root
+            .titled(function.name, ("sans-serif", 30).into_font())
+            .unwrap();
+        let areas = root.split_evenly((2, optimisers.len().div_ceil(2)));
+        for ((optimiser_name, mut optimiser), area) in optimisers.into_iter().zip(areas.iter()) {
+            let trajectory = trajectory(function, optimiser.as_mut());
+            let mut plot = ChartBuilder::on(area)
+                .caption(optimiser_name, ("sans-serif", 20).into_font())
+                .margin(5)
+                .x_label_area_size(20)
+                .y_label_area_size(30)
+                .build_cartesian_2d(
+                    function.x_range.0..function.x_range.1,
+                    function.y_range.0..function.y_range.1,
+                )
+                .unwrap();
+            plot.configure_mesh().disable_mesh().draw().unwrap();
+            plot.draw_series(contour_cells(function)).unwrap();
+            plot.draw_series(LineSeries::new(
+                trajectory
+                    .iter()
+                    .copied()
+                    .filter(|(x, y)| x.is_finite() && y.is_finite()),
+                &RED,
+            ))
+            .unwrap();
+            plot.draw_series(
+                trajectory
+                    .iter()
+                    .filter(|(x, y)| x.is_finite() && y.is_finite())
+                    .map(|&point| Circle::new(point, 2, RED.filled())),
+            )
+            .unwrap();
+        }
+        root.present().unwrap();
+    }
+}
+
+// Colours a grid of cells by the band of log(1 + f) they fall in, which
+// reads as a filled contour plot.
+fn contour_cells(function: &TestFunction) -> Vec<Rectangle<(f64, f64)>> {
+    let resolution = 100;
+    let levels = 12.0;
+    let (x_min, x_max) = function.x_range;
+    let (y_min, y_max) = function.y_range;
+    let dx = (x_max - x_min) / resolution as f64;
+    let dy = (y_max - y_min) / resolution as f64;
+    let values: Vec<f64> = (0..resolution * resolution)
+        .map(|n| {
+            let x = x_min + (n % resolution) as f64 * dx + dx / 2.0;
+            let y = y_min + (n / resolution) as f64 * dy + dy / 2.0;
+            (function.f)(x, y).ln_1p()
+        })
+        .collect();
+    let max = values.iter().copied().fold(f64::MIN, f64::max);
+    values
+        .iter()
+        .enumerate()
+        .map(|(n, value)| {
+            let x = x_min + (n % resolution) as f64 * dx;
+            let y = y_min + (n / resolution) as f64 * dy;
+            let band = (value / max * levels).floor() / levels;
+            let colour = HSLColor(0.6 - 0.6 * band, 0.6, 0.85);
+            Rectangle::new([(x, y), (x + dx, y + dy)], colour.filled())
+        })
+        .chain(std::iter::once(Rectangle::new(
+            [(x_min, y_min), (x_max, y_max)],
+            BLACK.stroke_width(1),
+        )))
+        .collect()
+}
+
+#[test]
+fn test_optimisers_approach_minimum() {
+    for (function, minimum) in [
+        (book_function(), (0.0, 0.0)),
+        (rosenbrock(), (1.0, 1.0)),
+        (beale(), (3.0, 0.5)),
+    ] {
+        let distance = |(x, y): (f64, f64)| (x - minimum.0).hypot(y - minimum.1);
+        for (name, mut optimiser) in (function.optimisers)() {
+            let trajectory = trajectory(&function, optimiser.as_mut());
+            let &(x, y) = trajectory.last().unwrap();
+            assert_eq!(trajectory.len(), function.iterations + 1);
+            assert!(
+                distance((x, y)) < distance(function.init),
+                "{} moved away from the minimum of {}: ({}, {})",
+                name,
+                function.name,
+                x,
+                y
+            );
+        }
+    }
+}
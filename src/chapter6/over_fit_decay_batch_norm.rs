@@ -1,115 +1,144 @@
-use std::time::Instant;
+use std::{path::Path, str::FromStr, time::Instant};
 
+use ::multi_layer_net::checkpoint::Checkpoint;
+use ::multi_layer_net::gradient_clipping::{ClipGradients, Clipping};
 use ::multi_layer_net::multi_layer_net_extended;
-use multi_layer_net::multi_layer_net;
+use multi_layer_net::{
+    callbacks::terminate_on_nan::TerminateOnNaN,
+    plot,
+    trainer::{Dataset, History, Trainer},
+};
 use mylib::mnist::{self, load_label, load_normalised_image, DatasetType};
-use rand::seq::IteratorRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 
-use crate::{
-    optimiser::{sgd, sgd_ext},
-    plot_accuracy, plot_loss,
-};
+use crate::optimiser::{sgd, OptimizerBase};
 
-fn train() -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+fn train() -> History {
     let dataset_dir = std::env::current_dir().unwrap().join("dataset");
     mnist::init_mnist();
-    let train_img = load_normalised_image(DatasetType::TrainImg, &dataset_dir)
-        .flatten()
-        .columns(0, 300)
-        * 1.0;
-    let train_label = load_label(DatasetType::TrainLabel, &dataset_dir)
-        .as_one_hot()
-        .rows(0, 300)
-        * 1;
-    let test_img = load_normalised_image(DatasetType::TestImg, &dataset_dir).flatten();
-    let test_label = load_label(DatasetType::TestLabel, &dataset_dir).as_one_hot();
-    let mut network = multi_layer_net_extended::MultiLayerNetExtended::new(
+    let train = Dataset::from_image_columns(
+        &load_normalised_image(DatasetType::TrainImg, &dataset_dir)
+            .flatten()
+            .columns(0, 300),
+        &load_label(DatasetType::TrainLabel, &dataset_dir)
+            .as_one_hot()
+            .rows(0, 300),
+    );
+    let test = Dataset::from_image_columns(
+        &load_normalised_image(DatasetType::TestImg, &dataset_dir).flatten(),
+        &load_label(DatasetType::TestLabel, &dataset_dir).as_one_hot(),
+    );
+    let resume = std::env::args().any(|arg| arg == "--resume");
+    let checkpoint_path = Path::new("over_fit_decay_batch_norm.ckpt");
+    let checkpoint_interval = 10;
+    // `--seed=<n>` repeats a run exactly; without it a new seed is drawn.
+    let seed = option::<u64>("--seed=").unwrap_or_else(rand::random);
+    println!("Seed {}", seed);
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut network = multi_layer_net_extended::MultiLayerNetExtended::new_with_rng(
         784,
         [100; 6].to_vec(),
         10,
         0.1,
         "relu",
         "relu",
+        &mut rng,
     );
-    let mut rng = rand::thread_rng();
-    let optimiser = sgd_ext::SGDExt::new(0.01);
+    // `--dropout=<ratio>` adds dropout after every hidden layer.
+    if let Some(dropout_ratio) = option::<f64>("--dropout=") {
+        if !(0.0..1.0).contains(&dropout_ratio) {
+            usage_error(&format!("--dropout={}", dropout_ratio));
+        }
+        network = network.dropout(dropout_ratio, &mut rng);
+    }
+    let max_grad_norm = 10.0;
+    // Reports the gradient norms after every iteration.
+    let mut optimiser =
+        ClipGradients::new(sgd::SGD::new(0.01), Clipping::GlobalNorm(max_grad_norm)).report(1);
     let max_epochs = 201;
-    let train_size = train_img.shape().1;
     let batch_size = 100;
-    let mut train_loss_list = vec![];
-    let mut train_accuracy_list = vec![];
-    let mut test_accuracy_list = vec![];
-    let iter_per_epoch = 1.max(train_size / batch_size);
+    let mut history = History::default();
     let mut epoch_count = 0;
-    for i in 0..10i32.pow(9) {
-        let mut img_batch = na::DMatrix::<f64>::zeros(batch_size, 784);
-        let mut label_batch = na::DMatrix::<u8>::zeros(batch_size, 10);
-        let batch_mask = (0..train_size).choose_multiple(&mut rng, batch_size);
-        img_batch
-            .row_iter_mut()
-            .zip(batch_mask.iter())
-            .for_each(|(mut column, n)| column.copy_from_slice(&train_img.column(*n).as_slice()));
-        label_batch
-            .row_iter_mut()
-            .zip(batch_mask.iter())
-            .for_each(|(mut column, n)| column.copy_from(&train_label.row(*n)));
-        network.gradient(&img_batch, &label_batch);
-        optimiser.update(&network.params, &network.grads);
-        let loss = network.loss(&img_batch, &label_batch, false);
-        train_loss_list.push(loss);
-        if i == 0 || (i + 1) % (iter_per_epoch as i32) == 0 {
-            let mut img_matrix = na::DMatrix::<f64>::from_element(train_img.ncols(), 784, 0.0);
-            img_matrix
-                .row_iter_mut()
-                .zip((0..train_img.ncols()).into_iter())
-                .for_each(|(mut column, n)| {
-                    column.copy_from_slice(&train_img.column(n).as_slice())
-                });
-            let train_acc = network.accuracy(
-                &img_matrix,
-                &train_label
-                    .clone()
-                    .resize(train_label.shape().0, train_label.shape().1, 0),
-            );
-            let mut img_matrix = na::DMatrix::<f64>::from_element(test_img.ncols(), 784, 0.0);
-            img_matrix
-                .row_iter_mut()
-                .zip((0..test_img.ncols()).into_iter())
-                .for_each(|(mut column, n)| column.copy_from_slice(&test_img.column(n).as_slice()));
-            let test_acc = network.accuracy(
-                &img_matrix,
-                &test_label
-                    .clone()
-                    .resize(test_label.shape().0, test_label.shape().1, 0),
-            );
-            train_accuracy_list.push(train_acc);
-            test_accuracy_list.push(test_acc);
-            print!("Training has done {} times! ", i + 1);
-            println!(
-                "Train Acc. {:.1}% Test Acc. {:.1}%",
-                train_acc * 100.0,
-                test_acc * 100.0
-            );
-            epoch_count += 1;
-            if epoch_count >= max_epochs {
-                break;
-            }
+    if resume {
+        let checkpoint = Checkpoint::load(checkpoint_path).unwrap();
+        network =
+            multi_layer_net_extended::MultiLayerNetExtended::from_checkpoint(&checkpoint).unwrap();
+        optimiser.load_state(&checkpoint, "optimiser.").unwrap();
+        rng = checkpoint.rng("rng").unwrap();
+        history.loss = checkpoint.list("train_loss_list").unwrap();
+        history.train_accuracy = checkpoint.list("train_accuracy_list").unwrap();
+        history.val_accuracy = checkpoint.list("test_accuracy_list").unwrap();
+        epoch_count = checkpoint.metadata("epoch_count").unwrap();
+        println!("Resuming from epoch {}", epoch_count);
+    }
+    // Training one epoch at a time leaves room for the checkpoints in
+    // between.
+    let mut trainer = Trainer::new().with_rng(rng);
+    let mut terminate_on_nan = TerminateOnNaN::new();
+    while epoch_count < max_epochs {
+        let epoch = trainer.fit(
+            &mut network,
+            &mut optimiser,
+            &train,
+            Some(&test),
+            1,
+            batch_size,
+            &mut [&mut terminate_on_nan],
+        );
+        if terminate_on_nan.terminated_at().is_some() {
+            break;
+        }
+        println!(
+            "Epoch {} Train Acc. {:.1}% Test Acc. {:.1}%",
+            epoch_count + 1,
+            epoch.train_accuracy[0] * 100.0,
+            epoch.val_accuracy[0] * 100.0
+        );
+        history.append(epoch);
+        epoch_count += 1;
+        if epoch_count % checkpoint_interval == 0 {
+            let mut checkpoint = network.to_checkpoint();
+            optimiser.save_state(&mut checkpoint, "optimiser.");
+            checkpoint.insert_rng("rng", trainer.rng());
+            checkpoint.insert_list("train_loss_list", &history.loss);
+            checkpoint.insert_list("train_accuracy_list", &history.train_accuracy);
+            checkpoint.insert_list("test_accuracy_list", &history.val_accuracy);
+            checkpoint.insert_metadata("epoch_count", epoch_count);
+            checkpoint.save(checkpoint_path).unwrap();
         }
     }
-    (train_loss_list, train_accuracy_list, test_accuracy_list)
+    network
+        .save(Path::new("MultiLayerNetExtended.ckpt"))
+        .unwrap();
+    history
+}
+
+// The value of the first `<prefix><value>` argument.
+fn option<T: FromStr>(prefix: &str) -> Option<T> {
+    std::env::args().find_map(|arg| {
+        arg.strip_prefix(prefix)
+            .map(|value| value.parse::<T>().unwrap_or_else(|_| usage_error(&arg)))
+    })
+}
+
+fn usage_error(arg: &str) -> ! {
+    eprintln!("Invalid argument {}.", arg);
+    eprintln!("--seed takes an unsigned integer and --dropout a ratio in [0, 1).");
+    std::process::exit(2)
 }
 
 pub fn overfit_weight_decay_batch_norm_train() {
     let start = Instant::now();
-    let (train_loss_list, train_accuracy_list, test_accuracy_list) = train();
+    let history = train();
     let end = start.elapsed();
     println!("Training has finished! Now starting to plot.");
-    plot_loss(&train_loss_list, "Iteration Overfit");
-    plot_accuracy(
-        &train_accuracy_list,
-        &test_accuracy_list,
-        "Accuracy Overfit",
-    );
+    plot::loss_plot(&history, "Loss")
+        .save(Path::new("Iteration Overfit.png"))
+        .unwrap();
+    plot::accuracy_plot(&history, "Accuracy")
+        .save(Path::new("Accuracy Overfit.png"))
+        .unwrap();
     println!(
         "Training takes {}.{:03}s",
         end.as_secs(),
@@ -117,6 +146,6 @@ pub fn overfit_weight_decay_batch_norm_train() {
     );
     println!(
         "Testdata accuracy is {:.1}%",
-        test_accuracy_list.last().unwrap() * 100.0
+        history.val_accuracy.last().unwrap() * 100.0
     );
 }
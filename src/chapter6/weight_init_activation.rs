@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use multi_layer_net::{multi_layer_net_extended::MultiLayerNetExtended, plot::HistogramGrid};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+// The initialisations compared in section 6.2 of the book, as understood by
+// `MultiLayerNetExtended::new`.
+const INITIALISATIONS: [(&str, &str); 4] = [
+    ("std=1", "1"),
+    ("std=0.01", "0.01"),
+    ("Xavier", "xavier"),
+    ("He", "he"),
+];
+
+// Passes 1000 random samples through five hidden layers of 100 units and
+// returns the activations of every layer. Batch normalisation is skipped, so
+// the distributions only depend on the initialisation.
+pub fn activation_distributions<R: Rng + ?Sized>(
+    weight_init_std: &str,
+    activation: &str,
+    rng: &mut R,
+) -> Vec<Vec<f64>> {
+    let network = MultiLayerNetExtended::new_with_rng(
+        100,
+        vec![100; 5],
+        10,
+        0.0,
+        weight_init_std,
+        activation,
+        rng,
+    );
+    let x = na::DMatrix::<f64>::from_fn(1000, 100, |_, _| {
+        rng.sample::<f64, _>(rand_distr::StandardNormal)
+    });
+    network
+        .hidden_activations(&x, false, false)
+        .into_iter()
+        .map(|a| a.as_slice().to_vec())
+        .collect()
+}
+
+// Draws one row of per-layer histograms for every initialisation, like
+// figures 6-10 to 6-14 of the book.
+pub fn weight_init_activation_histograms() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    for activation in ["sigmoid", "relu"] {
+        let mut grid = HistogramGrid::new(
+            &format!("Activations ({})", activation),
+            INITIALISATIONS.len(),
+            5,
+            0.0..1.0,
+            30,
+        );
+        for (name, weight_init_std) in INITIALISATIONS {
+            let distributions = activation_distributions(weight_init_std, activation, &mut rng);
+            for (layer, values) in distributions.into_iter().enumerate() {
+                grid = grid.panel(&format!("{} {}-layer", name, layer + 1), values);
+            }
+        }
+        grid.save(Path::new(&format!("Activations {}.png", activation)))
+            .unwrap();
+    }
+}
+
+#[test]
+fn test_activation_distributions() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let fraction = |values: &[f64], f: fn(f64) -> bool| {
+        values.iter().filter(|&&v| f(v)).count() as f64 / values.len() as f64
+    };
+    // With a standard deviation of 1 the sigmoids saturate at 0 and 1.
+    let std_1 = activation_distributions("1", "sigmoid", &mut rng);
+    assert_eq!(std_1.len(), 5);
+    assert!(fraction(&std_1[4], |v| !(0.1..=0.9).contains(&v)) > 0.5);
+    // With 0.01 every activation collapses onto 0.5.
+    let std_001 = activation_distributions("0.01", "sigmoid", &mut rng);
+    assert!(fraction(&std_001[4], |v| (v - 0.5).abs() < 0.05) > 0.99);
+    // Xavier keeps the sigmoid activations spread out.
+    let xavier = activation_distributions("xavier", "sigmoid", &mut rng);
+    assert!(fraction(&xavier[4], |v| (v - 0.5).abs() > 0.1) > 0.2);
+    // He keeps the spread of the ReLU activations from vanishing with depth.
+    let he = activation_distributions("he", "relu", &mut rng);
+    let std = |values: &[f64]| {
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64).sqrt()
+    };
+    assert!(std(&he[4]) > 0.25 * std(&he[0]));
+    let small = activation_distributions("0.01", "relu", &mut rng);
+    assert!(std(&small[4]) < 1e-4 * std(&small[0]));
+}